@@ -0,0 +1,472 @@
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::citation::BibEntry;
+use crate::docs::AnalyzedDocuments;
+use crate::{author_page_id, gen_section_id, get_root_href, get_string, get_tag_page_link};
+use crate::{
+    AnalyzedArticles, ImageAlignment, MetaJson, Paragraph, ParsedArticleAnalyzed, SectionLink,
+    SentenceItem,
+};
+
+/// Version of the JSON format, bumped on breaking changes. Old versions stay
+/// available under /schema/v{N} so that clients can check what they parse.
+pub const API_VERSION: u32 = 1;
+
+fn schema_url(name: &str) -> String {
+    format!("{}/schema/v{API_VERSION}/{name}.json", get_root_href())
+}
+
+/// Whether a page is an article or a document from /docs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageKind {
+    Article,
+    Document,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiLink {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiAuthor {
+    // key in meta.json
+    pub id: String,
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiTag {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+}
+
+/// Inline content of a paragraph
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ApiInline {
+    Text { text: String },
+    Link { text: String, url: String, title: String },
+    // reference to ApiPage::footnotes
+    Footnote { id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiAlign {
+    Full,
+    Left,
+    Right,
+}
+
+/// Block content of a section
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ApiBlock {
+    Paragraph {
+        content: Vec<ApiInline>,
+        // rendered HTML for Markdown blocks that are more than text (lists, tables)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        html: Option<String>,
+    },
+    Quote {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        blocks: Vec<ApiBlock>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<ApiLink>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source: Option<ApiLink>,
+    },
+    Image {
+        url: String,
+        alt: String,
+        caption: String,
+        align: ApiAlign,
+        // width in CSS pixels of floating images
+        #[serde(skip_serializing_if = "Option::is_none")]
+        width: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiSection {
+    // anchor of the section on the HTML page
+    pub id: String,
+    pub title: String,
+    // heading level, 1 = h1
+    pub level: usize,
+    pub blocks: Vec<ApiBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiFootnote {
+    pub id: String,
+    pub content: Vec<ApiInline>,
+}
+
+/// One article or document, written to /{lang}/{path}.md.json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiPage {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: u32,
+    pub kind: PageKind,
+    pub lang: String,
+    // path relative to /{lang} ("angelus", "dok/lefebvre/deklaration")
+    pub path: String,
+    pub url: String,
+    pub title: String,
+    // yyyy-mm-dd or null
+    pub date: Option<String>,
+    // sha256 of the source, changes with every edit
+    pub sha256: String,
+    pub authors: Vec<ApiAuthor>,
+    pub tags: Vec<ApiTag>,
+    // lang => url of the translated page
+    pub translations: BTreeMap<String, String>,
+    pub image: Option<ApiBlock>,
+    pub subtitle: Vec<ApiBlock>,
+    pub summary: Vec<ApiBlock>,
+    pub sections: Vec<ApiSection>,
+    pub footnotes: Vec<ApiFootnote>,
+    pub backlinks: Vec<ApiLink>,
+    pub similar: Vec<ApiLink>,
+    pub bibliography: Vec<BibEntry>,
+}
+
+/// Entry of the per-language manifest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiManifestEntry {
+    pub kind: PageKind,
+    pub path: String,
+    // collection (folder in /docs) of documents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    pub title: String,
+    pub date: Option<String>,
+    pub sha256: String,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    // url of the HTML page
+    pub url: String,
+    // url of the ApiPage
+    pub json: String,
+}
+
+/// All pages of one language, written to /{lang}/content.json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiManifest {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub version: u32,
+    pub lang: String,
+    pub pages: Vec<ApiManifestEntry>,
+}
+
+/// JSON export of one language
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiExport {
+    pub manifest: ApiManifest,
+    pub pages: Vec<ApiPage>,
+}
+
+impl ApiExport {
+    /// Writes /{lang}/content.json and /{lang}/{path}.md.json for every page
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let dir = dist.join(&self.manifest.lang);
+        for p in self.pages.iter() {
+            let path = dir.join(format!("{}.md.json", p.path));
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+            }
+            let json = serde_json::to_string(p).map_err(|e| e.to_string())?;
+            std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let json = serde_json::to_string_pretty(&self.manifest).map_err(|e| e.to_string())?;
+        std::fs::write(dir.join("content.json"), json).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Writes the JSON Schemas of the current version to /schema/v{API_VERSION}
+pub fn write_schemas(dist: &Path) -> Result<(), String> {
+    let dir = dist.join("schema").join(format!("v{API_VERSION}"));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let schemas = [
+        ("page", include_str!("../../templates/api-page.schema.json")),
+        ("manifest", include_str!("../../templates/api-manifest.schema.json")),
+    ];
+    for (name, s) in schemas {
+        let s = s.replace("$$SCHEMA_URL$$", &schema_url(name));
+        std::fs::write(dir.join(format!("{name}.json")), s).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn absolute_url(href: &str) -> String {
+    if href.starts_with('/') {
+        format!("{}{href}", get_root_href())
+    } else {
+        href.to_string()
+    }
+}
+
+fn api_link(lang: &str, l: &SectionLink) -> ApiLink {
+    let url = if l.slug.starts_with("http") {
+        l.slug.clone()
+    } else {
+        format!("{}/{lang}/{}", get_root_href(), l.slug)
+    };
+    ApiLink { title: l.title.clone(), url }
+}
+
+fn api_inlines(s: &[SentenceItem]) -> Vec<ApiInline> {
+    s.iter()
+        .map(|i| match i {
+            SentenceItem::Text { text } => ApiInline::Text {
+                text: text.replace("[R]: ", "℟ ").replace("[V]: ", "℣ "),
+            },
+            SentenceItem::Link { l } => ApiInline::Link {
+                text: l.text.clone(),
+                url: absolute_url(&l.href),
+                title: l.title.clone(),
+            },
+            SentenceItem::Footnote { id } => ApiInline::Footnote { id: id.clone() },
+        })
+        .collect()
+}
+
+// slug: folder of the images, same as in render_paragraph()
+fn api_block(lang: &str, slug: &str, p: &Paragraph) -> ApiBlock {
+    match p {
+        Paragraph::Sentence { s } => ApiBlock::Paragraph { content: api_inlines(s), html: None },
+        Paragraph::Markdown { html, s } => {
+            // only lists and tables need the HTML, the inlines say the same
+            let structured = ["<ul", "<ol", "<table", "<pre", "<dl"].iter().any(|t| html.contains(t));
+            ApiBlock::Paragraph {
+                content: api_inlines(s),
+                html: Some(html.clone()).filter(|_| structured),
+            }
+        }
+        Paragraph::Quote { q } => ApiBlock::Quote {
+            title: Some(q.title.clone()).filter(|t| !t.trim().is_empty()),
+            blocks: q.quote.iter().map(|p| api_block(lang, slug, p)).collect(),
+            author: q.author.as_ref().map(|l| ApiLink { title: l.text.clone(), url: absolute_url(&l.href) }),
+            source: q.source.as_ref().map(|l| ApiLink { title: l.text.clone(), url: absolute_url(&l.href) }),
+        },
+        Paragraph::Image { i } => {
+            let url = if i.href.contains("://") {
+                i.href.clone()
+            } else {
+                format!("{}/articles/{lang}/{slug}/{}", get_root_href(), i.href)
+            };
+            let (align, width) = match i.inline {
+                None | Some(ImageAlignment::FullWidth) => (ApiAlign::Full, None),
+                Some(ImageAlignment::Left(w)) => (ApiAlign::Left, Some(w)),
+                Some(ImageAlignment::Right(w)) => (ApiAlign::Right, Some(w)),
+            };
+            ApiBlock::Image {
+                url,
+                alt: i.alt.clone(),
+                caption: i.title.clone(),
+                align,
+                width,
+            }
+        }
+    }
+}
+
+impl ApiPage {
+    /// path: path of the page relative to /{lang}
+    pub fn new(
+        lang: &str,
+        kind: PageKind,
+        slug: &str,
+        path: &str,
+        a: &ParsedArticleAnalyzed,
+        meta: &MetaJson,
+    ) -> Result<Self, String> {
+        let root_href = get_root_href();
+
+        let authors = a
+            .authors
+            .iter()
+            .map(|id| ApiAuthor {
+                id: id.clone(),
+                name: meta
+                    .authors
+                    .get(id)
+                    .map(|q| q.displayname.clone())
+                    .unwrap_or_else(|| id.clone()),
+                url: format!("{root_href}/{lang}/author/{}", author_page_id(id)),
+            })
+            .collect();
+
+        let tags = a
+            .tags
+            .iter()
+            .map(|t| {
+                Ok(ApiTag {
+                    id: t.clone(),
+                    name: meta
+                        .tags
+                        .get(lang)
+                        .and_then(|q| q.tags.get(t))
+                        .cloned()
+                        .unwrap_or_else(|| t.clone()),
+                    url: format!("{root_href}/{}", get_tag_page_link(lang, t, meta)?),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let date = Some(a.get_iso_date()).filter(|d| !d.is_empty());
+
+        Ok(Self {
+            schema: schema_url("page"),
+            version: API_VERSION,
+            kind,
+            lang: lang.to_string(),
+            path: path.to_string(),
+            url: format!("{root_href}/{lang}/{path}"),
+            title: a.title.clone(),
+            date,
+            sha256: a.sha256.clone(),
+            authors,
+            tags,
+            translations: a
+                .translations
+                .iter()
+                .filter(|(l, _)| l.as_str() != lang)
+                .map(|(l, s)| (l.clone(), format!("{root_href}/{l}/{s}")))
+                .collect(),
+            image: a.img.as_ref().map(|i| api_block(lang, slug, &Paragraph::Image { i: i.clone() })),
+            subtitle: a.subtitle.iter().map(|p| api_block(lang, slug, p)).collect(),
+            summary: a.summary.iter().map(|p| api_block(lang, slug, p)).collect(),
+            sections: a
+                .sections
+                .iter()
+                .map(|s| ApiSection {
+                    id: gen_section_id(&s.title),
+                    title: s.title.clone(),
+                    level: s.indent,
+                    blocks: s.pars.iter().map(|p| api_block(lang, slug, p)).collect(),
+                })
+                .collect(),
+            footnotes: a
+                .footnotes
+                .iter()
+                .map(|f| ApiFootnote { id: f.id.clone(), content: api_inlines(&f.text) })
+                .collect(),
+            backlinks: a.backlinks.iter().map(|l| api_link(lang, l)).collect(),
+            similar: a.similar.iter().map(|l| api_link(lang, l)).collect(),
+            bibliography: a.bibliography.clone(),
+        })
+    }
+
+    fn manifest_entry(&self, collection: Option<String>) -> ApiManifestEntry {
+        ApiManifestEntry {
+            kind: self.kind,
+            path: self.path.clone(),
+            collection,
+            title: self.title.clone(),
+            date: self.date.clone(),
+            sha256: self.sha256.clone(),
+            authors: self.authors.iter().map(|a| a.id.clone()).collect(),
+            tags: self.tags.iter().map(|t| t.id.clone()).collect(),
+            url: self.url.clone(),
+            json: format!("{}.md.json", self.url),
+        }
+    }
+}
+
+pub fn generate_api(
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    meta: &MetaJson,
+) -> Result<BTreeMap<String, ApiExport>, String> {
+    let mut exports = BTreeMap::new();
+
+    for lang in meta.strings.keys() {
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
+        let mut entries = Vec::new();
+        let mut pages = Vec::new();
+
+        for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+            let page = ApiPage::new(lang, PageKind::Article, slug, slug, a, meta)?;
+            entries.push(page.manifest_entry(None));
+            pages.push(page);
+        }
+
+        for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+            for (slug, d) in docs {
+                let path = format!("{docs_path}/{collection}/{slug}");
+                let page = ApiPage::new(lang, PageKind::Document, slug, &path, d, meta)?;
+                entries.push(page.manifest_entry(Some(collection.clone())));
+                pages.push(page);
+            }
+        }
+
+        let manifest = ApiManifest {
+            schema: schema_url("manifest"),
+            version: API_VERSION,
+            lang: lang.clone(),
+            pages: entries,
+        };
+        exports.insert(lang.clone(), ApiExport { manifest, pages });
+    }
+
+    Ok(exports)
+}
+
+#[test]
+fn test_api_page() {
+    use crate::{ArticleSection, Footnote, Link, Quote};
+
+    let a = ParsedArticleAnalyzed {
+        title: "Test".to_string(),
+        date: "2025-02-04".to_string(),
+        sections: vec![ArticleSection {
+            title: "Erster Teil".to_string(),
+            indent: 2,
+            pars: vec![
+                Paragraph::Sentence {
+                    s: vec![
+                        SentenceItem::Text { text: "Text".to_string() },
+                        SentenceItem::Footnote { id: "1".to_string() },
+                    ],
+                },
+                Paragraph::Quote {
+                    q: Quote {
+                        quote: vec![Paragraph::Markdown {
+                            html: "<ul><li>a</li></ul>".to_string(),
+                            s: vec![SentenceItem::Text { text: "a".to_string() }],
+                        }],
+                        author: Some(Link { text: "Hesse".to_string(), href: "/de/author/hesse".to_string(), ..Default::default() }),
+                        ..Default::default()
+                    },
+                },
+            ],
+        }],
+        footnotes: vec![Footnote { id: "1".to_string(), text: vec![SentenceItem::Text { text: "Note".to_string() }] }],
+        ..Default::default()
+    };
+
+    let page = ApiPage::new("de", PageKind::Article, "test", "test", &a, &MetaJson::default()).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&page).unwrap()).unwrap();
+    assert_eq!(json["version"], API_VERSION);
+    assert_eq!(json["kind"], "article");
+    assert_eq!(json["sections"][0]["id"], "erster-teil");
+    assert_eq!(json["sections"][0]["blocks"][0]["content"][1], serde_json::json!({ "type": "footnote", "id": "1" }));
+    assert_eq!(json["sections"][0]["blocks"][1]["type"], "quote");
+    assert_eq!(json["sections"][0]["blocks"][1]["blocks"][0]["html"], "<ul><li>a</li></ul>");
+    assert!(json["sections"][0]["blocks"][1]["author"]["url"].as_str().unwrap().ends_with("/de/author/hesse"));
+    assert_eq!(json["footnotes"][0]["content"][0]["text"], "Note");
+}
@@ -0,0 +1,314 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::sha256;
+
+// characters of the content hash in the file name
+const HASH_LEN: usize = 10;
+// files in /static whose references to other assets are rewritten
+const STATIC_TEXT: &[&str] = &["css", "js", "CSS"];
+// generated files in /dist whose references are rewritten
+const DIST_TEXT: &[&str] = &["html", "css", "js", "webmanifest"];
+// generated scripts in /dist/{lang} that get fingerprinted, too
+const DIST_SCRIPTS: &[&str] = &["search.js"];
+// generated files in the root of /dist that get fingerprinted
+const DIST_ROOT: &[&str] = &["site.css"];
+// generated folders in /dist/{lang} whose files get fingerprinted
+const DIST_DIRS: &[&str] = &["font"];
+
+/// Content-hashed copies of /static, of the generated per-language
+/// scripts and fonts and of /site.css, original URL => fingerprinted URL
+/// ("/static/js/head.js" => "/static/js/head.3F9kq_x2Lm.js"), written to
+/// /dist/assets.json
+///
+/// The files stay available under their original name too, for links
+/// from other sites and relative url() references in third-party CSS
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assets {
+    pub map: BTreeMap<String, String>,
+}
+
+impl Assets {
+    /// Copies /static into /dist/static and fingerprints /dist/{lang}/search.js,
+    /// /dist/{lang}/font and /dist/site.css. Every file is written after the
+    /// assets it references, so that its hash covers their new names too.
+    pub fn build(cwd: &Path, dist: &Path) -> Result<Self, String> {
+        let static_dir = cwd.join("static");
+
+        // url => (path, whether references in it are rewritten)
+        let mut files = walkdir::WalkDir::new(&static_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| {
+                let rel = e.path().strip_prefix(&static_dir).ok()?.to_string_lossy().replace('\\', "/");
+                let url = format!("/static/{rel}");
+                let text = is_text(&url, STATIC_TEXT);
+                Some((url, (e.path().to_path_buf(), text)))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for entry in std::fs::read_dir(dist).map_err(|e| format!("{}: {e}", dist.display()))? {
+            let Ok(entry) = entry else { continue };
+            // the copies of an earlier build, /static is read from the source
+            if entry.path() == dist.join("static") {
+                continue;
+            }
+            for s in DIST_SCRIPTS {
+                if entry.path().join(s).is_file() {
+                    files.insert(format!("/{}/{s}", entry.file_name().to_string_lossy()), (entry.path().join(s), true));
+                }
+            }
+            for d in DIST_DIRS {
+                let Ok(dir) = std::fs::read_dir(entry.path().join(d)) else { continue };
+                for f in dir.filter_map(|f| f.ok()) {
+                    let name = f.file_name().to_string_lossy().to_string();
+                    // skip the fingerprinted copies of an earlier build
+                    if !has_fingerprint(&name) {
+                        files.insert(format!("/{}/{d}/{name}", entry.file_name().to_string_lossy()), (f.path(), false));
+                    }
+                }
+            }
+        }
+        for f in DIST_ROOT.iter().filter(|f| dist.join(f).is_file()) {
+            files.insert(format!("/{f}"), (dist.join(f), true));
+        }
+
+        let mut assets = Self::default();
+        let mut visited = BTreeSet::new();
+        for url in files.keys() {
+            assets.visit(dist, url, &files, &mut visited)?;
+        }
+        Ok(assets)
+    }
+
+    // adds the assets that `url` references, then `url` itself; a reference
+    // back to a file that is still being added (a cycle) keeps the original name
+    fn visit(
+        &mut self,
+        dist: &Path,
+        url: &str,
+        files: &BTreeMap<String, (PathBuf, bool)>,
+        visited: &mut BTreeSet<String>,
+    ) -> Result<(), String> {
+        if !visited.insert(url.to_string()) {
+            return Ok(());
+        }
+        let (path, text) = &files[url];
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let bytes = match String::from_utf8(bytes) {
+            Ok(s) if *text => {
+                let mut refs = Vec::new();
+                replace_paths(&s, |p| {
+                    if files.contains_key(p) {
+                        refs.push(p.to_string());
+                    }
+                    None
+                });
+                for r in refs {
+                    self.visit(dist, &r, files, visited)?;
+                }
+                self.rewrite(&s).into_bytes()
+            }
+            Ok(s) => s.into_bytes(),
+            Err(e) => e.into_bytes(),
+        };
+        self.add(dist, url, &bytes)
+    }
+
+    // writes the file under its original and its fingerprinted name, as two
+    // files: rewrite_dist may still change the original
+    fn add(&mut self, dist: &Path, url: &str, bytes: &[u8]) -> Result<(), String> {
+        let hashed = fingerprint(url, bytes);
+        let original = dist.join(url.trim_start_matches('/'));
+        let target = dist.join(hashed.trim_start_matches('/'));
+        if let Some(parent) = original.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        // an earlier build may have hard-linked the two
+        let _ = std::fs::remove_file(&original);
+        std::fs::write(&original, bytes).map_err(|e| format!("{}: {e}", original.display()))?;
+        std::fs::write(&target, bytes).map_err(|e| format!("{}: {e}", target.display()))?;
+        self.map.insert(url.to_string(), hashed);
+        Ok(())
+    }
+
+    /// Fingerprinted URL of an asset, or the URL itself
+    pub fn url(&self, url: &str) -> String {
+        self.map.get(url).cloned().unwrap_or_else(|| url.to_string())
+    }
+
+    pub fn is_fingerprinted(&self, url: &str) -> bool {
+        self.map.values().any(|v| v == url)
+    }
+
+    /// Replaces every path of an asset ("/static/js/head.js", also inside
+    /// "https://dubia.cc/static/js/head.js?v=1") with its fingerprinted path
+    pub fn rewrite(&self, text: &str) -> String {
+        if self.map.is_empty() {
+            return text.to_string();
+        }
+        replace_paths(text, |p| self.map.get(p).cloned())
+    }
+
+    /// Rewrites the references in the generated HTML, CSS and JS of /dist
+    /// (except /dist/static, which `build` already wrote, and the
+    /// fingerprinted files, whose contents have to match their hash)
+    pub fn rewrite_dist(&self, dist: &Path) -> Result<(), String> {
+        for entry in walkdir::WalkDir::new(dist)
+            .into_iter()
+            .filter_entry(|e| e.path() != dist.join("static"))
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            let url = path.to_string_lossy();
+            if !path.is_file() || !is_text(&url, DIST_TEXT) {
+                continue;
+            }
+            let rel = path.strip_prefix(dist).unwrap_or(path).to_string_lossy().replace('\\', "/");
+            if self.is_fingerprinted(&format!("/{rel}")) {
+                continue;
+            }
+            let Ok(text) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            let rewritten = self.rewrite(&text);
+            if rewritten != text {
+                std::fs::write(path, rewritten).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes /dist/assets.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.map).map_err(|e| e.to_string())?;
+        let path = dist.join("assets.json");
+        std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+// calls `f` with the path at every "/" of the text, up to the next quote,
+// bracket or whitespace, and replaces the path if `f` returns a new one
+fn replace_paths(text: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('/') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "\"'`()<>?#,;\\".contains(c))
+            .unwrap_or(rest.len());
+        match f(&rest[..end]) {
+            Some(replaced) => {
+                out.push_str(&replaced);
+                rest = &rest[end..];
+            }
+            None => {
+                out.push('/');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn is_text(url: &str, extensions: &[&str]) -> bool {
+    url.rsplit_once('.').is_some_and(|(_, ext)| extensions.contains(&ext))
+}
+
+// "/static/js/head.js" => "/static/js/head.{hash}.js"
+fn fingerprint(url: &str, bytes: &[u8]) -> String {
+    let hash = sha256(bytes).chars().take(HASH_LEN).collect::<String>();
+    let (dir, file) = url.rsplit_once('/').unwrap_or(("", url));
+    match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{dir}/{stem}.{hash}.{ext}"),
+        _ => format!("{dir}/{file}.{hash}"),
+    }
+}
+
+// "x.3F9kq_x2Lm.woff2", see fingerprint
+fn has_fingerprint(name: &str) -> bool {
+    let mut parts = name.rsplit('.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(hash), Some(stem)) if !stem.is_empty() => {
+            hash.len() == HASH_LEN && hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn test_assets() {
+    let head = fingerprint("/static/js/head.js", b"console.log(1)");
+    assert!(head.starts_with("/static/js/head.") && head.ends_with(".js"));
+    assert_eq!(head.len(), "/static/js/head..js".len() + HASH_LEN);
+    assert_ne!(head, fingerprint("/static/js/head.js", b"console.log(2)"));
+    assert_eq!(fingerprint("/static/LICENSE", b"x").len(), "/static/LICENSE.".len() + HASH_LEN);
+    assert!(has_fingerprint(head.rsplit('/').next().unwrap()));
+    assert!(!has_fingerprint("jquery.min.js") && !has_fingerprint("foo.bar.woff2") && !has_fingerprint("x.woff2"));
+
+    let assets = Assets {
+        map: [
+            ("/static/js/head.js".to_string(), "/static/js/head.abc.js".to_string()),
+            ("/static/img/icon/icons.svg".to_string(), "/static/img/icon/icons.def.svg".to_string()),
+            ("/de/search.js".to_string(), "/de/search.ghi.js".to_string()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    assert_eq!(
+        assets.rewrite("<script src=\"/static/js/head.js\"></script><script src='https://dubia.cc/de/search.js?v=2'></script>"),
+        "<script src=\"/static/js/head.abc.js\"></script><script src='https://dubia.cc/de/search.ghi.js?v=2'></script>"
+    );
+    assert_eq!(
+        assets.rewrite("url('/static/img/icon/icons.svg#question') /static/js/head.json </a>"),
+        "url('/static/img/icon/icons.def.svg#question') /static/js/head.json </a>"
+    );
+    assert!(assets.is_fingerprinted("/static/js/head.abc.js"));
+    assert_eq!(assets.url("/static/js/other.js"), "/static/js/other.js");
+    // a stylesheet that imports a later one gets its hashed name
+    let root = std::env::temp_dir().join(format!("md2json2-assets-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("static").join("img")).unwrap();
+    std::fs::create_dir_all(root.join("dist")).unwrap();
+    std::fs::write(root.join("static").join("a.css"), "@import url('/static/b.css');").unwrap();
+    std::fs::write(root.join("static").join("b.css"), ".x { background: url(/static/img/x.svg); }").unwrap();
+    std::fs::write(root.join("static").join("img").join("x.svg"), "<svg></svg>").unwrap();
+    // a cycle keeps the original name on the way back
+    std::fs::write(root.join("static").join("c.js"), "import('/static/d.js');").unwrap();
+    std::fs::write(root.join("static").join("d.js"), "import('/static/c.js');").unwrap();
+
+    let assets = Assets::build(&root, &root.join("dist")).unwrap();
+    let read = |url: &str| std::fs::read_to_string(root.join("dist").join(url.trim_start_matches('/'))).unwrap();
+    let a = read(&assets.url("/static/a.css"));
+    assert_eq!(a, format!("@import url('{}');", assets.url("/static/b.css")));
+    assert_eq!(read(&assets.url("/static/b.css")), format!(".x {{ background: url({}); }}", assets.url("/static/img/x.svg")));
+    assert_eq!(read("/static/a.css"), a);
+    assert_eq!(read(&assets.url("/static/c.js")), format!("import('{}');", assets.url("/static/d.js")));
+    assert_eq!(read(&assets.url("/static/d.js")), "import('/static/c.js');");
+
+    // the hash of a.css follows b.css
+    std::fs::write(root.join("static").join("b.css"), ".x { color: red; }").unwrap();
+    let rebuilt = Assets::build(&root, &root.join("dist")).unwrap();
+    assert_ne!(rebuilt.url("/static/a.css"), assets.url("/static/a.css"));
+
+    // generated files: fonts with dots in the name are fingerprinted, and
+    // rewriting the originals (site.css <-> search.js) leaves every
+    // fingerprinted copy matching its hash
+    std::fs::create_dir_all(root.join("dist").join("de").join("font")).unwrap();
+    std::fs::write(root.join("dist").join("de").join("font").join("foo.bar.woff2"), "wOF2").unwrap();
+    std::fs::write(root.join("dist").join("de").join("search.js"), "fetch('/site.css');").unwrap();
+    std::fs::write(root.join("dist").join("site.css"), "/* /de/search.js */").unwrap();
+    let assets = Assets::build(&root, &root.join("dist")).unwrap();
+    assets.rewrite_dist(&root.join("dist")).unwrap();
+    assert!(assets.map.contains_key("/de/font/foo.bar.woff2"));
+    assert_eq!(read("/site.css"), format!("/* {} */", assets.url("/de/search.js")));
+    for (url, hashed) in assets.map.iter() {
+        let bytes = std::fs::read(root.join("dist").join(hashed.trim_start_matches('/'))).unwrap();
+        assert_eq!(&fingerprint(url, &bytes), hashed);
+    }
+    let _ = std::fs::remove_dir_all(&root);
+}
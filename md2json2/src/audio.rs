@@ -0,0 +1,496 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::citation::xml_escape;
+use crate::sitemap::hreflang;
+use crate::{gen_section_id, get_description, get_root_href, get_string};
+use crate::{AnalyzedArticles, ArticleSection, MetaJson};
+
+type Lang = String;
+type Slug = String;
+
+/// Extensions of narration audio next to an index.md, in order of preference
+const AUDIO_EXTENSIONS: &[(&str, &str)] = &[("ogg", "audio/ogg"), ("opus", "audio/ogg"), ("mp3", "audio/mpeg")];
+
+/// Chapter markers next to the audio: one "[hh:]mm:ss Title" per line
+const CHAPTERS_FILE: &str = "chapters.txt";
+
+/// A chapter of the narration, `anchor` is the id of the section with the same title
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start: u32,
+    pub title: String,
+    pub anchor: String,
+}
+
+/// Narration of an article, e.g. articles/de/angelus/audio.ogg
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Audio {
+    // file name in the article folder
+    pub file: String,
+    pub mime: String,
+    pub bytes: u64,
+    // duration in seconds
+    pub duration: u32,
+    pub chapters: Vec<Chapter>,
+    // "offline-audio": true in the article config, adds the file to the precache
+    #[serde(default)]
+    pub offline: bool,
+}
+
+impl Audio {
+    /// URL of the audio file, same folder as the article images
+    pub fn url(&self, lang: &str, slug: &str) -> String {
+        format!("{}/articles/{lang}/{slug}/{}", get_root_href(), self.file)
+    }
+
+    /// Start of the chapter of a section, if there is one
+    pub fn chapter_start(&self, section_id: &str) -> Option<u32> {
+        self.chapters.iter().find(|c| !c.anchor.is_empty() && c.anchor == section_id).map(|c| c.start)
+    }
+}
+
+/// Audio files found next to the articles
+#[derive(Debug, Default)]
+pub struct AudioFiles {
+    pub articles: BTreeMap<Lang, BTreeMap<Slug, (PathBuf, Audio)>>,
+}
+
+impl AudioFiles {
+    /// Loads `articles/{lang}/{slug}/*.{ogg,opus,mp3}` and `chapters.txt`
+    pub fn load(cwd: &Path) -> Result<Self, String> {
+        let mut files = AudioFiles::default();
+
+        for entry in walkdir::WalkDir::new(cwd.join("articles")).min_depth(2).max_depth(2) {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let dir = entry.path();
+            if !dir.join("index.md").is_file() {
+                continue;
+            }
+            let (Some(slug), Some(lang)) = (dir.file_name(), dir.parent().and_then(|p| p.file_name())) else {
+                continue;
+            };
+
+            let mut found = std::fs::read_dir(dir)
+                .map_err(|e| format!("{}: {e}", dir.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter_map(|p| {
+                    let ext = p.extension()?.to_str()?.to_lowercase();
+                    let pos = AUDIO_EXTENSIONS.iter().position(|(e, _)| *e == ext)?;
+                    Some((pos, p))
+                })
+                .collect::<Vec<_>>();
+            found.sort();
+            let Some((pos, path)) = found.into_iter().next() else {
+                continue;
+            };
+
+            let bytes = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            let duration = match AUDIO_EXTENSIONS[pos].0 {
+                "mp3" => mp3_duration(&bytes),
+                _ => ogg_duration(&bytes),
+            }
+            .ok_or_else(|| format!("{}: could not read the duration", path.display()))?;
+
+            let chapters = match std::fs::read_to_string(dir.join(CHAPTERS_FILE)) {
+                Ok(s) => parse_chapters(&s),
+                Err(_) => Vec::new(),
+            };
+
+            let audio = Audio {
+                file: path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string(),
+                mime: AUDIO_EXTENSIONS[pos].1.to_string(),
+                bytes: bytes.len() as u64,
+                duration: duration.round() as u32,
+                chapters,
+                offline: false,
+            };
+
+            files
+                .articles
+                .entry(lang.to_string_lossy().to_string())
+                .or_default()
+                .insert(slug.to_string_lossy().to_string(), (path.clone(), audio));
+        }
+
+        Ok(files)
+    }
+
+    /// Audio of an article, chapters are linked to the sections with the same title
+    pub fn get_article(&self, lang: &str, slug: &str, sections: &[ArticleSection], offline: bool) -> Option<Audio> {
+        let (_, audio) = self.articles.get(lang)?.get(slug)?;
+        let mut audio = audio.clone();
+        for c in audio.chapters.iter_mut() {
+            let id = gen_section_id(&c.title);
+            if sections.iter().any(|s| gen_section_id(&s.title) == id) {
+                c.anchor = id;
+            }
+        }
+        audio.offline = offline;
+        Some(audio)
+    }
+
+    /// Copies the audio files to /articles/{lang}/{slug}/ in the dist folder
+    pub fn copy(&self, dist: &Path) -> Result<(), String> {
+        for (lang, slugs) in self.articles.iter() {
+            for (slug, (path, audio)) in slugs.iter() {
+                let dir = dist.join("articles").join(lang).join(slug);
+                std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+                std::fs::copy(path, dir.join(&audio.file)).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// "1:02:03" / "2:03"
+pub fn format_duration(secs: u32) -> String {
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
+
+// ISO 8601 duration for <time datetime="...">
+fn iso_duration(secs: u32) -> String {
+    format!("PT{}M{}S", secs / 60, secs % 60)
+}
+
+fn parse_timestamp(s: &str) -> Option<u32> {
+    let parts = s.split(':').map(|p| p.parse::<u32>().ok()).collect::<Option<Vec<_>>>()?;
+    match parts.as_slice() {
+        [m, s] => Some(m * 60 + s),
+        [h, m, s] => Some(h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+fn parse_chapters(s: &str) -> Vec<Chapter> {
+    let mut chapters = s
+        .lines()
+        .filter_map(|l| {
+            let (time, title) = l.trim().split_once(char::is_whitespace)?;
+            Some(Chapter {
+                start: parse_timestamp(time)?,
+                title: title.trim().to_string(),
+                anchor: String::new(),
+            })
+        })
+        .collect::<Vec<_>>();
+    chapters.sort_by_key(|c| c.start);
+    chapters
+}
+
+// granule position of the last page / sample rate of the first packet
+fn ogg_duration(b: &[u8]) -> Option<f64> {
+    let mut pos = 0;
+    let mut first_packet = None;
+    let mut last_granule = 0_i64;
+    while pos + 27 <= b.len() {
+        if &b[pos..pos + 4] != b"OggS" {
+            return None;
+        }
+        let granule = i64::from_le_bytes(b[pos + 6..pos + 14].try_into().ok()?);
+        let segments = b[pos + 26] as usize;
+        let table = b.get(pos + 27..pos + 27 + segments)?;
+        let body = pos + 27 + segments;
+        let len = table.iter().map(|s| *s as usize).sum::<usize>();
+        if first_packet.is_none() {
+            first_packet = Some(b.get(body..body + len)?);
+        }
+        if granule > 0 {
+            last_granule = granule;
+        }
+        pos = body + len;
+    }
+
+    let p = first_packet?;
+    if p.starts_with(b"OpusHead") {
+        // Opus always counts at 48 kHz, minus the encoder delay
+        let pre_skip = u16::from_le_bytes(p.get(10..12)?.try_into().ok()?) as i64;
+        Some((last_granule - pre_skip).max(0) as f64 / 48000.0)
+    } else if p.get(1..7) == Some(b"vorbis") {
+        let rate = u32::from_le_bytes(p.get(12..16)?.try_into().ok()?);
+        (rate > 0).then(|| last_granule as f64 / rate as f64)
+    } else {
+        None
+    }
+}
+
+// counts the MPEG layer III frames, works for CBR and VBR files
+fn mp3_duration(b: &[u8]) -> Option<f64> {
+    const BITRATES_V1: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+    const BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+    let mut pos = 0;
+    // ID3v2 tag: 10 byte header, syncsafe size
+    if b.starts_with(b"ID3") && b.len() >= 10 {
+        let size = b[6..10].iter().fold(0_usize, |acc, x| (acc << 7) | (*x as usize & 0x7f));
+        pos = 10 + size + if b[5] & 0x10 != 0 { 10 } else { 0 };
+    }
+
+    let mut seconds = 0.0;
+    while pos + 4 <= b.len() {
+        let h = u32::from_be_bytes(b[pos..pos + 4].try_into().ok()?);
+        let version = (h >> 19) & 3; // 3 = MPEG 1, 2 = MPEG 2, 0 = MPEG 2.5
+        let layer = (h >> 17) & 3; // 1 = layer III
+        let bitrate = ((h >> 12) & 0xf) as usize;
+        let rate = ((h >> 10) & 3) as usize;
+        if h >> 21 != 0x7ff || version == 1 || layer != 1 || bitrate == 0 || bitrate == 15 || rate == 3 {
+            // garbage between frames or the ID3v1 tag at the end
+            pos += 1;
+            continue;
+        }
+        let (kbps, sample_rate, samples) = match version {
+            3 => (BITRATES_V1[bitrate], [44100, 48000, 32000][rate], 1152),
+            2 => (BITRATES_V2[bitrate], [22050, 24000, 16000][rate], 576),
+            _ => (BITRATES_V2[bitrate], [11025, 12000, 8000][rate], 576),
+        };
+        let padding = (h >> 9) & 1;
+        let frame_len = (samples / 8 * kbps * 1000 / sample_rate + padding) as usize;
+        seconds += samples as f64 / sample_rate as f64;
+        pos += frame_len.max(1);
+    }
+
+    (seconds > 0.0).then_some(seconds)
+}
+
+/// Audio player for the page header, with the duration and the podcast feed
+pub fn render_player(lang: &str, slug: &str, audio: &Audio, meta: &MetaJson) -> Result<String, String> {
+    let duration = format_duration(audio.duration);
+    let title = get_string(meta, lang, "audio-title")?;
+    Ok(include_str!("../../templates/page-audio.html")
+        .replace("$$AUDIO_TITLE$$", &title)
+        .replace("$$AUDIO_LABEL$$", &format!("{title} ({duration})"))
+        .replace("$$AUDIO_DURATION$$", &duration)
+        .replace("$$AUDIO_DURATION_ISO$$", &iso_duration(audio.duration))
+        .replace("$$AUDIO_HREF$$", &audio.url(lang, slug))
+        .replace("$$AUDIO_TYPE$$", &audio.mime)
+        .replace("$$AUDIO_DOWNLOAD$$", &get_string(meta, lang, "audio-download")?)
+        .replace("$$PODCAST_HREF$$", &Podcast::url(lang))
+        .replace("$$PODCAST_TITLE$$", &get_string(meta, lang, "podcast-title")?))
+}
+
+/// "Listen from 1:23" link next to a section heading, seeks the player of the page
+pub fn render_section_link(lang: &str, audio: &Audio, section_id: &str, meta: &MetaJson) -> Result<String, String> {
+    let Some(start) = audio.chapter_start(section_id) else {
+        return Ok(String::new());
+    };
+    let time = format_duration(start);
+    let title = get_string(meta, lang, "audio-from")?.replace("$$TIME$$", &time);
+    Ok(format!(
+        "<a class='audio-seek' href='#page-audio' data-audio-t='{start}' title='{title}' aria-label='{title}'>▶ {time}</a>"
+    ))
+}
+
+/// URLs of the audio files to precache in the service worker, for articles that opted in
+pub fn precache_urls(articles: &AnalyzedArticles) -> Vec<String> {
+    articles
+        .map
+        .iter()
+        .flat_map(|(lang, v)| {
+            v.iter().filter_map(move |(slug, a)| {
+                let audio = a.audio.as_ref().filter(|au| au.offline)?;
+                Some(format!("/articles/{lang}/{slug}/{}", audio.file))
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PodcastEpisode {
+    pub slug: String,
+    pub title: String,
+    // yyyy-mm-dd or empty
+    pub date: String,
+    pub description: String,
+    pub audio: Audio,
+}
+
+/// Podcast RSS feed of all narrated articles of one language
+#[derive(Debug, Clone, PartialEq)]
+pub struct Podcast {
+    pub lang: String,
+    pub title: String,
+    pub description: String,
+    pub episodes: Vec<PodcastEpisode>,
+}
+
+impl Podcast {
+    pub fn url(lang: &str) -> String {
+        format!("{}/{lang}/podcast.xml", get_root_href())
+    }
+
+    fn chapters_url(&self, slug: &str) -> String {
+        format!("{}/{}/{slug}.chapters.json", get_root_href(), self.lang)
+    }
+
+    pub fn to_rss(&self) -> String {
+        let root_href = get_root_href();
+        let mut s = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n".to_string();
+        s += "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\" xmlns:podcast=\"https://podcastindex.org/namespace/1.0\">\n";
+        s += "  <channel>\n";
+        s += &format!("    <title>{}</title>\n", xml_escape(&self.title));
+        s += &format!("    <link>{root_href}/{}</link>\n", self.lang);
+        s += &format!("    <description>{}</description>\n", xml_escape(&self.description));
+        s += &format!("    <language>{}</language>\n", hreflang(&self.lang));
+        s += "    <copyright>CC BY 4.0</copyright>\n";
+        s += &format!(
+            "    <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+            xml_escape(&Self::url(&self.lang))
+        );
+        s += &format!("    <itunes:image href=\"{root_href}/static/img/logo/logo-sm-512.png\"/>\n");
+        s += "    <itunes:author>dubia.cc</itunes:author>\n";
+        s += "    <itunes:category text=\"Religion &amp; Spirituality\"><itunes:category text=\"Christianity\"/></itunes:category>\n";
+        s += "    <itunes:explicit>false</itunes:explicit>\n";
+
+        for e in self.episodes.iter() {
+            let url = format!("{root_href}/{}/{}", self.lang, e.slug);
+            let audio_url = e.audio.url(&self.lang, &e.slug);
+            s += "    <item>\n";
+            s += &format!("      <title>{}</title>\n", xml_escape(&e.title));
+            s += &format!("      <link>{}</link>\n", xml_escape(&url));
+            s += &format!("      <guid isPermaLink=\"false\">{}</guid>\n", xml_escape(&audio_url));
+            if let Some(d) = rfc2822_date(&e.date) {
+                s += &format!("      <pubDate>{d}</pubDate>\n");
+            }
+            s += &format!("      <description>{}</description>\n", xml_escape(&e.description));
+            s += &format!(
+                "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+                xml_escape(&audio_url),
+                e.audio.bytes,
+                e.audio.mime
+            );
+            s += &format!("      <itunes:duration>{}</itunes:duration>\n", e.audio.duration);
+            if !e.audio.chapters.is_empty() {
+                s += &format!(
+                    "      <podcast:chapters url=\"{}\" type=\"application/json+chapters\"/>\n",
+                    xml_escape(&self.chapters_url(&e.slug))
+                );
+            }
+            s += "    </item>\n";
+        }
+
+        s += "  </channel>\n";
+        s += "</rss>\n";
+        s
+    }
+
+    // Podcasting 2.0 JSON chapters
+    fn chapters_json(&self, e: &PodcastEpisode) -> String {
+        let chapters = e
+            .audio
+            .chapters
+            .iter()
+            .map(|c| {
+                let mut v = serde_json::json!({ "startTime": c.start, "title": c.title });
+                if !c.anchor.is_empty() {
+                    v["url"] = format!("{}/{}/{}#{}", get_root_href(), self.lang, e.slug, c.anchor).into();
+                }
+                v
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&serde_json::json!({ "version": "1.2.0", "chapters": chapters }))
+            .unwrap_or_default()
+    }
+
+    /// Writes /{lang}/podcast.xml and /{lang}/{slug}.chapters.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let dir = dist.join(&self.lang);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        std::fs::write(dir.join("podcast.xml"), self.to_rss()).map_err(|e| e.to_string())?;
+        for e in self.episodes.iter().filter(|e| !e.audio.chapters.is_empty()) {
+            let path = dir.join(format!("{}.chapters.json", e.slug));
+            std::fs::write(&path, self.chapters_json(e)).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+// "Tue, 04 Feb 2025 00:00:00 GMT" for a yyyy-mm-dd date
+fn rfc2822_date(date: &str) -> Option<String> {
+    let mut it = date.split('-').map(|s| s.parse::<i64>().ok());
+    let (y, m, d) = (it.next()??, it.next()??, it.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let y2 = if m <= 2 { y - 1 } else { y };
+    let era = y2.div_euclid(400);
+    let yoe = y2 - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let weekday = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][days.rem_euclid(7) as usize];
+    let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"][m as usize - 1];
+    Some(format!("{weekday}, {d:02} {month} {y} 00:00:00 GMT"))
+}
+
+/// Podcast feeds of the languages that have narrated articles, newest first
+pub fn generate_podcasts(articles: &AnalyzedArticles, meta: &MetaJson) -> Result<Vec<Podcast>, String> {
+    let mut podcasts = Vec::new();
+    for (lang, v) in articles.map.iter() {
+        let mut episodes = Vec::new();
+        for (slug, a) in v.iter() {
+            let Some(audio) = a.audio.as_ref() else {
+                continue;
+            };
+            episodes.push(PodcastEpisode {
+                slug: slug.clone(),
+                title: a.title.clone(),
+                date: a.get_iso_date(),
+                description: get_description(lang, a, meta)?,
+                audio: audio.clone(),
+            });
+        }
+        if episodes.is_empty() {
+            continue;
+        }
+        episodes.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+        podcasts.push(Podcast {
+            lang: lang.clone(),
+            title: get_string(meta, lang, "podcast-title")?,
+            description: get_string(meta, lang, "index-desc")?,
+            episodes,
+        });
+    }
+    Ok(podcasts)
+}
+
+#[test]
+fn test_audio() {
+    // Vorbis: identification header in the first page, granule of the last page
+    let page = |granule: i64, body: &[u8]| {
+        let mut p = b"OggS\0\0".to_vec();
+        p.extend(granule.to_le_bytes());
+        p.extend([0; 12]);
+        p.push(1);
+        p.push(body.len() as u8);
+        p.extend(body);
+        p
+    };
+    let mut ident = b"\x01vorbis\0\0\0\0\x01".to_vec();
+    ident.extend(44100_u32.to_le_bytes());
+    let mut ogg = page(0, &ident);
+    ogg.extend(page(44100 * 83, b"audio"));
+    assert_eq!(ogg_duration(&ogg), Some(83.0));
+
+    // two MPEG 1 layer III frames at 128 kbit/s, 44.1 kHz
+    let mut mp3 = Vec::new();
+    for _ in 0..2 {
+        mp3.extend([0xff, 0xfb, 0x90, 0x00]);
+        mp3.extend(vec![0; 417 - 4]);
+    }
+    let d = mp3_duration(&mp3).unwrap();
+    assert!((d - 2.0 * 1152.0 / 44100.0).abs() < 1e-9);
+
+    let chapters = parse_chapters("1:02:03 Third\n0:00 Erster Teil\nno time\n");
+    assert_eq!(chapters.iter().map(|c| c.start).collect::<Vec<_>>(), vec![0, 3723]);
+    assert_eq!(format_duration(3723), "1:02:03");
+    assert_eq!(format_duration(83), "1:23");
+    assert_eq!(rfc2822_date("2025-02-04").as_deref(), Some("Tue, 04 Feb 2025 00:00:00 GMT"));
+    assert_eq!(rfc2822_date(""), None);
+}
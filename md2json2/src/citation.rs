@@ -0,0 +1,779 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{get_root_href, Link, LinkType, MetaJson, ParsedArticleAnalyzed};
+
+type Lang = String;
+type Author = String;
+type Slug = String;
+
+/// A single bibliography entry, either derived from a link in the article
+/// or loaded from a `.bib` / CSL-JSON file
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BibEntry {
+    // citation key, the uuid of the href for entries derived from links
+    pub id: String,
+    // CSL item type ("webpage", "book", "article-journal", ...)
+    pub kind: String,
+    pub title: String,
+    // "Family, Given" or a literal name
+    pub authors: Vec<String>,
+    pub year: Option<String>,
+    pub publisher: Option<String>,
+    pub url: String,
+    // date the source was accessed (yyyy-mm-dd)
+    pub accessed: Option<String>,
+    // version of the cited content (content hash for pages of this site)
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CitationStyle {
+    #[default]
+    ChicagoNotes,
+    Chicago,
+    Apa,
+    Mla,
+}
+
+impl CitationStyle {
+    pub fn new(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "chicago-notes" | "chicago-note" => Some(CitationStyle::ChicagoNotes),
+            "chicago" | "chicago-bibliography" => Some(CitationStyle::Chicago),
+            "apa" => Some(CitationStyle::Apa),
+            "mla" => Some(CitationStyle::Mla),
+            _ => None,
+        }
+    }
+}
+
+/// Bibliography files found in `config/` and next to articles / documents
+#[derive(Debug, Default)]
+pub struct BibSources {
+    pub site: Vec<BibEntry>,
+    pub articles: BTreeMap<Lang, BTreeMap<Slug, Vec<BibEntry>>>,
+    pub documents: BTreeMap<Lang, BTreeMap<Author, BTreeMap<Slug, Vec<BibEntry>>>>,
+}
+
+impl BibSources {
+    /// Loads `config/bibliography.{bib,json}`, `articles/{lang}/{slug}/bibliography.{bib,json}`
+    /// and `docs/{lang}/{author}/{slug}.{bib,json}`
+    pub fn load(cwd: &Path) -> Result<Self, String> {
+        let mut sources = BibSources::default();
+
+        for ext in ["bib", "json"] {
+            let p = cwd.join("config").join(format!("bibliography.{ext}"));
+            if p.is_file() {
+                sources.site.extend(load_bib_file(&p));
+            }
+        }
+
+        for entry in walkdir::WalkDir::new(cwd.join("articles")).max_depth(3) {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let path = entry.path();
+            let fname = path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+            if fname != "bibliography.bib" && fname != "bibliography.json" {
+                continue;
+            }
+            let (Some(slug), Some(lang)) = (path.parent(), path.parent().and_then(|p| p.parent())) else {
+                continue;
+            };
+            let slug = slug.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+            let lang = lang.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+            sources
+                .articles
+                .entry(lang.to_string())
+                .or_default()
+                .entry(slug.to_string())
+                .or_default()
+                .extend(load_bib_file(path));
+        }
+
+        let docs = cwd.join("docs");
+        if docs.is_dir() {
+            for entry in walkdir::WalkDir::new(&docs).max_depth(3) {
+                let entry = entry.map_err(|e| e.to_string())?;
+                let path = entry.path();
+                let ext = path.extension().and_then(|s| s.to_str());
+                // only the bibliography next to a document, other json files in docs/ aren't CSL-JSON
+                if (ext != Some("bib") && ext != Some("json")) || !path.with_extension("md").is_file() {
+                    continue;
+                }
+                let slug = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                let author = path.parent().and_then(|p| p.file_name()).and_then(|s| s.to_str());
+                let lang = path
+                    .parent()
+                    .and_then(|p| p.parent())
+                    .and_then(|p| p.file_name())
+                    .and_then(|s| s.to_str());
+                let (Some(author), Some(lang)) = (author, lang) else {
+                    continue;
+                };
+                sources
+                    .documents
+                    .entry(lang.to_string())
+                    .or_default()
+                    .entry(author.to_string())
+                    .or_default()
+                    .entry(slug.to_string())
+                    .or_default()
+                    .extend(load_bib_file(path));
+            }
+        }
+
+        Ok(sources)
+    }
+
+    pub fn get_article(&self, lang: &str, slug: &str) -> &[BibEntry] {
+        self.articles
+            .get(lang)
+            .and_then(|s| s.get(slug))
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn get_document(&self, lang: &str, author: &str, slug: &str) -> &[BibEntry] {
+        self.documents
+            .get(lang)
+            .and_then(|s| s.get(author))
+            .and_then(|s| s.get(slug))
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Unreadable or invalid files are reported and skipped, like other bad input
+fn load_bib_file(path: &Path) -> Vec<BibEntry> {
+    let parsed = std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|s| {
+        match path.extension().and_then(|s| s.to_str()) {
+            Some("json") => parse_csl_json(&s),
+            _ => Ok(parse_bibtex(&s)),
+        }
+    });
+    match parsed {
+        Ok(o) => o,
+        Err(e) => {
+            println!("bibliography: {}: {e}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+/// Builds the bibliography of an article: every link becomes an entry, enriched
+/// with the data of a matching (same URL) entry from the article or site-wide
+/// bibliography file. All entries of the article file are included.
+pub fn resolve_bibliography(
+    links: &[Link],
+    article_entries: &[BibEntry],
+    site_entries: &[BibEntry],
+) -> Vec<BibEntry> {
+    let mut url_map: BTreeMap<String, BibEntry> = BTreeMap::new();
+
+    for link in links {
+        let key = normalize_url(&link.href);
+        let known = article_entries
+            .iter()
+            .chain(site_entries.iter())
+            .find(|e| normalize_url(&e.url) == key);
+
+        let entry = match known {
+            Some(e) => e.clone(),
+            None => BibEntry::from_link(link),
+        };
+
+        // If this URL already exists, keep the one with the more descriptive title
+        match url_map.get(&key) {
+            Some(existing) if existing.title.len() >= entry.title.len() => {}
+            _ => {
+                url_map.insert(key, entry);
+            }
+        }
+    }
+
+    for e in article_entries {
+        let key = if e.url.is_empty() { e.id.clone() } else { normalize_url(&e.url) };
+        url_map.entry(key).or_insert_with(|| e.clone());
+    }
+
+    let mut entries = url_map.into_values().collect::<Vec<_>>();
+    entries.sort_by_key(|e| e.sort_key());
+    entries
+}
+
+fn normalize_url(s: &str) -> String {
+    s.trim().trim_end_matches('/').to_string()
+}
+
+fn link_type(href: &str) -> LinkType {
+    if href.contains("wikipedia.org") {
+        LinkType::Wikipedia
+    } else if href.starts_with(get_root_href()) || href.contains("dubia.cc") {
+        LinkType::Internal
+    } else {
+        LinkType::Other
+    }
+}
+
+impl BibEntry {
+    pub fn from_link(l: &Link) -> Self {
+        let publisher = match link_type(&l.href) {
+            LinkType::Wikipedia => Some("Wikipedia".to_string()),
+            LinkType::Internal => Some("dubia.cc".to_string()),
+            LinkType::Other => None,
+        };
+
+        BibEntry {
+            id: l.id.clone(),
+            kind: "webpage".to_string(),
+            title: if l.title.trim().is_empty() { l.text.clone() } else { l.title.clone() },
+            authors: Vec::new(),
+            year: None,
+            publisher,
+            url: l.href.clone(),
+            accessed: None,
+            version: None,
+        }
+    }
+
+    /// Citation data for a page of this site: the sha256 of the content is
+    /// used as version, so a citation always refers to one revision of the text
+    pub fn for_page(id: &str, a: &ParsedArticleAnalyzed, page_href: &str, meta: &MetaJson) -> Self {
+        BibEntry {
+            id: id.to_string(),
+            kind: "webpage".to_string(),
+            title: a.title.clone(),
+            authors: a
+                .authors
+                .iter()
+                .map(|s| meta.authors.get(s).map(|q| q.displayname.clone()).unwrap_or_else(|| s.clone()))
+                .collect(),
+            year: a.get_date().map(|(y, _, _)| y.to_string()),
+            publisher: Some("dubia.cc".to_string()),
+            url: page_href.to_string(),
+            accessed: None,
+            version: if a.sha256.is_empty() { None } else { Some(a.sha256.chars().take(12).collect()) },
+        }
+    }
+
+    fn sort_key(&self) -> String {
+        self.authors.first().cloned().unwrap_or_default().to_lowercase() + &self.title.to_lowercase()
+    }
+
+    /// Renders the entry as HTML in the given citation style
+    pub fn format(&self, style: CitationStyle) -> String {
+        let title = html_escape(&self.title);
+        let url = if self.url.is_empty() {
+            String::new()
+        } else {
+            format!("<a href='{}'>{}</a>", html_escape(&self.url), html_escape(&self.url))
+        };
+        let accessed = self.accessed.as_deref().unwrap_or_default();
+        let publisher = html_escape(self.publisher.as_deref().unwrap_or_default());
+        let year = self.year.as_deref().unwrap_or_default();
+        let version = self.version.as_deref().map(|v| format!("version {v}")).unwrap_or_default();
+        let is_web = self.kind == "webpage" || self.kind == "post-weblog";
+
+        // title is italic for standalone works, quoted for web pages and articles
+        let title = if is_web || self.kind.starts_with("article") {
+            format!("“{title}”")
+        } else {
+            format!("<em>{title}</em>")
+        };
+
+        let parts = match style {
+            CitationStyle::ChicagoNotes => vec![
+                join_names(&self.authors, NameOrder::GivenFamily),
+                title,
+                publisher,
+                year.to_string(),
+                version,
+                if accessed.is_empty() { String::new() } else { format!("accessed {accessed}") },
+                url,
+            ],
+            CitationStyle::Chicago => vec![
+                join_names(&self.authors, NameOrder::FamilyGivenFirst),
+                title,
+                publisher,
+                year.to_string(),
+                capitalize(&version),
+                if accessed.is_empty() { String::new() } else { format!("Accessed {accessed}") },
+                url,
+            ],
+            CitationStyle::Apa => vec![
+                join_names(&self.authors, NameOrder::FamilyInitials),
+                format!("({})", if year.is_empty() { "n.d." } else { year }),
+                if version.is_empty() { title } else { format!("{title} ({})", capitalize(&version)) },
+                publisher,
+                url,
+            ],
+            CitationStyle::Mla => vec![
+                join_names(&self.authors, NameOrder::FamilyGivenFirst),
+                title,
+                capitalize(&version),
+                publisher,
+                year.to_string(),
+                url,
+                if accessed.is_empty() { String::new() } else { format!("Accessed {accessed}") },
+            ],
+        };
+
+        let sep = match style {
+            CitationStyle::ChicagoNotes => ", ",
+            _ => ". ",
+        };
+
+        parts
+            .into_iter()
+            .filter(|s| !s.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(sep)
+            + "."
+    }
+
+    pub fn to_bibtex(&self) -> String {
+        let kind = match self.kind.as_str() {
+            "book" => "book",
+            "article-journal" | "article" => "article",
+            "chapter" => "incollection",
+            "thesis" => "phdthesis",
+            _ => "online",
+        };
+        let mut fields = vec![("title", self.title.clone())];
+        if !self.authors.is_empty() {
+            fields.push(("author", self.authors.join(" and ")));
+        }
+        if let Some(y) = self.year.as_ref() {
+            fields.push(("year", y.clone()));
+        }
+        if let Some(p) = self.publisher.as_ref() {
+            fields.push(("publisher", p.clone()));
+        }
+        if !self.url.is_empty() {
+            fields.push(("url", self.url.clone()));
+        }
+        if let Some(a) = self.accessed.as_ref() {
+            fields.push(("urldate", a.clone()));
+        }
+        if let Some(v) = self.version.as_ref() {
+            fields.push(("version", v.clone()));
+        }
+
+        let fields = fields
+            .iter()
+            .map(|(k, v)| format!("  {k} = {{{}}}", v.replace('{', "\\{").replace('}', "\\}")))
+            .collect::<Vec<_>>()
+            .join(",\n");
+
+        format!("@{kind}{{{},\n{fields}\n}}\n", bibtex_key(&self.id))
+    }
+
+    pub fn to_ris(&self) -> String {
+        let kind = match self.kind.as_str() {
+            "book" => "BOOK",
+            "article-journal" | "article" => "JOUR",
+            "chapter" => "CHAP",
+            "thesis" => "THES",
+            _ => "ELEC",
+        };
+        let mut lines = vec![format!("TY  - {kind}"), format!("ID  - {}", self.id)];
+        lines.extend(self.authors.iter().map(|a| format!("AU  - {a}")));
+        lines.push(format!("TI  - {}", self.title));
+        if let Some(y) = self.year.as_ref() {
+            lines.push(format!("PY  - {y}"));
+        }
+        if let Some(p) = self.publisher.as_ref() {
+            lines.push(format!("PB  - {p}"));
+        }
+        if !self.url.is_empty() {
+            lines.push(format!("UR  - {}", self.url));
+        }
+        if let Some(a) = self.accessed.as_ref() {
+            lines.push(format!("Y2  - {}", a.replace('-', "/")));
+        }
+        if let Some(v) = self.version.as_ref() {
+            lines.push(format!("ET  - {v}"));
+        }
+        lines.push("ER  - ".to_string());
+        lines.join("\r\n") + "\r\n"
+    }
+}
+
+pub fn entries_to_bibtex(entries: &[BibEntry]) -> String {
+    entries.iter().map(|e| e.to_bibtex()).collect::<Vec<_>>().join("\n")
+}
+
+pub fn entries_to_ris(entries: &[BibEntry]) -> String {
+    entries.iter().map(|e| e.to_ris()).collect::<Vec<_>>().join("\r\n")
+}
+
+fn capitalize(s: &str) -> String {
+    let mut c = s.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+        None => String::new(),
+    }
+}
+
+fn bibtex_key(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == ':')
+        .collect()
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+}
+
+// text and attribute values of the feeds, sitemaps, EPUBs, SSML and podcasts
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[derive(Copy, Clone)]
+enum NameOrder {
+    // "Marcel Lefebvre and Gregorius Hesse"
+    GivenFamily,
+    // "Lefebvre, Marcel, and Gregorius Hesse"
+    FamilyGivenFirst,
+    // "Lefebvre, M., & Hesse, G."
+    FamilyInitials,
+}
+
+fn split_name(n: &str) -> (String, String) {
+    match n.split_once(',') {
+        Some((family, given)) => (family.trim().to_string(), given.trim().to_string()),
+        None => (n.trim().to_string(), String::new()),
+    }
+}
+
+fn join_names(authors: &[String], order: NameOrder) -> String {
+    let names = authors
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let (family, given) = split_name(a);
+            if given.is_empty() {
+                return html_escape(&family);
+            }
+            let s = match order {
+                NameOrder::GivenFamily => format!("{given} {family}"),
+                NameOrder::FamilyGivenFirst if i == 0 => format!("{family}, {given}"),
+                NameOrder::FamilyGivenFirst => format!("{given} {family}"),
+                NameOrder::FamilyInitials => {
+                    let initials = given
+                        .split_whitespace()
+                        .filter_map(|g| g.chars().next())
+                        .map(|c| format!("{c}."))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    format!("{family}, {initials}")
+                }
+            };
+            html_escape(&s)
+        })
+        .collect::<Vec<_>>();
+
+    match (order, names.len()) {
+        (_, 0) => String::new(),
+        (_, 1) => names[0].clone(),
+        (NameOrder::FamilyInitials, _) => {
+            let (last, rest) = names.split_last().unwrap();
+            format!("{}, &amp; {last}", rest.join(", "))
+        }
+        _ => {
+            let (last, rest) = names.split_last().unwrap();
+            format!("{}, and {last}", rest.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CslItem {
+    id: serde_json::Value,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    author: Vec<CslName>,
+    #[serde(default)]
+    issued: Option<CslDate>,
+    #[serde(default)]
+    accessed: Option<CslDate>,
+    #[serde(default)]
+    publisher: Option<String>,
+    #[serde(default, rename = "URL")]
+    url: String,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslName {
+    #[serde(default)]
+    family: Option<String>,
+    #[serde(default)]
+    given: Option<String>,
+    #[serde(default)]
+    literal: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslDate {
+    #[serde(default, rename = "date-parts")]
+    date_parts: Vec<Vec<serde_json::Value>>,
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+impl CslDate {
+    fn to_iso(&self) -> Option<String> {
+        if let Some(r) = self.raw.as_ref() {
+            return Some(r.clone());
+        }
+        let parts = self.date_parts.first()?;
+        let parts = parts
+            .iter()
+            .map(|p| match p {
+                serde_json::Value::String(s) => s.clone(),
+                o => o.to_string(),
+            })
+            .enumerate()
+            .map(|(i, p)| if i == 0 { p } else { format!("{p:0>2}") })
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("-"))
+        }
+    }
+}
+
+pub fn parse_csl_json(s: &str) -> Result<Vec<BibEntry>, String> {
+    let items = serde_json::from_str::<Vec<CslItem>>(s).map_err(|e| e.to_string())?;
+    Ok(items
+        .into_iter()
+        .map(|i| BibEntry {
+            id: match i.id {
+                serde_json::Value::String(s) => s,
+                o => o.to_string(),
+            },
+            kind: if i.kind.is_empty() { "webpage".to_string() } else { i.kind },
+            title: i.title,
+            authors: i
+                .author
+                .iter()
+                .filter_map(|n| match (&n.literal, &n.family, &n.given) {
+                    (Some(l), _, _) => Some(l.clone()),
+                    (None, Some(f), Some(g)) => Some(format!("{f}, {g}")),
+                    (None, Some(f), None) => Some(f.clone()),
+                    _ => None,
+                })
+                .collect(),
+            year: i
+                .issued
+                .and_then(|d| d.to_iso())
+                .and_then(|d| d.split('-').next().map(|s| s.to_string())),
+            publisher: i.publisher,
+            url: i.url,
+            accessed: i.accessed.and_then(|d| d.to_iso()),
+            version: i.version,
+        })
+        .collect())
+}
+
+/// Minimal BibTeX parser: supports `@type{key, field = {value}, field = "value", field = 1234}`
+pub fn parse_bibtex(s: &str) -> Vec<BibEntry> {
+    let mut entries = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find('@') {
+        rest = &rest[start + 1..];
+        let Some(open) = rest.find('{') else {
+            break;
+        };
+        let kind = rest[..open].trim().to_lowercase();
+        let body_start = open + 1;
+
+        // find the matching closing brace of the entry
+        let mut depth = 1;
+        let mut end = None;
+        for (i, c) in rest[body_start..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(body_start + i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            break;
+        };
+        let body = &rest[body_start..end];
+        rest = &rest[end + 1..];
+
+        if kind == "comment" || kind == "string" || kind == "preamble" {
+            continue;
+        }
+
+        let (key, fields) = body.split_once(',').unwrap_or((body, ""));
+        let fields = parse_bibtex_fields(fields);
+        let get = |k: &str| fields.get(k).cloned().filter(|s| !s.is_empty());
+
+        entries.push(BibEntry {
+            id: key.trim().to_string(),
+            kind: match kind.as_str() {
+                "book" => "book",
+                "article" => "article-journal",
+                "incollection" | "inbook" => "chapter",
+                "phdthesis" | "mastersthesis" => "thesis",
+                _ => "webpage",
+            }
+            .to_string(),
+            title: get("title").unwrap_or_default(),
+            authors: get("author")
+                .map(|a| a.split(" and ").map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default(),
+            year: get("year").or_else(|| get("date").and_then(|d| d.split('-').next().map(|s| s.to_string()))),
+            publisher: get("publisher").or_else(|| get("organization")).or_else(|| get("journal")),
+            url: get("url").unwrap_or_default(),
+            accessed: get("urldate"),
+            version: get("version"),
+        });
+    }
+
+    entries
+}
+
+fn parse_bibtex_fields(s: &str) -> BTreeMap<String, String> {
+    let mut map = BTreeMap::new();
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // field name
+        let name_start = i;
+        while i < chars.len() && chars[i] != '=' {
+            i += 1;
+        }
+        let name = chars[name_start..i]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_lowercase();
+        i += 1;
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        if i >= chars.len() {
+            break;
+        }
+
+        // field value
+        let mut value = String::new();
+        match chars[i] {
+            '{' => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    match chars[i] {
+                        '{' => {
+                            depth += 1;
+                            if depth > 1 {
+                                value.push('{');
+                            }
+                        }
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                i += 1;
+                                break;
+                            }
+                            value.push('}');
+                        }
+                        c => value.push(c),
+                    }
+                    i += 1;
+                }
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => {
+                while i < chars.len() && chars[i] != ',' {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        // skip to the next field
+        while i < chars.len() && chars[i] != ',' {
+            i += 1;
+        }
+        i += 1;
+
+        if !name.is_empty() {
+            let value = value
+                .replace(['{', '}'], "")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            map.insert(name, value);
+        }
+    }
+
+    map
+}
+
+#[test]
+fn test_parse_bibtex() {
+    let s = r#"
+        @book{lefebvre1986,
+          author = {Lefebvre, Marcel and Hesse, Gregorius},
+          title = {Open Letter to {Confused} Catholics},
+          publisher = "Angelus Press",
+          year = 1986,
+          url = {https://example.com/open-letter},
+        }
+    "#;
+
+    let entries = parse_bibtex(s);
+    assert_eq!(
+        entries,
+        vec![BibEntry {
+            id: "lefebvre1986".to_string(),
+            kind: "book".to_string(),
+            title: "Open Letter to Confused Catholics".to_string(),
+            authors: vec!["Lefebvre, Marcel".to_string(), "Hesse, Gregorius".to_string()],
+            year: Some("1986".to_string()),
+            publisher: Some("Angelus Press".to_string()),
+            url: "https://example.com/open-letter".to_string(),
+            accessed: None,
+            version: None,
+        }]
+    );
+
+    assert_eq!(parse_bibtex(&entries_to_bibtex(&entries)), entries);
+}
@@ -0,0 +1,640 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Full stylesheet of the site, loaded without blocking the rendering
+/// after the critical CSS that is inlined into every page
+pub const SITE_CSS_URL: &str = "/site.css";
+
+// at-rules whose rules are pruned like top-level rules
+const GROUP_RULES: &[&str] = &["@media", "@supports", "@layer", "@container"];
+
+/// The stylesheets of every page in cascade order, minified
+pub fn site_css() -> &'static str {
+    static CSS: OnceLock<String> = OnceLock::new();
+    CSS.get_or_init(|| {
+        minify(
+            &[
+                include_str!("../../static/css/head2.css"),
+                include_str!("../../static/css/PAGE_TOOLBAR.css"),
+                include_str!("../../static/css/TOC.css"),
+                include_str!("../../static/css/FIGURE.css"),
+                include_str!("../../static/css/FLOATING_HEADER.css"),
+                include_str!("../../static/css/FOOTNOTE.css"),
+            ]
+            .concat(),
+        )
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    // "selector" { declarations }, also @font-face and @page
+    Rule { prelude: String, body: String },
+    // @media (...) { rules }, @keyframes x { from {...} }
+    Block { prelude: String, children: Vec<Node> },
+    // @import url(...);
+    Statement(String),
+}
+
+/// Removes comments and whitespace, keeps strings, url() and calc() intact
+pub fn minify(css: &str) -> String {
+    serialize(&parse(css))
+}
+
+/// Only the rules of `css` whose selectors can match an element of `html`.
+/// Classes and ids that occur in inline scripts count as used, because
+/// scripts add them before the full stylesheet is loaded.
+pub fn prune(css: &str, html: &str) -> String {
+    let page = PageIndex::new(html);
+    serialize(&prune_nodes(parse(css), &page))
+}
+
+fn prune_nodes(nodes: Vec<Node>, page: &PageIndex) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .filter_map(|n| match n {
+            Node::Rule { prelude, body } if !prelude.starts_with('@') => {
+                let selectors = split_top_level(&prelude, ',')
+                    .into_iter()
+                    .filter(|s| page.matches(s))
+                    .collect::<Vec<_>>();
+                (!selectors.is_empty()).then(|| Node::Rule {
+                    prelude: selectors.join(","),
+                    body,
+                })
+            }
+            Node::Block { prelude, children } if GROUP_RULES.iter().any(|g| at_rule_is(&prelude, g)) => {
+                let children = prune_nodes(children, page);
+                (!children.is_empty()).then_some(Node::Block { prelude, children })
+            }
+            other => Some(other),
+        })
+        .collect()
+}
+
+fn at_rule_is(prelude: &str, name: &str) -> bool {
+    prelude
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '('))
+}
+
+// -- parser
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+fn parse(css: &str) -> Vec<Node> {
+    let mut p = Parser { s: css.as_bytes(), i: 0 };
+    p.nodes()
+}
+
+impl Parser<'_> {
+    // nodes until the closing "}" of the enclosing block (consumed) or the end
+    fn nodes(&mut self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        loop {
+            let (prelude, end) = self.until(b"{;}");
+            let prelude = compact_selector(&prelude);
+            match end {
+                Some(b'{') if prelude.starts_with('@') && !is_declaration_at_rule(&prelude) => {
+                    let children = self.nodes();
+                    nodes.push(Node::Block { prelude, children });
+                }
+                Some(b'{') => {
+                    let body = self.body();
+                    nodes.push(Node::Rule { prelude, body });
+                }
+                Some(b';') if prelude.starts_with('@') => nodes.push(Node::Statement(prelude)),
+                Some(b';') => {}
+                Some(_) | None => return nodes,
+            }
+        }
+    }
+
+    // declarations until the matching "}"
+    fn body(&mut self) -> String {
+        let mut depth = 0;
+        let mut out = Vec::new();
+        loop {
+            let (text, end) = self.until(b"{}");
+            out.push(text);
+            match end {
+                Some(b'{') => {
+                    depth += 1;
+                    out.push("{".to_string());
+                }
+                Some(_) if depth > 0 => {
+                    depth -= 1;
+                    out.push("}".to_string());
+                }
+                _ => break,
+            }
+        }
+        compact_declarations(&out.concat())
+    }
+
+    // text until one of `stop` outside of strings, comments and parentheses;
+    // the stop character is consumed, comments are dropped
+    fn until(&mut self, stop: &[u8]) -> (String, Option<u8>) {
+        let mut out = Vec::new();
+        let mut parens = 0_usize;
+        while self.i < self.s.len() {
+            let c = self.s[self.i];
+            if c == b'/' && self.s.get(self.i + 1) == Some(&b'*') {
+                let end = find(self.s, self.i + 2, b"*/").map(|e| e + 2).unwrap_or(self.s.len());
+                self.i = end;
+                out.push(b' ');
+                continue;
+            }
+            if c == b'"' || c == b'\'' {
+                let start = self.i;
+                self.i += 1;
+                while self.i < self.s.len() && self.s[self.i] != c {
+                    if self.s[self.i] == b'\\' {
+                        self.i += 1;
+                    }
+                    self.i += 1;
+                }
+                self.i = (self.i + 1).min(self.s.len());
+                out.extend_from_slice(&self.s[start..self.i]);
+                continue;
+            }
+            if c == b'\\' && self.i + 1 < self.s.len() {
+                out.extend_from_slice(&self.s[self.i..self.i + 2]);
+                self.i += 2;
+                continue;
+            }
+            match c {
+                b'(' => parens += 1,
+                b')' => parens = parens.saturating_sub(1),
+                _ if parens == 0 && stop.contains(&c) => {
+                    self.i += 1;
+                    return (String::from_utf8_lossy(&out).to_string(), Some(c));
+                }
+                _ => {}
+            }
+            out.push(c);
+            self.i += 1;
+        }
+        (String::from_utf8_lossy(&out).to_string(), None)
+    }
+}
+
+fn find(s: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    s.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|p| p + from)
+}
+
+fn is_declaration_at_rule(prelude: &str) -> bool {
+    ["@font-face", "@page", "@counter-style", "@property", "@font-feature-values"]
+        .iter()
+        .any(|a| at_rule_is(prelude, a))
+}
+
+// -- minification
+
+fn serialize(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    for n in nodes {
+        match n {
+            Node::Rule { body, .. } if body.is_empty() => {}
+            Node::Rule { prelude, body } => {
+                out.push_str(prelude);
+                out.push('{');
+                out.push_str(body);
+                out.push('}');
+            }
+            Node::Block { prelude, children } => {
+                let inner = serialize(children);
+                if !inner.is_empty() || !GROUP_RULES.iter().any(|g| at_rule_is(prelude, g)) {
+                    out.push_str(prelude);
+                    out.push('{');
+                    out.push_str(&inner);
+                    out.push('}');
+                }
+            }
+            Node::Statement(s) => {
+                out.push_str(s);
+                out.push(';');
+            }
+        }
+    }
+    out
+}
+
+// Calls `f` for every character outside of strings with the index in `s`,
+// characters in strings are copied as they are
+fn map_outside_strings(s: &str, mut f: impl FnMut(&mut String, char, &str, usize)) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                out.push(c);
+            }
+            None => f(&mut out, c, s, i),
+        }
+    }
+    out
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    map_outside_strings(s.trim(), |out, c, _, _| {
+        if c.is_whitespace() {
+            if !out.ends_with(' ') {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+        }
+    })
+}
+
+// removes the spaces around characters that never need them
+fn strip_spaces_around(s: &str, chars: &str) -> String {
+    let s = collapse_whitespace(s);
+    let out = map_outside_strings(&s, |out, c, s, i| {
+        let next = s[i + c.len_utf8()..].chars().next();
+        if c == ' ' && (out.ends_with(|p| chars.contains(p)) || next.is_some_and(|n| chars.contains(n))) {
+            return;
+        }
+        out.push(c);
+    });
+    out.trim().to_string()
+}
+
+fn compact_selector(s: &str) -> String {
+    if s.trim_start().starts_with('@') {
+        // "@media screen and (max-width: 650px)": the spaces around "and" matter
+        return strip_spaces_around(s, ",{}");
+    }
+    strip_spaces_around(s, ",>+~{}")
+}
+
+fn compact_declarations(s: &str) -> String {
+    split_top_level(s, ';')
+        .into_iter()
+        .filter_map(|d| {
+            let d = collapse_whitespace(d);
+            if d.contains('{') {
+                // nested rules, keep as they are
+                return Some(d);
+            }
+            let (prop, value) = d.split_once(':')?;
+            let value = strip_spaces_around(value, ",!");
+            let prop = prop.trim();
+            (!prop.is_empty() && !value.is_empty()).then(|| format!("{prop}:{value}"))
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// splits at `sep` outside of strings, parentheses and brackets
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0_usize;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if c == sep && depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts.into_iter().filter(|p| !p.is_empty()).collect()
+}
+
+// -- pruning
+
+/// Tags, classes, ids and attribute names that occur in a page
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct PageIndex {
+    tags: BTreeSet<String>,
+    classes: BTreeSet<String>,
+    ids: BTreeSet<String>,
+    attributes: BTreeSet<String>,
+    // words in the strings of inline scripts
+    script_words: BTreeSet<String>,
+}
+
+impl PageIndex {
+    fn new(html: &str) -> Self {
+        let mut page = Self::default();
+        let mut rest = html;
+        while let Some(pos) = rest.find('<') {
+            rest = &rest[pos + 1..];
+            if let Some(r) = rest.strip_prefix("!--") {
+                rest = r.find("-->").map(|e| &r[e + 3..]).unwrap_or("");
+                continue;
+            }
+            let name_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+                .unwrap_or(rest.len());
+            if name_len == 0 {
+                continue;
+            }
+            let tag = rest[..name_len].to_ascii_lowercase();
+            rest = &rest[name_len..];
+            rest = page.attributes_of(rest);
+
+            // the content of scripts and styles isn't markup
+            if tag == "script" || tag == "style" {
+                let end = rest.to_ascii_lowercase().find(&format!("</{tag}")).unwrap_or(rest.len());
+                if tag == "script" {
+                    page.add_script_words(&rest[..end]);
+                }
+                rest = &rest[end..];
+            }
+            page.tags.insert(tag);
+        }
+        page
+    }
+
+    // reads the attributes up to the closing ">", returns the rest
+    fn attributes_of<'a>(&mut self, mut rest: &'a str) -> &'a str {
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            if rest.is_empty() || rest.starts_with('>') {
+                return rest.get(1..).unwrap_or("");
+            }
+            let name_len = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                .unwrap_or(rest.len())
+                .max(1);
+            let name = rest[..name_len].to_ascii_lowercase();
+            rest = rest[name_len..].trim_start();
+            let mut value = "";
+            if let Some(r) = rest.strip_prefix('=') {
+                let r = r.trim_start();
+                let (v, len) = match r.chars().next() {
+                    Some(q @ ('"' | '\'')) => {
+                        let end = r[1..].find(q).map(|e| e + 1).unwrap_or(r.len());
+                        (&r[1..end], (end + 1).min(r.len()))
+                    }
+                    _ => {
+                        let end = r.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(r.len());
+                        (&r[..end], end)
+                    }
+                };
+                value = v;
+                rest = &r[len..];
+            }
+            match name.as_str() {
+                "class" => self.classes.extend(value.split_whitespace().map(|c| c.to_string())),
+                "id" => {
+                    self.ids.insert(value.trim().to_string());
+                }
+                _ => {}
+            }
+            self.attributes.insert(name);
+        }
+    }
+
+    fn add_script_words(&mut self, script: &str) {
+        let words = script
+            .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_string());
+        self.script_words.extend(words);
+    }
+
+    /// Conservative: true unless a tag, class, id or attribute of the
+    /// selector is missing from the page
+    fn matches(&self, selector: &str) -> bool {
+        let s = selector.chars().collect::<Vec<_>>();
+        let mut i = 0;
+        // true at the start of a compound selector, where a tag name can be
+        let mut compound_start = true;
+        while i < s.len() {
+            let c = s[i];
+            match c {
+                '.' | '#' => {
+                    let (name, next) = read_name(&s, i + 1);
+                    i = next;
+                    let found = match c {
+                        '.' => self.classes.contains(&name),
+                        _ => self.ids.contains(&name),
+                    };
+                    if !found && !self.script_words.contains(&name) {
+                        return false;
+                    }
+                    compound_start = false;
+                }
+                '[' => {
+                    let end = (i..s.len()).find(|j| s[*j] == ']').unwrap_or(s.len());
+                    let inner = s[i + 1..end].iter().collect::<String>();
+                    let name = inner
+                        .split(|c: char| "=~|^$*".contains(c))
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_ascii_lowercase();
+                    if !name.is_empty() && !self.attributes.contains(&name) {
+                        return false;
+                    }
+                    i = end + 1;
+                    compound_start = false;
+                }
+                ':' => {
+                    // pseudo-classes and -elements don't restrict the match
+                    let mut j = i + 1;
+                    while j < s.len() && (s[j] == ':' || s[j].is_alphanumeric() || s[j] == '-') {
+                        j += 1;
+                    }
+                    if j < s.len() && s[j] == '(' {
+                        let mut depth = 0;
+                        while j < s.len() {
+                            match s[j] {
+                                '(' => depth += 1,
+                                ')' => depth -= 1,
+                                _ => {}
+                            }
+                            j += 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                    }
+                    i = j;
+                    compound_start = false;
+                }
+                ' ' | '>' | '+' | '~' => {
+                    i += 1;
+                    compound_start = true;
+                }
+                '*' => {
+                    i += 1;
+                    compound_start = false;
+                }
+                _ if compound_start && (c.is_alphabetic() || c == '-' || c == '_') => {
+                    let (name, next) = read_name(&s, i);
+                    i = next;
+                    if !self.tags.contains(&name.to_ascii_lowercase()) {
+                        return false;
+                    }
+                    compound_start = false;
+                }
+                _ => i += 1,
+            }
+        }
+        true
+    }
+}
+
+// identifier starting at `i`, with CSS escapes resolved ("md\:flex")
+fn read_name(s: &[char], mut i: usize) -> (String, usize) {
+    let mut name = String::new();
+    while i < s.len() {
+        match s[i] {
+            '\\' if i + 1 < s.len() => {
+                name.push(s[i + 1]);
+                i += 2;
+            }
+            c if c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() => {
+                name.push(c);
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+    (name, i)
+}
+
+// -- critical CSS of the generated pages
+
+/// Inline CSS per page before and after pruning, in bytes
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CssReport {
+    #[serde(rename = "site-css")]
+    pub site_css: usize,
+    // page (relative to /dist) => (before, after)
+    pub pages: BTreeMap<String, (usize, usize)>,
+}
+
+impl CssReport {
+    pub fn summary(&self) -> Vec<String> {
+        let n = self.pages.len().max(1);
+        let before = self.pages.values().map(|(b, _)| b).sum::<usize>();
+        let after = self.pages.values().map(|(_, a)| a).sum::<usize>();
+        let mut lines = vec![format!(
+            "css: {} pages, inline {} bytes per page before pruning, {} after, {} KB for {SITE_CSS_URL}",
+            self.pages.len(),
+            before / n,
+            after / n,
+            self.site_css / 1024
+        )];
+        let mut largest = self.pages.iter().collect::<Vec<_>>();
+        largest.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then(a.0.cmp(b.0)));
+        for (page, (_, after)) in largest.iter().take(5) {
+            lines.push(format!("  {after} bytes {page}"));
+        }
+        lines
+    }
+
+    /// Writes /dist/css-report.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let path = dist.join("css-report.json");
+        std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+// (start, end) of the content of <style id=critical-css>
+fn critical_css_range(html: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(pos) = html[from..].find("<style") {
+        let tag_start = from + pos;
+        let tag_end = tag_start + html[tag_start..].find('>')? + 1;
+        if html[tag_start..tag_end].contains("critical-css") {
+            let end = tag_end + html[tag_end..].find("</style>")?;
+            return Some((tag_end, end));
+        }
+        from = tag_end;
+    }
+    None
+}
+
+/// Writes /dist/site.css and prunes the inlined critical CSS of every page
+/// to the rules that match its elements
+pub fn split_critical_css(dist: &Path, site_css: &str) -> Result<CssReport, String> {
+    let path = dist.join(SITE_CSS_URL.trim_start_matches('/'));
+    std::fs::write(&path, site_css).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    let mut report = CssReport {
+        site_css: site_css.len(),
+        ..Default::default()
+    };
+    for entry in walkdir::WalkDir::new(dist).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("html") {
+            continue;
+        }
+        let Ok(html) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        let Some((start, end)) = critical_css_range(&html) else {
+            continue;
+        };
+        let without_css = format!("{}{}", &html[..start], &html[end..]);
+        let critical = prune(&html[start..end], &without_css);
+        let page = path.strip_prefix(dist).map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        report.pages.insert(page, (end - start, critical.len()));
+        let html = format!("{}{critical}{}", &html[..start], &html[end..]);
+        std::fs::write(path, html).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    Ok(report)
+}
+
+#[test]
+fn test_css() {
+    let css = "/* header */\n.a  >  .b , h1:hover::before {\n  color : red ;\n  font-family: \"A  B\" , serif;\n}\n\n@media screen and (max-width: 650px) {\n  .c { margin: 0 auto !important; }\n}\n@font-face { font-family: X; src: url('/x.woff2'); }\n@keyframes spin { from { transform: rotate(0deg) } to { transform: rotate(360deg) } }\n.d:not(.e) { width: calc(100% - 2px); }\n[data-x=\"a > b\"] { top: 0 }\n";
+    let min = minify(css);
+    assert_eq!(
+        min,
+        ".a>.b,h1:hover::before{color:red;font-family:\"A  B\",serif}@media screen and (max-width: 650px){.c{margin:0 auto!important}}@font-face{font-family:X;src:url('/x.woff2')}@keyframes spin{from{transform:rotate(0deg)}to{transform:rotate(360deg)}}.d:not(.e){width:calc(100% - 2px)}[data-x=\"a > b\"]{top:0}"
+    );
+
+    let html = "<html><body><h1 class='t'>x</h1><div class=d data-x=1>y</div><script>el.classList.add('c')</script></body></html>";
+    assert_eq!(
+        prune(&min, html),
+        "h1:hover::before{color:red;font-family:\"A  B\",serif}@media screen and (max-width: 650px){.c{margin:0 auto!important}}@font-face{font-family:X;src:url('/x.woff2')}@keyframes spin{from{transform:rotate(0deg)}to{transform:rotate(360deg)}}.d:not(.e){width:calc(100% - 2px)}[data-x=\"a > b\"]{top:0}"
+    );
+    assert_eq!(prune(&min, "<p class=a></p>"), "@font-face{font-family:X;src:url('/x.woff2')}@keyframes spin{from{transform:rotate(0deg)}to{transform:rotate(360deg)}}");
+
+    let page = "<head><style id=critical-css>.a{}</style></head>";
+    let (start, end) = critical_css_range(page).unwrap();
+    assert_eq!(&page[start..end], ".a{}");
+}
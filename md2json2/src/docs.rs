@@ -1,10 +1,11 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
 use crate::{ParsedArticleAnalyzed, get_string, MetaJson, minify, get_root_href};
 use crate::{head, header_navigation, link_tags, table_of_contents, page_desciption, page_metadata};
-use crate::{body_abstract, body_content, body_noscript, footnotes, bibliography, body_footer};
+use crate::{body_abstract, body_content, body_noscript, footnotes, bibliography, body_footer, cite_this};
+use crate::citation::{self, BibSources};
 
 type Lang = String;
 type Author = String;
@@ -20,6 +21,17 @@ pub struct AnalyzedDocuments {
     pub map: BTreeMap<Lang, BTreeMap<Author, BTreeMap<Slug, ParsedArticleAnalyzed>>>,
 }
 
+impl AnalyzedDocuments {
+    /// All characters of the documents in `lang`, see fonts::WebFonts
+    pub fn get_chars(&self, lang: &str) -> BTreeSet<char> {
+        self.map
+            .get(lang)
+            .iter()
+            .flat_map(|a| a.values().flat_map(|d| d.values().flat_map(|p| p.get_chars())))
+            .collect()
+    }
+}
+
 /// Load documents from the /docs directory
 pub fn load_documents(dir: &Path) -> Result<LoadedDocuments, String> {
     let mut langs = BTreeMap::new();
@@ -67,7 +79,7 @@ pub fn load_documents(dir: &Path) -> Result<LoadedDocuments, String> {
 }
 
 /// Process documents - similar to article processing but for documents
-pub fn process_documents(documents: &LoadedDocuments) -> Result<AnalyzedDocuments, String> {
+pub fn process_documents(documents: &LoadedDocuments, bib: &BibSources) -> Result<AnalyzedDocuments, String> {
     let mut analyzed = AnalyzedDocuments::default();
     
     for (lang, authors) in &documents.langs {
@@ -85,6 +97,9 @@ pub fn process_documents(documents: &LoadedDocuments) -> Result<AnalyzedDocument
                     date: parsed.date.clone(),
                     tags: parsed.tags.clone(),
                     authors: parsed.authors.clone(),
+                    translations: parsed.translations.clone(),
+                    aliases: Vec::new(), // moved documents go into config/redirects.json
+                    atype: crate::ArticleType::new(content),
                     sha256: parsed.sha256.clone(),
                     img: parsed.img.clone(),
                     subtitle: parsed.summary.clone(),
@@ -92,8 +107,14 @@ pub fn process_documents(documents: &LoadedDocuments) -> Result<AnalyzedDocument
                     sections: parsed.sections.clone(),
                     similar: Vec::new(), // No similarities for documents
                     backlinks: Vec::new(),
-                    bibliography: parsed.get_bibliography(),
+                    bibliography: citation::resolve_bibliography(
+                        &parsed.get_bibliography(),
+                        bib.get_document(lang, author, slug),
+                        &bib.site,
+                    ),
                     footnotes: parsed.footnotes.clone(),
+                    audio: None, // narration is only picked up next to articles
+                    initials: parsed.initials.clone(),
                 };
                 
                 analyzed
@@ -150,10 +171,13 @@ pub fn document2html(
     static HTML: &str = include_str!("../../templates/lorem.html");
     
     let title_id = format!("{}-docs-{}-{}", lang, author, slug);
+    let docs_folder = get_string(meta, lang, "special-docs-path")?;
+    let page_path = format!("{}/{}/{}", docs_folder, author, slug);
+    let page_href = format!("{}/{}/{}", get_root_href(), lang, page_path);
     
     let html = HTML.replace(
         "<!-- HEAD_TEMPLATE_HTML -->",
-        &head(doc, lang, &title_id, meta)?,
+        &head(doc, lang, &title_id, &page_path, meta)?,
     );
     let html = html.replace(
         "<!-- HEADER_NAVIGATION -->",
@@ -165,12 +189,13 @@ pub fn document2html(
         "<!-- PAGE_DESCRIPTION -->",
         &page_desciption(lang, doc, meta)?,
     );
-    let html = html.replace("<!-- PAGE_METADATA -->", &page_metadata(lang, doc, meta)?);
+    let epub = crate::epub::EbookKind::Collection(author.to_string());
+    let html = html.replace("<!-- PAGE_METADATA -->", &page_metadata(lang, doc, &epub, &page_path, meta)?);
     let html = html.replace(
         "<!-- BODY_ABSTRACT -->",
-        &body_abstract(lang, slug, doc.is_prayer(), &doc.summary),
+        &body_abstract(lang, slug, doc, meta),
     );
-    let html = html.replace("<!-- BODY_CONTENT -->", &body_content(lang, slug, &doc.sections, meta)?);
+    let html = html.replace("<!-- BODY_CONTENT -->", &body_content(lang, slug, &doc.sections, doc.audio.as_ref(), meta)?);
     
     // No donate, similar sections for documents
     let html = html.replace("<!-- DONATE -->", "");
@@ -180,6 +205,7 @@ pub fn document2html(
     let html = html.replace("<!-- FOOTNOTES -->", &footnotes(lang, doc, meta)?);
     let html = html.replace("<!-- BACKLINKS -->", &crate::backlinks(lang, doc, meta)?);
     let html = html.replace("<!-- BIBLIOGRAPHY -->", &bibliography(lang, doc, meta)?);
+    let html = html.replace("<!-- CITE_THIS -->", &cite_this(lang, &title_id, doc, &page_href, meta)?);
     let html = html.replace("<!-- BODY_FOOTER -->", &body_footer(lang, doc, meta)?);
     
     // Standard replacements
@@ -187,7 +213,6 @@ pub fn document2html(
     let html = html.replace("$$SKIP_TO_MAIN_CONTENT$$", &skip);
     let contact = crate::get_special_page_link(lang, "about", meta)?;
     let root_href = get_root_href();
-    let docs_folder = get_string(meta, lang, "special-docs-path")?;
     let special_about_path = get_string(meta, lang, "special-about-path")?;
     let special_about_title = get_string(meta, lang, "special-about-title")?;
 
@@ -199,10 +224,7 @@ pub fn document2html(
     let html = html.replace("$$LANG$$", lang);
     let html = html.replace("$$SLUG$$", slug);
     let html = html.replace("$$ROOT_HREF$$", root_href);
-    let html = html.replace(
-        "$$PAGE_HREF$$",
-        &format!("{}/{}/{}/{}/{}", root_href, lang, docs_folder, author, slug),
-    );
+    let html = html.replace("$$PAGE_HREF$$", &page_href);
     
     Ok(html)
 }
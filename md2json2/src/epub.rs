@@ -0,0 +1,663 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::citation::xml_escape;
+use crate::docs::AnalyzedDocuments;
+use crate::{gen_section_id, get_root_href, get_string};
+use crate::{AnalyzedArticles, ArticlesByTag, MetaJson, Paragraph, ParsedArticleAnalyzed, SentenceItem};
+
+// dcterms:modified is required, undated books get a fixed date so
+// that the output doesn't change between builds
+const UNDATED: &str = "1970-01-01";
+
+/// Which pages an e-book collects
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EbookKind {
+    // a single article
+    Article(String),
+    // all articles with this tag
+    Tag(String),
+    // documents in /docs/{lang}/{collection}
+    Collection(String),
+}
+
+impl EbookKind {
+    /// Path of the e-book relative to /{lang}, without the .epub extension
+    pub fn path(&self) -> String {
+        match self {
+            EbookKind::Article(slug) => format!("epub/{slug}"),
+            EbookKind::Tag(t) => format!("epub/tag/{t}"),
+            EbookKind::Collection(c) => format!("epub/docs/{c}"),
+        }
+    }
+
+    pub fn url(&self, lang: &str) -> String {
+        format!("{}/{lang}/{}.epub", get_root_href(), self.path())
+    }
+}
+
+/// Entry of the table of contents, nested by section level
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct TocEntry {
+    href: String,
+    title: String,
+    children: Vec<TocEntry>,
+}
+
+impl TocEntry {
+    // (href, title, level) => tree, deeper levels are children of the previous entry
+    fn tree(flat: &[(String, String, usize)]) -> Vec<TocEntry> {
+        fn insert(list: &mut Vec<TocEntry>, e: TocEntry, level: usize, depth: usize) {
+            match list.last_mut() {
+                Some(last) if level > depth => insert(&mut last.children, e, level, depth + 1),
+                _ => list.push(e),
+            }
+        }
+
+        let min = flat.iter().map(|(_, _, l)| *l).min().unwrap_or_default();
+        let mut tree = Vec::new();
+        for (href, title, level) in flat {
+            let e = TocEntry {
+                href: href.clone(),
+                title: title.clone(),
+                children: Vec::new(),
+            };
+            insert(&mut tree, e, *level, min);
+        }
+        tree
+    }
+
+    fn to_nav(list: &[TocEntry]) -> String {
+        if list.is_empty() {
+            return String::new();
+        }
+        let items = list
+            .iter()
+            .map(|e| {
+                format!(
+                    "<li><a href=\"{}\">{}</a>{}</li>",
+                    xml_escape(&e.href),
+                    xml_escape(&e.title),
+                    Self::to_nav(&e.children)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("<ol>\n{items}\n</ol>")
+    }
+
+    fn to_ncx(list: &[TocEntry], order: &mut usize) -> String {
+        list.iter()
+            .map(|e| {
+                *order += 1;
+                let id = *order;
+                format!(
+                    "<navPoint id=\"nav-{id}\" playOrder=\"{id}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>{}</navPoint>",
+                    xml_escape(&e.title),
+                    xml_escape(&e.href),
+                    Self::to_ncx(&e.children, order)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// One article or document, rendered as an XHTML content document
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Chapter {
+    title: String,
+    // yyyy-mm-dd or empty
+    date: String,
+    // (section id, title, level)
+    toc: Vec<(String, String, usize)>,
+    body: String,
+}
+
+/// Image file of a chapter: copied from the article folder, AVIF images
+/// (not an EPUB core media type) get an SVG with the caption as fallback
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EbookImage {
+    src: PathBuf,
+    href: String,
+    media_type: &'static str,
+    caption: String,
+}
+
+/// One EPUB 3 file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ebook {
+    pub lang: String,
+    pub kind: EbookKind,
+    pub title: String,
+    // display names
+    pub authors: Vec<String>,
+    // website page that shows the same content
+    pub home_page_url: String,
+    chapters: Vec<Chapter>,
+    images: Vec<EbookImage>,
+}
+
+impl Ebook {
+    fn new(lang: &str, kind: EbookKind, title: String, home_page_url: String) -> Self {
+        Self {
+            lang: lang.to_string(),
+            kind,
+            title,
+            authors: Vec::new(),
+            home_page_url,
+            chapters: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.kind.url(&self.lang)
+    }
+
+    /// Adds an article as the next chapter, images are read from img_dir
+    fn push(&mut self, a: &ParsedArticleAnalyzed, img_dir: &Path, meta: &MetaJson) {
+        for id in a.authors.iter() {
+            let name = meta
+                .authors
+                .get(id)
+                .map(|q| q.displayname.clone())
+                .unwrap_or_else(|| id.clone());
+            if !self.authors.contains(&name) {
+                self.authors.push(name);
+            }
+        }
+
+        let chapter = self.chapters.len();
+        let mut r = ChapterRenderer {
+            chapter,
+            img_dir,
+            footnotes: a.footnotes.iter().map(|f| f.id.clone()).collect(),
+            section_ids: BTreeSet::new(),
+            noterefs: BTreeSet::new(),
+            images: &mut self.images,
+        };
+
+        let mut body = format!("<h1 id=\"title\">{}</h1>\n", xml_escape(&a.title));
+        for p in a.subtitle.iter() {
+            body += &format!("<p class=\"subtitle\">{}</p>\n", r.sentence(p.as_sentence().unwrap_or_default()));
+        }
+        for p in a.summary.iter() {
+            body += &r.paragraph(p);
+        }
+
+        let mut toc = Vec::new();
+        for s in a.sections.iter() {
+            if !s.title.trim().is_empty() {
+                let id = r.section_id(&s.title);
+                let level = s.indent.clamp(1, 5) + 1;
+                body += &format!("<h{level} id=\"{id}\">{}</h{level}>\n", xml_escape(&s.title));
+                toc.push((id, s.title.clone(), s.indent));
+            }
+            for p in s.pars.iter() {
+                body += &r.paragraph(p);
+            }
+        }
+
+        if !a.footnotes.is_empty() {
+            body += "<section epub:type=\"footnotes\">\n";
+            for f in a.footnotes.iter() {
+                let id = note_id(&f.id);
+                body += &format!(
+                    "<aside epub:type=\"footnote\" id=\"fn-{id}\"><p><a href=\"#fnref-{id}\">{}</a> {}</p></aside>\n",
+                    xml_escape(&f.id),
+                    r.sentence(&f.text)
+                );
+            }
+            body += "</section>\n";
+        }
+
+        self.chapters.push(Chapter {
+            title: a.title.clone(),
+            date: a.get_iso_date(),
+            toc,
+            body,
+        });
+    }
+
+    fn modified(&self) -> String {
+        let newest = self.chapters.iter().map(|c| c.date.as_str()).max().unwrap_or_default();
+        let date = if newest.is_empty() { UNDATED } else { newest };
+        format!("{date}T00:00:00Z")
+    }
+
+    fn xhtml(&self, title: &str, body: &str) -> String {
+        let mut s = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n".to_string();
+        s += &format!(
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{0}\" lang=\"{0}\">\n",
+            self.lang
+        );
+        s += &format!("<head><meta charset=\"utf-8\"/><title>{}</title>", xml_escape(title));
+        s += "<link rel=\"stylesheet\" type=\"text/css\" href=\"../style.css\"/></head>\n";
+        s += &format!("<body>\n{body}</body>\n</html>\n");
+        s
+    }
+
+    fn title_page(&self) -> String {
+        let mut body = format!("<section epub:type=\"titlepage\" class=\"titlepage\">\n<h1>{}</h1>\n", xml_escape(&self.title));
+        if !self.authors.is_empty() {
+            body += &format!("<p class=\"authors\">{}</p>\n", xml_escape(&self.authors.join(", ")));
+        }
+        body += &format!(
+            "<p class=\"source\"><a href=\"{0}\">{0}</a></p>\n</section>\n",
+            xml_escape(&self.home_page_url)
+        );
+        self.xhtml(&self.title, &body)
+    }
+
+    fn toc(&self) -> Vec<TocEntry> {
+        self.chapters
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let flat = c
+                    .toc
+                    .iter()
+                    .map(|(id, title, level)| (format!("text/c{i}.xhtml#{id}"), title.clone(), *level))
+                    .collect::<Vec<_>>();
+                TocEntry {
+                    href: format!("text/c{i}.xhtml"),
+                    title: c.title.clone(),
+                    children: TocEntry::tree(&flat),
+                }
+            })
+            .collect()
+    }
+
+    fn nav(&self, meta: &MetaJson) -> Result<String, String> {
+        let contents = get_string(meta, &self.lang, "epub-contents")?;
+        let body = format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n{}\n</nav>\n",
+            xml_escape(&contents),
+            TocEntry::to_nav(&self.toc())
+        );
+        // nav.xhtml is in the root folder, not in text/
+        Ok(self.xhtml(&contents, &body).replace("href=\"../style.css\"", "href=\"style.css\""))
+    }
+
+    fn ncx(&self) -> String {
+        let mut s = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n".to_string();
+        s += "<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n";
+        s += &format!("<head><meta name=\"dtb:uid\" content=\"{}\"/></head>\n", xml_escape(&self.url()));
+        s += &format!("<docTitle><text>{}</text></docTitle>\n", xml_escape(&self.title));
+        s += &format!("<navMap>\n{}\n</navMap>\n</ncx>\n", TocEntry::to_ncx(&self.toc(), &mut 0));
+        s
+    }
+
+    fn opf(&self) -> String {
+        let mut s = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n".to_string();
+        s += &format!(
+            "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"uid\" xml:lang=\"{}\">\n",
+            self.lang
+        );
+        s += "<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n";
+        s += &format!("<dc:identifier id=\"uid\">{}</dc:identifier>\n", xml_escape(&self.url()));
+        s += &format!("<dc:title>{}</dc:title>\n", xml_escape(&self.title));
+        s += &format!("<dc:language>{}</dc:language>\n", self.lang);
+        for a in self.authors.iter() {
+            s += &format!("<dc:creator>{}</dc:creator>\n", xml_escape(a));
+        }
+        if let Some(date) = self.chapters.iter().map(|c| c.date.as_str()).filter(|d| !d.is_empty()).min() {
+            s += &format!("<dc:date>{date}</dc:date>\n");
+        }
+        s += "<dc:publisher>dubia.cc</dc:publisher>\n<dc:rights>CC BY 4.0</dc:rights>\n";
+        s += &format!("<dc:source>{}</dc:source>\n", xml_escape(&self.home_page_url));
+        s += &format!("<meta property=\"dcterms:modified\">{}</meta>\n", self.modified());
+        s += "</metadata>\n<manifest>\n";
+        s += "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n";
+        s += "<item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n";
+        s += "<item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n";
+        s += "<item id=\"titlepage\" href=\"text/title.xhtml\" media-type=\"application/xhtml+xml\"/>\n";
+        for i in 0..self.chapters.len() {
+            s += &format!("<item id=\"c{i}\" href=\"text/c{i}.xhtml\" media-type=\"application/xhtml+xml\"/>\n");
+        }
+        for (i, img) in self.images.iter().enumerate() {
+            match img.media_type {
+                "image/avif" => {
+                    s += &format!(
+                        "<item id=\"img{i}\" href=\"{}\" media-type=\"image/avif\" fallback=\"img{i}-fallback\"/>\n",
+                        img.href
+                    );
+                    s += &format!(
+                        "<item id=\"img{i}-fallback\" href=\"{}.svg\" media-type=\"image/svg+xml\"/>\n",
+                        img.href
+                    );
+                }
+                t => s += &format!("<item id=\"img{i}\" href=\"{}\" media-type=\"{t}\"/>\n", img.href),
+            }
+        }
+        s += "</manifest>\n<spine toc=\"ncx\">\n<itemref idref=\"titlepage\"/>\n";
+        for i in 0..self.chapters.len() {
+            s += &format!("<itemref idref=\"c{i}\"/>\n");
+        }
+        s += "</spine>\n</package>\n";
+        s
+    }
+
+    /// The zipped EPUB, "mimetype" has to be the first, uncompressed file
+    pub fn to_epub(&self, meta: &MetaJson) -> Result<Vec<u8>, String> {
+        use zip::write::SimpleFileOptions;
+        use zip::CompressionMethod;
+
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut files = vec![
+            ("META-INF/container.xml".to_string(), include_str!("../../templates/epub-container.xml").as_bytes().to_vec()),
+            ("OEBPS/content.opf".to_string(), self.opf().into_bytes()),
+            ("OEBPS/nav.xhtml".to_string(), self.nav(meta)?.into_bytes()),
+            ("OEBPS/toc.ncx".to_string(), self.ncx().into_bytes()),
+            ("OEBPS/style.css".to_string(), include_str!("../../templates/epub.css").as_bytes().to_vec()),
+            ("OEBPS/text/title.xhtml".to_string(), self.title_page().into_bytes()),
+        ];
+        for (i, c) in self.chapters.iter().enumerate() {
+            let body = format!("<section epub:type=\"chapter\">\n{}</section>\n", c.body);
+            files.push((format!("OEBPS/text/c{i}.xhtml"), self.xhtml(&c.title, &body).into_bytes()));
+        }
+        for img in self.images.iter() {
+            let bytes = std::fs::read(&img.src).map_err(|e| format!("{}: {e}", img.src.display()))?;
+            files.push((format!("OEBPS/{}", img.href), bytes));
+            if img.media_type == "image/avif" {
+                files.push((format!("OEBPS/{}.svg", img.href), caption_svg(&img.caption).into_bytes()));
+            }
+        }
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("mimetype", stored).map_err(|e| e.to_string())?;
+        zip.write_all(b"application/epub+zip").map_err(|e| e.to_string())?;
+        for (name, bytes) in files {
+            zip.start_file(name, deflated).map_err(|e| e.to_string())?;
+            zip.write_all(&bytes).map_err(|e| e.to_string())?;
+        }
+        let cursor = zip.finish().map_err(|e| e.to_string())?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Writes /{lang}/epub/{path}.epub
+    pub fn write(&self, dist: &Path, meta: &MetaJson) -> Result<(), String> {
+        let path = dist.join(&self.lang).join(format!("{}.epub", self.kind.path()));
+        if let Some(p) = path.parent() {
+            std::fs::create_dir_all(p).map_err(|e| format!("{}: {e}", p.display()))?;
+        }
+        std::fs::write(&path, self.to_epub(meta)?).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+// Renders the paragraphs of one chapter as XHTML
+struct ChapterRenderer<'a> {
+    chapter: usize,
+    img_dir: &'a Path,
+    // footnotes that exist in this chapter, other references stay text
+    footnotes: BTreeSet<String>,
+    section_ids: BTreeSet<String>,
+    // a footnote can be referenced more than once, only the first reference gets an id
+    noterefs: BTreeSet<String>,
+    images: &'a mut Vec<EbookImage>,
+}
+
+impl<'a> ChapterRenderer<'a> {
+    // XML ids have to start with a letter and be unique in the document
+    fn section_id(&mut self, title: &str) -> String {
+        let base = format!("s-{}", gen_section_id(title));
+        let mut id = base.clone();
+        let mut i = 1;
+        while !self.section_ids.insert(id.clone()) {
+            i += 1;
+            id = format!("{base}-{i}");
+        }
+        id
+    }
+
+    fn noteref(&mut self, id: &str) -> String {
+        let nid = note_id(id);
+        let anchor = match self.noterefs.insert(nid.clone()) {
+            true => format!(" id=\"fnref-{nid}\""),
+            false => String::new(),
+        };
+        format!(
+            "<a epub:type=\"noteref\" href=\"#fn-{nid}\"{anchor}><sup>{}</sup></a>",
+            xml_escape(id)
+        )
+    }
+
+    fn sentence(&mut self, s: &[SentenceItem]) -> String {
+        s.iter()
+            .map(|i| match i {
+                SentenceItem::Text { text } => xml_escape(text).replace("[R]: ", "℟ ").replace("[V]: ", "℣ "),
+                SentenceItem::Link { l } => format!(
+                    "<a href=\"{}\">{}</a>",
+                    xml_escape(&absolute_href(&l.href)),
+                    xml_escape(&l.text)
+                ),
+                SentenceItem::Footnote { id } if self.footnotes.contains(id) => self.noteref(id),
+                SentenceItem::Footnote { id } => format!("[{}]", xml_escape(id)),
+            })
+            .collect()
+    }
+
+    fn paragraph(&mut self, p: &Paragraph) -> String {
+        match p {
+            Paragraph::Sentence { s } if s.is_empty() => String::new(),
+            Paragraph::Sentence { s } => format!("<p>{}</p>\n", self.sentence(s)),
+            // comrak output is well-formed, but footnotes stay as "[^1]"
+            Paragraph::Markdown { html, s } => {
+                let mut html = html.replace("href=\"/", &format!("href=\"{}/", get_root_href()));
+                for i in s.iter() {
+                    if let SentenceItem::Footnote { id } = i {
+                        if self.footnotes.contains(id) {
+                            html = html.replace(&format!("[^{id}]"), &self.noteref(id));
+                        }
+                    }
+                }
+                html.replace("[R]: ", "℟ ").replace("[V]: ", "℣ ") + "\n"
+            }
+            Paragraph::Quote { q } => {
+                let mut s = "<blockquote>\n".to_string();
+                if !q.title.is_empty() {
+                    s += &format!("<p><strong>{}</strong></p>\n", xml_escape(&q.title));
+                }
+                for p in q.quote.iter() {
+                    s += &self.paragraph(p);
+                }
+                let attribution = [q.author.as_ref(), q.source.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|l| format!("<a href=\"{}\">{}</a>", xml_escape(&absolute_href(&l.href)), xml_escape(&l.text)))
+                    .collect::<Vec<_>>();
+                if !attribution.is_empty() {
+                    s += &format!("<p class=\"attribution\">— {}</p>\n", attribution.join(", "));
+                }
+                s + "</blockquote>\n"
+            }
+            Paragraph::Image { i } => {
+                let caption = match i.title.is_empty() {
+                    true => String::new(),
+                    false => format!("<figcaption>{}</figcaption>", xml_escape(&i.title)),
+                };
+                match self.image(&i.href, &i.title) {
+                    Some(href) => format!(
+                        "<figure><img src=\"../{href}\" alt=\"{}\"/>{caption}</figure>\n",
+                        xml_escape(&i.alt)
+                    ),
+                    None if caption.is_empty() => String::new(),
+                    None => format!("<figure>{caption}</figure>\n"),
+                }
+            }
+        }
+    }
+
+    // Adds a local image, returns the path inside OEBPS/
+    fn image(&mut self, href: &str, caption: &str) -> Option<String> {
+        if href.contains("://") {
+            return None;
+        }
+        let src = self.img_dir.join(href.trim_start_matches('/'));
+        let ext = src.extension()?.to_str()?.to_lowercase();
+        let media_type = match ext.as_str() {
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "svg" => "image/svg+xml",
+            "avif" => "image/avif",
+            _ => return None,
+        };
+        if !src.is_file() {
+            println!("epub: image {} not found", src.display());
+            return None;
+        }
+        let href = format!("images/c{}-{}.{ext}", self.chapter, self.images.len());
+        self.images.push(EbookImage {
+            src,
+            href: href.clone(),
+            media_type,
+            caption: caption.to_string(),
+        });
+        Some(href)
+    }
+}
+
+fn absolute_href(href: &str) -> String {
+    match href.starts_with('/') {
+        true => format!("{}{href}", get_root_href()),
+        false => href.to_string(),
+    }
+}
+
+// footnote ids are usually numbers, but can be any text
+fn note_id(id: &str) -> String {
+    id.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect()
+}
+
+fn caption_svg(caption: &str) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 600 60\"><text x=\"300\" y=\"35\" text-anchor=\"middle\" font-size=\"16\">{}</text></svg>",
+        xml_escape(caption)
+    )
+}
+
+fn tag_name(lang: &str, tag: &str, meta: &MetaJson) -> String {
+    meta.tags
+        .get(lang)
+        .and_then(|t| t.tags.get(tag))
+        .cloned()
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// Builds the article, tag and document collection e-books of every language
+pub fn generate_ebooks(
+    cwd: &Path,
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    by_tag: &ArticlesByTag,
+    meta: &MetaJson,
+) -> Result<BTreeMap<String, Vec<Ebook>>, String> {
+    let root_href = get_root_href();
+    let mut ebooks = BTreeMap::new();
+
+    for lang in meta.strings.keys() {
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
+        let docs_title = get_string(meta, lang, "special-docs-title")?;
+        let arts = articles.map.get(lang);
+        let mut lang_ebooks = Vec::new();
+
+        for (slug, a) in arts.into_iter().flatten() {
+            let mut e = Ebook::new(
+                lang,
+                EbookKind::Article(slug.clone()),
+                a.title.clone(),
+                format!("{root_href}/{lang}/{slug}"),
+            );
+            e.push(a, &cwd.join("articles").join(lang).join(slug), meta);
+            lang_ebooks.push(e);
+        }
+
+        // same order as on the tag page: newest first
+        for (tag, links) in by_tag.get(lang).into_iter().flatten() {
+            let mut items = links
+                .iter()
+                .filter_map(|l| Some((l.slug.as_str(), arts?.get(&l.slug)?)))
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                continue;
+            }
+            items.sort_by(|(sa, a), (sb, b)| b.get_iso_date().cmp(&a.get_iso_date()).then_with(|| sa.cmp(sb)));
+            let mut e = Ebook::new(
+                lang,
+                EbookKind::Tag(tag.clone()),
+                tag_name(lang, tag, meta),
+                format!("{root_href}/{}", crate::get_tag_page_link(lang, tag, meta)?),
+            );
+            for (slug, a) in items {
+                e.push(a, &cwd.join("articles").join(lang).join(slug), meta);
+            }
+            lang_ebooks.push(e);
+        }
+
+        for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+            let name = meta
+                .authors
+                .get(collection)
+                .map(|a| a.displayname.clone())
+                .unwrap_or_else(|| collection.clone());
+            let mut e = Ebook::new(
+                lang,
+                EbookKind::Collection(collection.clone()),
+                format!("{docs_title} - {name}"),
+                format!("{root_href}/{lang}/{docs_path}/{collection}"),
+            );
+            for d in docs.values() {
+                e.push(d, &cwd.join("docs").join(lang).join(collection), meta);
+            }
+            lang_ebooks.push(e);
+        }
+
+        ebooks.insert(lang.clone(), lang_ebooks);
+    }
+
+    Ok(ebooks)
+}
+
+#[test]
+fn test_ebook() {
+    use crate::{ArticleSection, Footnote};
+
+    let mut meta = MetaJson::default();
+    meta.strings.entry("en".to_string()).or_default().insert("epub-contents".to_string(), "Contents".to_string());
+
+    let text = |s: &str| SentenceItem::Text { text: s.to_string() };
+    let section = |title: &str, indent: usize| ArticleSection {
+        title: title.to_string(),
+        indent,
+        pars: vec![Paragraph::Sentence {
+            s: vec![text("A & B"), SentenceItem::Footnote { id: "1".to_string() }],
+        }],
+    };
+    let a = ParsedArticleAnalyzed {
+        title: "Test".to_string(),
+        date: "2024-05-01".to_string(),
+        sections: vec![section("One", 2), section("One A", 3), section("Two", 2)],
+        footnotes: vec![Footnote { id: "1".to_string(), text: vec![text("note")] }],
+        ..Default::default()
+    };
+
+    let mut e = Ebook::new("en", EbookKind::Article("test".to_string()), "Test".to_string(), String::new());
+    e.push(&a, Path::new("/nonexistent"), &meta);
+
+    let toc = e.toc();
+    assert_eq!(toc[0].children.len(), 2);
+    assert_eq!(toc[0].children[0].children[0].href, "text/c0.xhtml#s-one-a");
+    let body = &e.chapters[0].body;
+    assert!(body.contains("A &amp; B<a epub:type=\"noteref\" href=\"#fn-1\""));
+    assert!(body.contains("<aside epub:type=\"footnote\" id=\"fn-1\">"));
+
+    let epub = e.to_epub(&meta).unwrap();
+    assert_eq!(&epub[30..38], b"mimetype");
+    assert_eq!(&epub[38..58], b"application/epub+zip");
+}
@@ -0,0 +1,160 @@
+use std::path::Path;
+
+use crate::docs::AnalyzedDocuments;
+use crate::{get_root_href, get_string, minify, special2html, AnalyzedArticles, MetaJson, SpecialPage};
+
+// the root /404.html is rendered in this language and loads the
+// /{lang}/404.html of the requested language
+const DEFAULT_LANG: &str = "en";
+
+/// Writes /404.html, /{lang}/404.html and the /{lang}/offline.html
+/// page that the service worker shows for uncached pages
+///
+/// searchbars: searchbar html per language, see search_html()
+pub fn write_fallback_pages(
+    dist: &Path,
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    searchbars: &std::collections::BTreeMap<String, String>,
+    meta: &MetaJson,
+) -> Result<(), String> {
+    let langs = meta.strings.keys().cloned().collect::<Vec<_>>();
+
+    for lang in langs.iter() {
+        let searchbar = searchbars.get(lang).cloned().unwrap_or_default();
+        let _ = std::fs::create_dir_all(dist.join(lang));
+
+        let (_, html) = special2html(lang, &not_found_page(lang, articles, documents, &searchbar, meta)?, meta)?;
+        std::fs::write(dist.join(lang).join("404.html"), minify(&html)).map_err(|e| e.to_string())?;
+
+        if lang == DEFAULT_LANG {
+            let loader = include_str!("../../templates/notfound-loader.js")
+                .replace("$$LANGS$$", &serde_json::to_string(&langs).unwrap_or_default())
+                .replace("$$DEFAULT_LANG$$", DEFAULT_LANG);
+            let html = html.replace("</head>", &format!("<script>{loader}</script></head>"));
+            std::fs::write(dist.join("404.html"), minify(&html)).map_err(|e| e.to_string())?;
+        }
+
+        let (_, html) = special2html(lang, &offline_page(lang, meta)?, meta)?;
+        std::fs::write(dist.join(lang).join("offline.html"), minify(&html)).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn not_found_page(
+    lang: &str,
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    searchbar: &str,
+    meta: &MetaJson,
+) -> Result<SpecialPage, String> {
+    let root_href = get_root_href();
+    let title = get_string(meta, lang, "notfound-title")?;
+    let text = get_string(meta, lang, "notfound-text")?;
+    let suggestions = get_string(meta, lang, "notfound-suggestions")?;
+    let docs_path = get_string(meta, lang, "special-docs-path")?;
+
+    // [slug, url, title], matched against the last part of the requested path
+    let mut pages = Vec::new();
+    for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+        pages.push([slug.clone(), format!("{root_href}/{lang}/{slug}"), a.title.clone()]);
+    }
+    for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+        for (slug, d) in docs {
+            let url = format!("{root_href}/{lang}/{docs_path}/{collection}/{slug}");
+            pages.push([slug.clone(), url, d.title.clone()]);
+        }
+    }
+    let script = include_str!("../../templates/notfound.js")
+        .replace("$$PAGES$$", &serde_json::to_string(&pages).unwrap_or_default());
+
+    let mut content = format!("<p style='text-indent: 0px;'>{text}</p>");
+    content += "<div id='notfound-suggestions' style='display: none;'>";
+    content += &format!("<h2 class='heading-level-1'>{suggestions}</h2><ul class='list'></ul></div>");
+
+    Ok(SpecialPage {
+        title: title.clone(),
+        filepath: "404".to_string(),
+        id: format!("{lang}-404"),
+        description: text,
+        content,
+        special_content: format!(
+            "<style>#special-contents {{ display: block; }}</style>{searchbar}<script>{script}</script>\
+            <script type='text/javascript' src='{root_href}/{lang}/search.js' defer=''></script>"
+        ),
+    })
+}
+
+fn offline_page(lang: &str, meta: &MetaJson) -> Result<SpecialPage, String> {
+    let title = get_string(meta, lang, "offline-title")?;
+    let text = get_string(meta, lang, "offline-text")?;
+    let retry = get_string(meta, lang, "offline-retry")?;
+    let home = get_string(meta, lang, "offline-home")?;
+    let root_href = get_root_href();
+
+    let mut content = format!("<p style='text-indent: 0px;'>{text}</p>");
+    content += &format!("<p style='text-indent: 0px;'><a href='#' onclick='window.location.reload(); return false;'>{retry}</a>");
+    content += &format!(" · <a href='{root_href}/{lang}'>{home}</a></p>");
+
+    Ok(SpecialPage {
+        title,
+        filepath: "offline".to_string(),
+        id: format!("{lang}-offline"),
+        description: text,
+        content,
+        special_content: "<style>#special-contents { display: block; }</style>".to_string(),
+    })
+}
+
+/// Needs node: `cargo test -- --ignored test_notfound_scripts`
+#[test]
+#[ignore]
+fn test_notfound_scripts() {
+    // the language choice and the suggestions run in the browser, run them in node with a stub DOM
+    let run = |stub: &str, script: &str| -> String {
+        let out = std::process::Command::new("node")
+            .arg("-e")
+            .arg(format!("{stub}\n{script}"))
+            .output()
+            .expect("node not found");
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        String::from_utf8_lossy(&out.stdout).trim().to_string()
+    };
+
+    let loader = include_str!("../../templates/notfound-loader.js")
+        .replace("$$LANGS$$", r#"["de","en","pl"]"#)
+        .replace("$$DEFAULT_LANG$$", DEFAULT_LANG);
+    let fetched = |path: &str| {
+        let stub = format!(
+            "var fetch = u => {{ console.log(u); return new Promise(() => {{}}); }};\
+             var window = {{ location: {{ pathname: '{path}' }}, fetch }};"
+        );
+        run(&stub, &loader)
+    };
+    assert_eq!(fetched("/pl/unknown"), "/pl/404.html");
+    assert_eq!(fetched("/de/a/b.html"), "/de/404.html");
+    // no language, an unknown one or the default: /404.html is already the right page
+    assert_eq!(fetched("/unknown"), "");
+    assert_eq!(fetched("/xx/unknown"), "");
+    assert_eq!(fetched("/en/unknown"), "");
+
+    let pages = r#"[["tugend","/de/tugend","Tugend"],["tugenden","/de/tugenden","Tugenden"],["laster","/de/laster","Laster"],["papst","/de/dokumente/hesse/papst","Papst"]]"#;
+    let notfound = include_str!("../../templates/notfound.js").replace("$$PAGES$$", pages);
+    let suggested = |path: &str| {
+        let stub = format!(
+            "var window = {{ location: {{ pathname: '{path}' }} }};\
+             var list = {{ appendChild: li => console.log(li.link.href) }};\
+             var document = {{\
+                 getElementById: id => id === 'notfound-suggestions' ? {{ style: {{}}, querySelector: () => list }} : null,\
+                 createElement: () => ({{ appendChild(c) {{ this.link = c; }} }}),\
+             }};"
+        );
+        run(&stub, &notfound)
+    };
+    // closest slug first, equal distances by title
+    assert_eq!(suggested("/de/tugende"), "/de/tugend\n/de/tugenden");
+    assert_eq!(suggested("/de/Tugend.html"), "/de/tugend\n/de/tugenden");
+    assert_eq!(suggested("/de/dokumente/hesse/papts/"), "/de/dokumente/hesse/papst");
+    assert_eq!(suggested("/de/zzzzzzzz"), "");
+}
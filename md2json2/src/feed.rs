@@ -0,0 +1,495 @@
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::citation::xml_escape;
+use crate::docs::AnalyzedDocuments;
+use crate::{author_page_id, get_description, get_root_href, get_string, get_tag_page_link, render_paragraph};
+use crate::{AnalyzedArticles, ArticlesByTag, MetaJson, ParsedArticleAnalyzed};
+
+// Only the newest items end up in a feed, older ones are on the website
+const MAX_FEED_ITEMS: usize = 50;
+// Atom requires a date for every entry, undated pages are sorted last
+const UNDATED: &str = "1970-01-01";
+
+/// Which pages a feed collects
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FeedKind {
+    // all articles of one language
+    Lang,
+    // articles with this tag
+    Tag(String),
+    // articles and documents by this author (key of meta.authors)
+    Author(String),
+    // documents in /docs/{lang}/{collection}
+    Collection(String),
+}
+
+impl FeedKind {
+    /// Path of the feed relative to /{lang}, without the .atom / .json extension
+    pub fn path(&self) -> String {
+        match self {
+            FeedKind::Lang => "feed".to_string(),
+            FeedKind::Tag(t) => format!("feed/tag/{t}"),
+            FeedKind::Author(a) => format!("feed/author/{}", author_page_id(a)),
+            FeedKind::Collection(c) => format!("feed/docs/{c}"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeedItem {
+    pub url: String,
+    pub title: String,
+    // yyyy-mm-dd or empty
+    pub date: String,
+    // (display name, url of the author page)
+    pub authors: Vec<(String, String)>,
+    pub tags: Vec<String>,
+    pub summary: String,
+    // full rendered body
+    pub content_html: String,
+}
+
+impl FeedItem {
+    fn new(
+        lang: &str,
+        slug: &str,
+        url: &str,
+        a: &ParsedArticleAnalyzed,
+        meta: &MetaJson,
+    ) -> Result<Self, String> {
+        let authors = a
+            .authors
+            .iter()
+            .map(|id| {
+                let name = meta
+                    .authors
+                    .get(id)
+                    .map(|q| q.displayname.clone())
+                    .unwrap_or_else(|| id.clone());
+                let href = format!("{}/{lang}/author/{}", get_root_href(), author_page_id(id));
+                (name, href)
+            })
+            .collect();
+
+        Ok(Self {
+            url: url.to_string(),
+            title: a.title.clone(),
+            date: a.get_iso_date(),
+            authors,
+            tags: a.tags.clone(),
+            summary: get_description(lang, a, meta)?,
+            content_html: feed_content(lang, slug, url, a),
+        })
+    }
+}
+
+/// One feed, written as both Atom and JSON Feed
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Feed {
+    pub lang: String,
+    pub kind: FeedKind,
+    pub title: String,
+    pub description: String,
+    // website page that shows the same items
+    pub home_page_url: String,
+    pub items: Vec<FeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: &'a str,
+    feed_url: String,
+    description: &'a str,
+    language: &'a str,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    summary: &'a str,
+    content_html: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: &'a [String],
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+    url: &'a str,
+}
+
+impl Feed {
+    fn new(
+        lang: &str,
+        kind: FeedKind,
+        title: String,
+        home_page_url: String,
+        mut items: Vec<FeedItem>,
+        meta: &MetaJson,
+    ) -> Result<Self, String> {
+        // newest first, undated last
+        items.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+        items.truncate(MAX_FEED_ITEMS);
+        Ok(Self {
+            lang: lang.to_string(),
+            kind,
+            title,
+            description: get_string(meta, lang, "index-desc")?,
+            home_page_url,
+            items,
+        })
+    }
+
+    pub fn url(&self, ext: &str) -> String {
+        format!("{}/{}/{}.{ext}", get_root_href(), self.lang, self.kind.path())
+    }
+
+    fn updated(&self) -> String {
+        let newest = self.items.iter().map(|i| i.date.as_str()).max().unwrap_or_default();
+        atom_date(newest)
+    }
+
+    pub fn to_atom(&self) -> String {
+        let mut s = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n".to_string();
+        s += &format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n", self.lang);
+        s += &format!("  <id>{}</id>\n", xml_escape(&self.url("atom")));
+        s += &format!("  <title>{}</title>\n", xml_escape(&self.title));
+        s += &format!("  <subtitle>{}</subtitle>\n", xml_escape(&self.description));
+        s += &format!("  <updated>{}</updated>\n", self.updated());
+        s += &format!(
+            "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n",
+            xml_escape(&self.url("atom"))
+        );
+        s += &format!(
+            "  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+            xml_escape(&self.home_page_url)
+        );
+        s += &format!("  <author><name>dubia.cc</name><uri>{}</uri></author>\n", get_root_href());
+        s += "  <rights>CC BY 4.0</rights>\n";
+
+        for i in self.items.iter() {
+            let date = atom_date(&i.date);
+            s += "  <entry>\n";
+            s += &format!("    <id>{}</id>\n", xml_escape(&i.url));
+            s += &format!("    <title>{}</title>\n", xml_escape(&i.title));
+            s += &format!("    <updated>{date}</updated>\n");
+            if !i.date.is_empty() {
+                s += &format!("    <published>{date}</published>\n");
+            }
+            s += &format!(
+                "    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+                xml_escape(&i.url)
+            );
+            for (name, href) in i.authors.iter() {
+                s += &format!(
+                    "    <author><name>{}</name><uri>{}</uri></author>\n",
+                    xml_escape(name),
+                    xml_escape(href)
+                );
+            }
+            for t in i.tags.iter() {
+                s += &format!("    <category term=\"{}\"/>\n", xml_escape(t));
+            }
+            s += &format!("    <summary>{}</summary>\n", xml_escape(&i.summary));
+            s += &format!("    <content type=\"html\">{}</content>\n", xml_escape(&i.content_html));
+            s += "  </entry>\n";
+        }
+
+        s += "</feed>\n";
+        s
+    }
+
+    pub fn to_json(&self) -> String {
+        let feed = JsonFeed {
+            version: "https://jsonfeed.org/version/1.1",
+            title: &self.title,
+            home_page_url: &self.home_page_url,
+            feed_url: self.url("json"),
+            description: &self.description,
+            language: &self.lang,
+            items: self
+                .items
+                .iter()
+                .map(|i| JsonFeedItem {
+                    id: &i.url,
+                    url: &i.url,
+                    title: &i.title,
+                    summary: &i.summary,
+                    content_html: &i.content_html,
+                    date_published: if i.date.is_empty() {
+                        None
+                    } else {
+                        Some(atom_date(&i.date))
+                    },
+                    authors: i
+                        .authors
+                        .iter()
+                        .map(|(name, url)| JsonFeedAuthor { name, url })
+                        .collect(),
+                    tags: &i.tags,
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&feed).unwrap_or_default()
+    }
+
+    /// Writes /{lang}/{path}.atom and /{lang}/{path}.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let path = dist.join(&self.lang).join(self.kind.path());
+        if let Some(p) = path.parent() {
+            std::fs::create_dir_all(p).map_err(|e| format!("{}: {e}", p.display()))?;
+        }
+        std::fs::write(path.with_extension("atom"), self.to_atom()).map_err(|e| e.to_string())?;
+        std::fs::write(path.with_extension("json"), self.to_json()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+// RFC 3339 timestamp for a yyyy-mm-dd date
+fn atom_date(date: &str) -> String {
+    let date = if date.is_empty() { UNDATED } else { date };
+    format!("{date}T00:00:00Z")
+}
+
+// Summary and sections without the page layout (drop caps, section
+// anchors, toolbars), footnote references point to the page itself
+fn feed_content(lang: &str, slug: &str, url: &str, a: &ParsedArticleAnalyzed) -> String {
+    let mut s = a
+        .summary
+        .iter()
+        .map(|p| render_paragraph(lang, p, true, slug))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    for section in a.sections.iter() {
+        let level = section.indent.clamp(1, 6);
+        if !section.title.trim().is_empty() {
+            s += &format!("<h{level}>{}</h{level}>", section.title);
+        }
+        for p in section.pars.iter() {
+            s += &render_paragraph(lang, p, false, slug);
+        }
+    }
+
+    s.replace("$$PAGE_HREF$$", url)
+}
+
+fn tag_name(lang: &str, tag: &str, meta: &MetaJson) -> String {
+    meta.tags
+        .get(lang)
+        .and_then(|t| t.tags.get(tag))
+        .cloned()
+        .unwrap_or_else(|| tag.to_string())
+}
+
+fn author_name(id: &str, meta: &MetaJson) -> String {
+    meta.authors
+        .get(id)
+        .map(|a| a.displayname.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// Builds the language, tag, author and document collection feeds of every language
+pub fn generate_feeds(
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    by_tag: &ArticlesByTag,
+    meta: &MetaJson,
+) -> Result<BTreeMap<String, Vec<Feed>>, String> {
+    let root_href = get_root_href();
+    let mut feeds = BTreeMap::new();
+
+    for lang in meta.strings.keys() {
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
+        let docs_title = get_string(meta, lang, "special-docs-title")?;
+
+        let mut article_items = BTreeMap::new();
+        for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+            let url = format!("{root_href}/{lang}/{slug}");
+            article_items.insert(slug.clone(), (a, FeedItem::new(lang, slug, &url, a, meta)?));
+        }
+
+        let mut doc_items = BTreeMap::new();
+        for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+            for (slug, d) in docs {
+                let url = format!("{root_href}/{lang}/{docs_path}/{collection}/{slug}");
+                let item = FeedItem::new(lang, slug, &url, d, meta)?;
+                doc_items
+                    .entry(collection.clone())
+                    .or_insert_with(Vec::new)
+                    .push((d, item));
+            }
+        }
+
+        let mut lang_feeds = vec![Feed::new(
+            lang,
+            FeedKind::Lang,
+            get_string(meta, lang, "index-title")?,
+            format!("{root_href}/{lang}"),
+            article_items.values().map(|(_, i)| i.clone()).collect(),
+            meta,
+        )?];
+
+        for (tag, links) in by_tag.get(lang).into_iter().flatten() {
+            let items = links
+                .iter()
+                .filter_map(|l| article_items.get(&l.slug))
+                .map(|(_, i)| i.clone())
+                .collect();
+            lang_feeds.push(Feed::new(
+                lang,
+                FeedKind::Tag(tag.clone()),
+                format!("{} - dubia.cc", tag_name(lang, tag, meta)),
+                format!("{root_href}/{}", get_tag_page_link(lang, tag, meta)?),
+                items,
+                meta,
+            )?);
+        }
+
+        for id in meta.authors.keys() {
+            let items = article_items
+                .values()
+                .chain(doc_items.values().flatten())
+                .filter(|(a, _)| a.authors.contains(id))
+                .map(|(_, i)| i.clone())
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                continue;
+            }
+            lang_feeds.push(Feed::new(
+                lang,
+                FeedKind::Author(id.clone()),
+                format!("{} - dubia.cc", author_name(id, meta)),
+                format!("{root_href}/{lang}/author/{}", author_page_id(id)),
+                items,
+                meta,
+            )?);
+        }
+
+        for (collection, docs) in doc_items.iter() {
+            lang_feeds.push(Feed::new(
+                lang,
+                FeedKind::Collection(collection.clone()),
+                format!("{docs_title} - {} - dubia.cc", author_name(collection, meta)),
+                format!("{root_href}/{lang}/{docs_path}/{collection}"),
+                docs.iter().map(|(_, i)| i.clone()).collect(),
+                meta,
+            )?);
+        }
+
+        feeds.insert(lang.clone(), lang_feeds);
+    }
+
+    Ok(feeds)
+}
+
+/// <link rel="alternate"> tags for the feeds a page belongs to
+///
+/// page_path: path of the page relative to /{lang}, see head()
+pub fn feed_links(
+    a: &ParsedArticleAnalyzed,
+    lang: &str,
+    page_path: &str,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    let docs_path = get_string(meta, lang, "special-docs-path")?;
+    let collection = page_path
+        .strip_prefix(&format!("{docs_path}/"))
+        .and_then(|p| p.split_once('/'))
+        .map(|(c, _)| c.to_string());
+
+    let mut feeds = vec![(FeedKind::Lang, get_string(meta, lang, "index-title")?)];
+    // the index page lists all topics as keywords
+    if page_path.is_empty() {
+        return Ok(render_feed_links(lang, &feeds));
+    }
+
+    match collection {
+        Some(c) => {
+            let docs_title = get_string(meta, lang, "special-docs-title")?;
+            let name = format!("{docs_title} - {}", author_name(&c, meta));
+            feeds.push((FeedKind::Collection(c), name));
+        }
+        None => {
+            for t in a.tags.iter() {
+                feeds.push((FeedKind::Tag(t.clone()), tag_name(lang, t, meta)));
+            }
+        }
+    }
+    for id in a.authors.iter().filter(|id| meta.authors.contains_key(*id)) {
+        feeds.push((FeedKind::Author(id.clone()), author_name(id, meta)));
+    }
+
+    Ok(render_feed_links(lang, &feeds))
+}
+
+fn render_feed_links(lang: &str, feeds: &[(FeedKind, String)]) -> String {
+    let root_href = get_root_href();
+    feeds
+        .iter()
+        .flat_map(|(kind, title)| {
+            let path = kind.path();
+            let title = xml_escape(title);
+            [
+                format!("<link rel=\"alternate\" type=\"application/atom+xml\" title=\"{title} (Atom)\" href=\"{root_href}/{lang}/{path}.atom\">"),
+                format!("<link rel=\"alternate\" type=\"application/feed+json\" title=\"{title} (JSON Feed)\" href=\"{root_href}/{lang}/{path}.json\">"),
+            ]
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n    ")
+}
+
+#[test]
+fn test_feed_atom() {
+    let feed = Feed {
+        lang: "en".to_string(),
+        kind: FeedKind::Tag("mass".to_string()),
+        title: "Mass & Liturgy".to_string(),
+        description: "desc".to_string(),
+        home_page_url: "https://dubia.cc/en/topics/mass".to_string(),
+        items: vec![
+            FeedItem {
+                url: "https://dubia.cc/en/a".to_string(),
+                title: "A <b>".to_string(),
+                date: "2025-02-04".to_string(),
+                authors: vec![("Fr. G. Hesse".to_string(), "https://dubia.cc/en/author/hesse".to_string())],
+                tags: vec!["mass".to_string()],
+                summary: "s".to_string(),
+                content_html: "<p>x</p>".to_string(),
+            },
+            FeedItem {
+                url: "https://dubia.cc/en/b".to_string(),
+                title: "B".to_string(),
+                ..Default::default()
+            },
+        ],
+    };
+
+    assert_eq!(feed.kind.path(), "feed/tag/mass");
+    assert_eq!(FeedKind::Author("discord:123".to_string()).path(), "feed/author/discord-123");
+
+    let atom = feed.to_atom();
+    assert!(atom.contains("<title>Mass &amp; Liturgy</title>"));
+    assert!(atom.contains("<updated>2025-02-04T00:00:00Z</updated>"));
+    assert!(atom.contains("<title>A &lt;b&gt;</title>"));
+    assert!(atom.contains("<content type=\"html\">&lt;p&gt;x&lt;/p&gt;</content>"));
+    assert!(atom.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+    assert_eq!(atom.matches("<published>").count(), 1);
+
+    let json: serde_json::Value = serde_json::from_str(&feed.to_json()).unwrap();
+    assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(json["items"][0]["date_published"], "2025-02-04T00:00:00Z");
+    assert_eq!(json["items"][0]["authors"][0]["name"], "Fr. G. Hesse");
+    assert!(json["items"][1].get("date_published").is_none());
+}
@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{extract_config, parse_article, parse_footnote, parse_image_align, take_next_link};
+use crate::{Config, Image, ImageAlignment, ParsedArticle};
+
+/// `md2json2 fmt [--check] [files or folders]`
+///
+/// Rewrites the Markdown sources of articles and documents in canonical form,
+/// with --check only lists the files that aren't formatted (for CI)
+pub fn run(cwd: &Path, args: &[String]) -> Result<(), String> {
+    let check = args.iter().any(|a| a == "--check");
+    let mut roots = args
+        .iter()
+        .filter(|a| !a.starts_with("--"))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if roots.is_empty() {
+        roots = vec![cwd.join("articles"), cwd.join("docs")];
+    }
+
+    let mut files = Vec::new();
+    for root in roots.iter() {
+        for entry in walkdir::WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("md") {
+                files.push(path.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+
+    let mut unformatted = 0;
+    let mut errors = 0;
+    for path in files.iter() {
+        let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        // not an article (sources lists, empty translations), nothing to format
+        if !src.lines().any(|l| l.starts_with("# ")) {
+            continue;
+        }
+        let formatted = match format_article(&src) {
+            Ok(o) => o,
+            Err(e) => {
+                println!("{}: {e}", path.display());
+                errors += 1;
+                continue;
+            }
+        };
+        if formatted == src {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            println!("{}", path.display());
+        } else {
+            std::fs::write(path, formatted).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+    }
+
+    if check && unformatted > 0 {
+        return Err(format!("{unformatted} of {} files are not formatted, run `md2json2 fmt`", files.len()));
+    }
+    if errors > 0 {
+        return Err(format!("{errors} of {} files could not be formatted", files.len()));
+    }
+    if !check {
+        println!("formatted {unformatted} of {} files", files.len());
+    }
+    Ok(())
+}
+
+/// Returns the canonical Markdown of an article, Err if the source can't be
+/// formatted without changing the parsed article
+pub fn format_article(src: &str) -> Result<String, String> {
+    let lines = src.lines().collect::<Vec<_>>();
+    let title_line = lines
+        .iter()
+        .position(|s| s.starts_with("# "))
+        .ok_or("missing \"# \" title line")?;
+
+    // same partition as parse_article()
+    let (config, config_lines) = extract_config(&lines);
+    let config_src = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, l)| config_lines.contains(i) && !l.contains("```"))
+        .map(|(_, l)| *l)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !config_src.trim().is_empty() {
+        serde_json::from_str::<Config>(&config_src).map_err(|e| format!("invalid config block: {e}"))?;
+    }
+
+    let keep = |range: std::ops::Range<usize>| {
+        range
+            .filter(|i| !config_lines.contains(i))
+            .map(|i| lines[i])
+            .collect::<Vec<_>>()
+    };
+    let summary = keep(0..title_line);
+    let after = keep(title_line + 1..lines.len());
+    let abstract_len = after.iter().take_while(|s| !s.contains("# ")).count();
+    let (article_abstract, rest) = after.split_at(abstract_len);
+    let footnotes = rest
+        .iter()
+        .filter(|l| parse_footnote(l).is_some())
+        .map(|l| l.trim())
+        .collect::<Vec<_>>();
+    let rest = rest
+        .iter()
+        .filter(|l| parse_footnote(l).is_none())
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut blocks = format_blocks(&summary);
+    blocks.push(format!("# {}", lines[title_line].replace("# ", "").trim()));
+    blocks.extend(format_blocks(article_abstract));
+
+    let mut section = Vec::new();
+    for l in rest.iter() {
+        if l.contains("# ") {
+            blocks.extend(format_blocks(&section));
+            section.clear();
+            blocks.push(format_heading(l));
+        } else {
+            section.push(*l);
+        }
+    }
+    blocks.extend(format_blocks(&section));
+
+    let text = blocks.join("\n");
+    blocks.extend(sort_footnotes(&text, &footnotes));
+    if !config_src.trim().is_empty() {
+        blocks.push(format!("```\n{}\n```", format_config(&config)));
+    }
+
+    let formatted = blocks.join("\n\n") + "\n";
+    if !same_article(&parse_article(src), &parse_article(&formatted)) {
+        return Err("formatting would change the parsed article, please fix the syntax by hand".to_string());
+    }
+    Ok(formatted)
+}
+
+// lines => blocks separated by empty lines, like parse_paragraphs()
+fn format_blocks(lines: &[&str]) -> Vec<String> {
+    let lines = lines.iter().map(|l| l.trim()).collect::<Vec<_>>();
+    lines
+        .split(|l| l.is_empty())
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            if b.iter().all(|l| l.starts_with('>')) {
+                format_quote(b)
+            } else if let [l] = b {
+                format_image(l).unwrap_or_else(|| l.to_string())
+            } else {
+                b.join("\n")
+            }
+        })
+        .collect()
+}
+
+fn format_heading(l: &str) -> String {
+    if !l.trim_start().starts_with('#') {
+        return l.trim().to_string();
+    }
+    let indent = l.chars().filter(|c| *c == '#').count();
+    format!("{} {}", "#".repeat(indent), l.replace("#", "").trim())
+}
+
+fn format_align(a: ImageAlignment) -> String {
+    match a {
+        ImageAlignment::FullWidth => "full-width".to_string(),
+        ImageAlignment::Left(w) => format!("align-left({w})"),
+        ImageAlignment::Right(w) => format!("align-right({w})"),
+    }
+}
+
+// ![alt :: align-right(300)](href "title")
+fn format_image(l: &str) -> Option<String> {
+    Image::new(l)?;
+    let (alt, rest) = l.strip_prefix("![")?.split_once("](")?;
+    let rest = rest.strip_suffix(')')?;
+    if rest.contains(')') {
+        return None;
+    }
+    let (alt, align) = match alt.split_once("::") {
+        Some((a, i)) => (a.trim(), parse_image_align(i).map(format_align)),
+        None => (alt.trim(), None),
+    };
+    let target = match rest.trim().split_once(char::is_whitespace) {
+        Some((href, title)) => format!("{href} {}", title.trim()),
+        None => rest.trim().to_string(),
+    };
+    Some(match align {
+        Some(a) => format!("![{alt} :: {a}]({target})"),
+        None => format!("![{alt}]({target})"),
+    })
+}
+
+// "> **Title**", paragraphs, "> -- [Author](..) [Source](..)", separated by ">"
+fn format_quote(b: &[&str]) -> String {
+    let inner = b
+        .iter()
+        .map(|l| l.replacen('>', "", 1).trim().to_string())
+        .collect::<Vec<_>>();
+    let prefixed = |lines: &[String]| {
+        lines
+            .iter()
+            .map(|l| if l.is_empty() { ">".to_string() } else { format!("> {l}") })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let title = inner
+        .iter()
+        .find(|l| !l.is_empty())
+        .filter(|l| l.starts_with("**") && l.ends_with("**"))
+        .cloned();
+    let author = inner
+        .iter()
+        .filter(|l| Some(*l) != title.as_ref())
+        .find(|l| l.starts_with("--") || l.starts_with("—-"))
+        .cloned();
+    let body = inner
+        .iter()
+        .filter(|l| Some(*l) != title.as_ref() && Some(*l) != author.as_ref())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // the parser drops these lines, leave the quote to the author
+    if body.iter().any(|l| l.starts_with("**") || l.starts_with("--")) {
+        return prefixed(&inner);
+    }
+
+    let mut out = Vec::new();
+    out.extend(title);
+    for p in body.split(|l| l.is_empty()).filter(|p| !p.is_empty()) {
+        if !out.is_empty() {
+            out.push(String::new());
+        }
+        out.extend(p.iter().cloned());
+    }
+    if let Some(a) = author {
+        if !out.is_empty() {
+            out.push(String::new());
+        }
+        out.push(format_attribution(&a));
+    }
+    prefixed(&out)
+}
+
+// "-- [Author](..). -- [Source](..)" => "-- [Author](..) [Source](..)"
+fn format_attribution(l: &str) -> String {
+    let a = l.replacen("--", "", 1).replacen("—-", "", 1);
+    let a = a.trim();
+    // take_next_link() counts an unclosed link up to the end of the line
+    let Some((author, rest)) = take_next_link(a).and_then(|(_, n)| Some((a.get(..n)?, a.get(n..)?))) else {
+        return format!("-- {a}");
+    };
+    let source = rest
+        .find('[')
+        .and_then(|p| take_next_link(&rest[p..]).and_then(|(_, m)| rest.get(p..p + m)));
+    match source {
+        Some(s) => format!("-- {author} {s}"),
+        None => format!("-- {author}"),
+    }
+}
+
+// footnotes in the order of their first reference, unreferenced ones last
+fn sort_footnotes(text: &str, footnotes: &[&str]) -> Vec<String> {
+    let mut first_ref = BTreeMap::new();
+    for (pos, _) in text.match_indices("[^") {
+        if let Some(end) = text[pos..].find(']') {
+            first_ref.entry(text[pos + 2..pos + end].to_string()).or_insert(pos);
+        }
+    }
+    let mut notes = footnotes
+        .iter()
+        .filter_map(|l| {
+            let (id, rest) = l.split_once(':')?;
+            let id = id.replace("[^", "").replace("]", "").trim().to_string();
+            Some((first_ref.get(&id).copied().unwrap_or(usize::MAX), format!("[^{id}]: {}", rest.trim())))
+        })
+        .collect::<Vec<_>>();
+    notes.sort_by_key(|(pos, _)| *pos);
+    if notes.is_empty() {
+        return Vec::new();
+    }
+    vec![notes.into_iter().map(|(_, n)| n).collect::<Vec<_>>().join("\n")]
+}
+
+// the config block with the keys in a fixed order and short arrays on one line
+fn format_config(c: &Config) -> String {
+    let s = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    let list = |v: &[String]| format!("[{}]", v.iter().map(|q| s(q)).collect::<Vec<_>>().join(", "));
+    let mut fields = Vec::new();
+    if !c.date.is_empty() {
+        fields.push(format!("    \"date\": {}", s(&c.date)));
+    }
+    if !c.authors.is_empty() {
+        fields.push(format!("    \"authors\": {}", list(&c.authors)));
+    }
+    if !c.tags.is_empty() {
+        fields.push(format!("    \"tags\": {}", list(&c.tags)));
+    }
+    if !c.translations.is_empty() {
+        let t = c
+            .translations
+            .iter()
+            .map(|(k, v)| format!("        {}: {}", s(k), s(v)))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        fields.push(format!("    \"translations\": {{\n{t}\n    }}"));
+    }
+    if !c.aliases.is_empty() {
+        fields.push(format!("    \"aliases\": {}", list(&c.aliases)));
+    }
+    if c.offline_audio {
+        fields.push("    \"offline-audio\": true".to_string());
+    }
+    if let Some(i) = c.initials.as_ref() {
+        fields.push(format!("    \"initials\": {}", s(i)));
+    }
+    format!("{{\n{}\n}}", fields.join(",\n"))
+}
+
+fn same_article(a: &ParsedArticle, b: &ParsedArticle) -> bool {
+    let json = |v: &[crate::ArticleSection]| serde_json::to_value(v).ok();
+    let mut fa = a.footnotes.clone();
+    let mut fb = b.footnotes.clone();
+    fa.sort();
+    fb.sort();
+    a.title == b.title
+        && a.date == b.date
+        && a.tags == b.tags
+        && a.authors == b.authors
+        && a.translations == b.translations
+        && a.aliases == b.aliases
+        && a.offline_audio == b.offline_audio
+        && a.initials == b.initials
+        && a.summary == b.summary
+        && a.article_abstract == b.article_abstract
+        && json(&a.sections) == json(&b.sections)
+        && fa == fb
+}
+
+#[test]
+fn test_format_article() {
+    let src = "```\n{ \"tags\": [\"a\",\"b\"], \"date\": \"2025-01-02\" }\n```\nSummary   \n\n\n# Title\n\nAbstract\n## Part one  \n\nText[^b] and[^a].\n\n[^a]: First\n\n>**Q**\n>\n> \"Quoted\n> text\"\n>\n> —- [Augustine](https://a.org/x (y)). -- [Confessions](https://b.org)\n\n![Image ::align-right(300)](image-1.avif   \"A title\")\n\n###   Two\n[^b]: Second\n";
+
+    let expected = "Summary\n\n# Title\n\nAbstract\n\n## Part one\n\nText[^b] and[^a].\n\n> **Q**\n>\n> \"Quoted\n> text\"\n>\n> -- [Augustine](https://a.org/x (y)) [Confessions](https://b.org)\n\n![Image :: align-right(300)](image-1.avif \"A title\")\n\n### Two\n\n[^b]: Second\n[^a]: First\n\n```\n{\n    \"date\": \"2025-01-02\",\n    \"tags\": [\"a\", \"b\"]\n}\n```\n";
+
+    let formatted = format_article(src).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_article(&formatted).unwrap(), formatted);
+
+    assert!(format_article("no title").is_err());
+    assert!(format_article("# T\n\n```\n{ \"date\": \"x\", }\n```\n").is_err());
+}
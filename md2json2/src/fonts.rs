@@ -0,0 +1,499 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+type Lang = String;
+
+/// Characters every page needs, whatever the language: printable ASCII
+/// and the punctuation of the templates (the range of the "BASIC" fonts)
+const BASE_CHARS: &[(char, char)] = &[
+    ('\u{0020}', '\u{007E}'),
+    ('\u{2010}', '\u{2010}'),
+    ('\u{2013}', '\u{2014}'),
+    ('\u{2018}', '\u{2019}'),
+    ('\u{201C}', '\u{201D}'),
+    ('\u{2212}', '\u{2212}'),
+];
+
+// Tables copied from the source font as they are: they only refer to glyph
+// ids, which the subsetter keeps. The glyphs GSUB substitutes (small caps,
+// old-style figures, ligatures) are added to the subset, see gsub_closure.
+const LAYOUT_TABLES: &[&[u8; 4]] = &[b"GDEF", b"GPOS", b"GSUB", b"kern"];
+
+/// config/fonts.json: the faces that are subsetted per language
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontsJson {
+    #[serde(default)]
+    pub faces: Vec<FontFace>,
+    // languages whose script the faces don't have (Greek, Cyrillic, Arabic,
+    // CJK): no subsets and no preloads, the text is set in the serif font
+    // stack of the system
+    #[serde(default)]
+    pub system: BTreeSet<Lang>,
+    // regular, italic, bold and bold italic face relative to the repository
+    // for the characters of the PDFs that the "BASIC" fonts don't have, see
+    // pdf::FontFiles
+    #[serde(default, rename = "pdf-fallback")]
+    pub pdf_fallback: Vec<String>,
+}
+
+impl FontsJson {
+    pub fn load(file: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(file) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{}: {e}", file.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FontFace {
+    pub family: String,
+    #[serde(default = "default_weight")]
+    pub weight: u16,
+    // "normal" or "italic"
+    #[serde(default = "default_style")]
+    pub style: String,
+    // TrueType or OpenType font relative to the repository, the more glyphs
+    // it has, the more languages are covered
+    pub src: String,
+    // <link rel="preload"> in the head of every page
+    #[serde(default)]
+    pub preload: bool,
+}
+
+fn default_weight() -> u16 {
+    400
+}
+
+fn default_style() -> String {
+    "normal".to_string()
+}
+
+impl FontFace {
+    /// "/de/font/source-serif-pro-400-italic.woff2"
+    pub fn url(&self, lang: &str) -> String {
+        let family = self
+            .family
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let style = if self.style == "normal" { String::new() } else { format!("-{}", self.style) };
+        format!("/{lang}/font/{family}-{}{style}.woff2", self.weight)
+    }
+}
+
+/// A face subsetted to the characters of one language
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WebFont {
+    pub face: FontFace,
+    pub url: String,
+    pub bytes: usize,
+    pub chars: BTreeSet<char>,
+    // characters of the language that the source font doesn't have, not
+    // counting BASE_CHARS (the "BASIC" fonts leave out space and hyphen)
+    pub missing: BTreeSet<char>,
+}
+
+impl WebFont {
+    pub fn font_face_css(&self) -> String {
+        format!(
+            "@font-face{{font-family:'{}';font-weight:{};font-style:{};src:url('{}') format('woff2');font-display:swap;unicode-range:{}}}",
+            self.face.family,
+            self.face.weight,
+            self.face.style,
+            self.url,
+            unicode_range(&self.chars)
+        )
+    }
+}
+
+/// WOFF2 subsets of the configured fonts per language, in /dist/{lang}/font
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WebFonts {
+    pub langs: BTreeMap<Lang, Vec<WebFont>>,
+    // see FontsJson::system
+    pub system: BTreeSet<Lang>,
+}
+
+impl WebFonts {
+    /// Subsets every face of `config` to the characters of each language
+    /// (see AnalyzedArticles::get_chars) and writes the WOFF2 files
+    pub fn build(
+        cwd: &Path,
+        dist: &Path,
+        config: &FontsJson,
+        chars: &BTreeMap<Lang, BTreeSet<char>>,
+    ) -> Result<Self, String> {
+        let mut fonts = Self {
+            system: config.system.clone(),
+            ..Default::default()
+        };
+        for face in config.faces.iter() {
+            let path = cwd.join(&face.src);
+            let source = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            for (lang, chars) in chars.iter().filter(|(l, _)| !config.system.contains(*l)) {
+                let base = base_chars();
+                let mut wanted = base.clone();
+                wanted.extend(chars.iter().copied().filter(|c| !c.is_control() && !c.is_whitespace()));
+                let (woff2, covered) = subset(&source, &wanted).map_err(|e| format!("{}: {e}", path.display()))?;
+                let url = face.url(lang);
+                let target = dist.join(url.trim_start_matches('/'));
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+                }
+                std::fs::write(&target, &woff2).map_err(|e| format!("{}: {e}", target.display()))?;
+                fonts.langs.entry(lang.clone()).or_default().push(WebFont {
+                    face: face.clone(),
+                    url,
+                    bytes: woff2.len(),
+                    missing: wanted.difference(&covered).filter(|c| !base.contains(c)).copied().collect(),
+                    chars: covered,
+                });
+            }
+        }
+        Ok(fonts)
+    }
+
+    /// Preload links and @font-face rules for the head of a page, None if
+    /// no fonts are configured, then the static "BASIC" fonts are used
+    pub fn head_html(&self, lang: &str) -> Option<(String, String)> {
+        if self.system.contains(lang) {
+            return Some((String::new(), String::new()));
+        }
+        let fonts = self.langs.get(lang).filter(|f| !f.is_empty())?;
+        let preload = fonts
+            .iter()
+            .filter(|f| f.face.preload)
+            .map(|f| format!("<link crossorigin=\"anonymous\" rel=\"preload\" href=\"{}\" as=\"font\" type=\"font/woff2\" crossorigin>", f.url))
+            .collect::<Vec<_>>()
+            .join("\r\n    ");
+        let css = fonts.iter().map(|f| f.font_face_css()).collect::<String>();
+        Some((preload, format!("<style id='font-css'>{css}</style>")))
+    }
+
+    /// One line per language, and a warning for every language with
+    /// characters that fall back to the system fonts
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (lang, fonts) in self.langs.iter() {
+            let bytes = fonts.iter().map(|f| f.bytes).sum::<usize>();
+            lines.push(format!("fonts: {lang}: {} faces, {} KB", fonts.len(), bytes / 1024));
+            let missing = fonts.iter().flat_map(|f| f.missing.iter().copied()).collect::<BTreeSet<_>>();
+            if !missing.is_empty() {
+                let sample = missing.iter().take(20).collect::<String>();
+                lines.push(format!(
+                    "fonts: warning: {lang}: {} characters not in the source fonts, set in the system fonts: {sample}",
+                    missing.len()
+                ));
+            }
+        }
+        if !self.system.is_empty() {
+            let langs = self.system.iter().cloned().collect::<Vec<_>>().join(", ");
+            lines.push(format!("fonts: {langs}: system fonts"));
+        }
+        lines
+    }
+}
+
+fn base_chars() -> BTreeSet<char> {
+    BASE_CHARS.iter().flat_map(|(a, b)| *a..=*b).collect()
+}
+
+/// "U+0020-007E,U+00E4,U+2013-2014"
+pub fn unicode_range(chars: &BTreeSet<char>) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for c in chars.iter().map(|c| *c as u32) {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == c => *end = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(a, b)| if a == b { format!("U+{a:04X}") } else { format!("U+{a:04X}-{b:04X}") })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// WOFF2 of the glyphs for `chars`, and the characters the font has
+fn subset(source: &[u8], chars: &BTreeSet<char>) -> Result<(Vec<u8>, BTreeSet<char>), String> {
+    let face = ttf_parser::Face::parse(source, 0).map_err(|e| e.to_string())?;
+    let covered = chars
+        .iter()
+        .copied()
+        .filter(|c| face.glyph_index(*c).is_some())
+        .collect::<BTreeSet<_>>();
+    let mut gids = BTreeSet::from([0]);
+    gids.extend(covered.iter().filter_map(|c| face.glyph_index(*c)).map(|g| g.0));
+    gsub_closure(&face, &mut gids);
+    let gids = gids.into_iter().collect::<Vec<_>>();
+
+    let subset = subsetter::subset(source, 0, subsetter::Profile::pdf(&gids)).map_err(|e| e.to_string())?;
+    let (flavor, mut tables) = read_tables(&subset)?;
+    let (_, original) = read_tables(source)?;
+    for (tag, data) in original {
+        if LAYOUT_TABLES.contains(&&tag) && !tables.iter().any(|(t, _)| *t == tag) {
+            tables.push((tag, data));
+        }
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+    Ok((woff2(flavor, tables)?, covered))
+}
+
+// Adds every glyph that a GSUB lookup can substitute for the glyphs in
+// `gids`, until nothing new is reached (small caps of old-style figures, ...)
+fn gsub_closure(face: &ttf_parser::Face, gids: &mut BTreeSet<u16>) {
+    use ttf_parser::gsub::{SingleSubstitution, SubstitutionSubtable};
+    use ttf_parser::GlyphId;
+
+    let Some(gsub) = face.tables().gsub else {
+        return;
+    };
+    loop {
+        let mut reached = BTreeSet::new();
+        for lookup in gsub.lookups {
+            for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                for g in gids.iter().map(|g| GlyphId(*g)) {
+                    let Some(i) = subtable.coverage().get(g) else {
+                        continue;
+                    };
+                    match subtable {
+                        SubstitutionSubtable::Single(SingleSubstitution::Format1 { delta, .. }) => {
+                            reached.insert(g.0.wrapping_add(delta as u16));
+                        }
+                        SubstitutionSubtable::Single(SingleSubstitution::Format2 { substitutes, .. }) => {
+                            reached.extend(substitutes.get(i).map(|s| s.0));
+                        }
+                        SubstitutionSubtable::Multiple(m) => {
+                            reached.extend(m.sequences.get(i).into_iter().flat_map(|s| s.substitutes).map(|s| s.0));
+                        }
+                        SubstitutionSubtable::Alternate(a) => {
+                            reached.extend(a.alternate_sets.get(i).into_iter().flat_map(|s| s.alternates).map(|s| s.0));
+                        }
+                        SubstitutionSubtable::Ligature(l) => {
+                            for ligature in l.ligature_sets.get(i).into_iter().flatten() {
+                                if ligature.components.into_iter().all(|c| gids.contains(&c.0)) {
+                                    reached.insert(ligature.glyph.0);
+                                }
+                            }
+                        }
+                        SubstitutionSubtable::ReverseChainSingle(r) => {
+                            reached.extend(r.substitutes.get(i).map(|s| s.0));
+                        }
+                        // only call the other lookups, at glyphs already in the set
+                        SubstitutionSubtable::Context(_) | SubstitutionSubtable::ChainContext(_) => {}
+                    }
+                }
+            }
+        }
+        let len = gids.len();
+        gids.extend(reached.into_iter().filter(|g| *g < face.number_of_glyphs()));
+        if gids.len() == len {
+            break;
+        }
+    }
+}
+
+type Table = ([u8; 4], Vec<u8>);
+
+// flavor and tables of a TrueType / OpenType file
+fn read_tables(sfnt: &[u8]) -> Result<(u32, Vec<Table>), String> {
+    let u32_at = |i: usize| sfnt.get(i..i + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    let flavor = u32_at(0).ok_or("font too short")?;
+    let count = sfnt.get(4..6).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or("font too short")?;
+    let mut tables = Vec::new();
+    for i in 0..count as usize {
+        let record = 12 + i * 16;
+        let tag = sfnt.get(record..record + 4).ok_or("invalid table record")?;
+        let offset = u32_at(record + 8).ok_or("invalid table record")? as usize;
+        let length = u32_at(record + 12).ok_or("invalid table record")? as usize;
+        let data = sfnt.get(offset..offset + length).ok_or("invalid table offset")?;
+        tables.push(([tag[0], tag[1], tag[2], tag[3]], data.to_vec()));
+    }
+    Ok((flavor, tables))
+}
+
+// WOFF2 with the null transform for all tables, compressed with Brotli,
+// see https://www.w3.org/TR/WOFF2/
+fn woff2(flavor: u32, mut tables: Vec<Table>) -> Result<Vec<u8>, String> {
+    // bit 11 of head.flags: the font was converted losslessly
+    if let Some((_, head)) = tables.iter_mut().find(|(tag, _)| tag == b"head") {
+        if let Some(flags) = head.get_mut(16..18) {
+            let v = u16::from_be_bytes([flags[0], flags[1]]) | (1 << 11);
+            flags.copy_from_slice(&v.to_be_bytes());
+        }
+    }
+
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    let mut sfnt_size = 12 + 16 * tables.len();
+    for (tag, data) in tables.iter() {
+        // 63: the tag follows, transform version 3 is the null transform of
+        // glyf and loca, version 0 the one of all other tables
+        let transform = if tag == b"glyf" || tag == b"loca" { 3 } else { 0 };
+        directory.push(0x3F | (transform << 6));
+        directory.extend_from_slice(tag);
+        write_base128(&mut directory, data.len() as u32);
+        stream.extend_from_slice(data);
+        sfnt_size += data.len().div_ceil(4) * 4;
+    }
+
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        mode: brotli::enc::backward_references::BrotliEncoderMode::BROTLI_MODE_FONT,
+        size_hint: stream.len(),
+        ..Default::default()
+    };
+    let mut compressed = Vec::new();
+    brotli::BrotliCompress(&mut stream.as_slice(), &mut compressed, &params).map_err(|e| e.to_string())?;
+
+    let length = (48 + directory.len() + compressed.len()).div_ceil(4) * 4;
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(b"wOF2");
+    out.extend_from_slice(&flavor.to_be_bytes());
+    out.extend_from_slice(&(length as u32).to_be_bytes());
+    out.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+    out.extend_from_slice(&0_u16.to_be_bytes());
+    out.extend_from_slice(&(sfnt_size as u32).to_be_bytes());
+    out.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+    out.extend_from_slice(&1_u16.to_be_bytes());
+    out.extend_from_slice(&0_u16.to_be_bytes());
+    // no metadata or private data
+    out.extend_from_slice(&[0; 20]);
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&compressed);
+    out.resize(length, 0);
+    Ok(out)
+}
+
+// UIntBase128: 7 bits per byte, most significant first, no leading zeros
+fn write_base128(out: &mut Vec<u8>, mut v: u32) {
+    let mut bytes = vec![(v & 0x7F) as u8];
+    v >>= 7;
+    while v > 0 {
+        bytes.push((v & 0x7F) as u8 | 0x80);
+        v >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+#[test]
+fn test_fonts() {
+    let mut v = Vec::new();
+    write_base128(&mut v, 63);
+    write_base128(&mut v, 128);
+    write_base128(&mut v, 16384);
+    assert_eq!(v, vec![0x3F, 0x81, 0x00, 0x81, 0x80, 0x00]);
+
+    let chars = "abcz\u{2013}\u{2014}".chars().collect::<BTreeSet<_>>();
+    assert_eq!(unicode_range(&chars), "U+0061-0063,U+007A,U+2013-2014");
+
+    let face = FontFace {
+        family: "Source Serif Pro".to_string(),
+        weight: 400,
+        style: "italic".to_string(),
+        src: String::new(),
+        preload: false,
+    };
+    assert_eq!(face.url("de"), "/de/font/source-serif-pro-400-italic.woff2");
+
+    // languages set in the system fonts get neither subsets nor preloads
+    let dist = std::env::temp_dir().join("md2json2-test-fonts");
+    let config = FontsJson {
+        faces: vec![FontFace { src: "static/font/ssfp/SourceSerifPro-BASIC-Regular.ttf".to_string(), preload: true, ..face.clone() }],
+        system: BTreeSet::from(["ru".to_string()]),
+        ..Default::default()
+    };
+    let chars = BTreeMap::from([
+        ("pl".to_string(), "zażółć".chars().collect()),
+        ("ru".to_string(), "Привет".chars().collect()),
+    ]);
+    let fonts = WebFonts::build(Path::new(".."), &dist, &config, &chars).unwrap();
+    let _ = std::fs::remove_dir_all(&dist);
+    assert!(!fonts.langs.contains_key("ru"));
+    assert_eq!(fonts.head_html("ru"), Some((String::new(), String::new())));
+    assert!(fonts.head_html("pl").unwrap().0.contains("/pl/font/source-serif-pro-400-italic.woff2"));
+    assert_eq!(fonts.langs["pl"][0].missing, "ćłż".chars().collect());
+    assert!(fonts.report().iter().any(|l| l.starts_with("fonts: warning: pl: 3 characters")));
+
+    let source = include_bytes!("../../static/font/ssfp/SourceSerifPro-BASIC-Regular.ttf");
+    let wanted = "Hallo Ω".chars().collect::<BTreeSet<_>>();
+    let (woff2, covered) = subset(source, &wanted).unwrap();
+    assert_eq!(&woff2[..4], b"wOF2");
+    assert_eq!(woff2.len() % 4, 0);
+    assert_eq!(u32::from_be_bytes([woff2[8], woff2[9], woff2[10], woff2[11]]) as usize, woff2.len());
+    assert!(covered.contains(&'H') && !covered.contains(&'Ω'));
+    assert!(woff2.len() < source.len() / 2);
+
+    // decode again: directory of null-transformed tables, then the Brotli stream
+    let num_tables = u16::from_be_bytes([woff2[12], woff2[13]]) as usize;
+    let compressed = u32::from_be_bytes([woff2[20], woff2[21], woff2[22], woff2[23]]) as usize;
+    let mut pos = 48;
+    let mut tables = Vec::new();
+    for _ in 0..num_tables {
+        assert_eq!(woff2[pos] & 0x3F, 0x3F);
+        let tag = [woff2[pos + 1], woff2[pos + 2], woff2[pos + 3], woff2[pos + 4]];
+        pos += 5;
+        let mut len = 0_usize;
+        loop {
+            len = (len << 7) | (woff2[pos] & 0x7F) as usize;
+            pos += 1;
+            if woff2[pos - 1] & 0x80 == 0 {
+                break;
+            }
+        }
+        tables.push((tag, len));
+    }
+    let mut stream = Vec::new();
+    brotli::BrotliDecompress(&mut &woff2[pos..pos + compressed], &mut stream).unwrap();
+    assert_eq!(stream.len(), tables.iter().map(|(_, l)| l).sum::<usize>());
+    assert!(tables.iter().any(|(t, _)| t == b"GPOS") && tables.iter().any(|(t, _)| t == b"GSUB"));
+
+    let mut sfnt = u32::from_be_bytes([woff2[4], woff2[5], woff2[6], woff2[7]]).to_be_bytes().to_vec();
+    sfnt.extend_from_slice(&(num_tables as u16).to_be_bytes());
+    sfnt.extend_from_slice(&[0; 6]);
+    let mut offset = 12 + 16 * num_tables;
+    let mut data = Vec::new();
+    let mut start = 0;
+    for (tag, len) in tables.iter() {
+        sfnt.extend_from_slice(tag);
+        sfnt.extend_from_slice(&[0; 4]);
+        sfnt.extend_from_slice(&(offset as u32).to_be_bytes());
+        sfnt.extend_from_slice(&(*len as u32).to_be_bytes());
+        data.extend_from_slice(&stream[start..start + len]);
+        data.resize(data.len().div_ceil(4) * 4, 0);
+        offset = 12 + 16 * num_tables + data.len();
+        start += len;
+    }
+    sfnt.extend_from_slice(&data);
+    let decoded = ttf_parser::Face::parse(&sfnt, 0).unwrap();
+    let h = decoded.glyph_index('H').unwrap();
+    assert!(decoded.glyph_bounding_box(h).is_some());
+    assert!(decoded.glyph_bounding_box(decoded.glyph_index('Z').unwrap()).is_none());
+
+    // font-variant-caps: small-caps, the small "a" of the smcp lookup still has an outline
+    let gsub = decoded.tables().gsub.unwrap();
+    let smcp = gsub.features.find(ttf_parser::Tag::from_bytes(b"smcp")).unwrap();
+    let a = decoded.glyph_index('a').unwrap();
+    let small_a = smcp
+        .lookup_indices
+        .into_iter()
+        .filter_map(|i| gsub.lookups.get(i))
+        .flat_map(|l| l.subtables.into_iter::<ttf_parser::gsub::SubstitutionSubtable>())
+        .find_map(|s| match s {
+            ttf_parser::gsub::SubstitutionSubtable::Single(s) => {
+                let i = s.coverage().get(a)?;
+                match s {
+                    ttf_parser::gsub::SingleSubstitution::Format1 { delta, .. } => Some(ttf_parser::GlyphId(a.0.wrapping_add(delta as u16))),
+                    ttf_parser::gsub::SingleSubstitution::Format2 { substitutes, .. } => substitutes.get(i),
+                }
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_ne!(small_a, a);
+    assert!(decoded.glyph_bounding_box(small_a).is_some());
+}
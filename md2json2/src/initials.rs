@@ -0,0 +1,290 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::docs::AnalyzedDocuments;
+use crate::{AnalyzedArticles, ParsedArticleAnalyzed};
+
+type Lang = String;
+
+/// Font name that turns the initials off ("languages": {"ja": "none"})
+pub const NONE: &str = "none";
+
+// Placeholder for the letter in the path of fonts with one file per letter
+const LETTER: &str = "{letter}";
+
+/// config/initials.json: the fonts of the dropcaps and which pages use which
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InitialsJson {
+    // font of the initials if neither the article nor its language sets one
+    pub default: String,
+    // lang => font
+    pub languages: BTreeMap<Lang, String>,
+    // name => font, the name is the class of the paragraph ("dropcap-kanzlei")
+    pub fonts: BTreeMap<String, InitialFont>,
+}
+
+impl Default for InitialsJson {
+    fn default() -> Self {
+        Self {
+            default: "kanzlei".to_string(),
+            languages: BTreeMap::new(),
+            fonts: [(
+                "kanzlei".to_string(),
+                InitialFont {
+                    family: "Kanzlei Initialen".to_string(),
+                    src: "/static/font/kanzlei/Kanzlei-Initialen-{letter}.ttf".to_string(),
+                    fallback: Vec::new(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+impl InitialsJson {
+    pub fn load(file: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(file) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{}: {e}", file.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct InitialFont {
+    pub family: String,
+    // URL of the font, with "{letter}" for fonts that have one file per letter
+    // ("/static/font/yinit/Yinit-{letter}.ttf")
+    pub src: String,
+    // fonts that are tried next for letters this one doesn't have
+    #[serde(default)]
+    pub fallback: Vec<String>,
+}
+
+/// The dropcap of a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Initial {
+    // quotation marks etc. before the letter ("„")
+    pub preceding: String,
+    pub letter: char,
+    // font that has the letter, None if none of the fonts has it: then the
+    // letter is set in the serif font of the text
+    pub font: Option<String>,
+}
+
+/// The fonts of config/initials.json with the letters they have
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Initials {
+    pub config: InitialsJson,
+    // font => letters
+    pub letters: BTreeMap<String, BTreeSet<char>>,
+}
+
+impl Initials {
+    /// Looks up the letters of every font: the files next to each other for
+    /// fonts with one file per letter, the cmap for the others
+    pub fn new(cwd: &Path, config: InitialsJson) -> Self {
+        let letters = config
+            .fonts
+            .iter()
+            .map(|(name, font)| (name.clone(), font_letters(cwd, &font.src)))
+            .collect();
+        Self { config, letters }
+    }
+
+    /// Font that the article wants, `initials` is the "initials" of its config
+    pub fn font_of<'a>(&'a self, lang: &str, initials: Option<&'a str>) -> &'a str {
+        initials
+            .or(self.config.languages.get(lang).map(|s| s.as_str()))
+            .unwrap_or(&self.config.default)
+    }
+
+    /// Dropcap for the first paragraph of the article, None for prayers,
+    /// pages without a first paragraph and languages without initials
+    pub fn resolve(&self, lang: &str, a: &ParsedArticleAnalyzed) -> Option<Initial> {
+        if a.is_prayer() {
+            return None;
+        }
+        let font = self.font_of(lang, a.initials.as_deref());
+        if font == NONE {
+            return None;
+        }
+        let text = first_text(a)?;
+        let start = text.find(|c: char| c.is_alphanumeric())?;
+        let preceding = &text[..start];
+        if preceding.chars().any(|c| !(c.is_ascii_punctuation() || is_quote(c))) {
+            return None;
+        }
+        let letter = text[start..].chars().next()?;
+        if !letter.is_alphabetic() {
+            return None;
+        }
+        // "ß" => "SS" isn't one letter
+        let mut upper = letter.to_uppercase();
+        let letter = match (upper.next(), upper.next()) {
+            (Some(u), None) => u,
+            _ => letter,
+        };
+        Some(Initial {
+            preceding: preceding.to_string(),
+            letter,
+            font: self.font_for(font, letter, &mut BTreeSet::new()),
+        })
+    }
+
+    // the first font in the fallback chain of `font` that has `letter`
+    fn font_for(&self, font: &str, letter: char, visited: &mut BTreeSet<String>) -> Option<String> {
+        if !visited.insert(font.to_string()) {
+            return None;
+        }
+        if self.letters.get(font).is_some_and(|l| l.contains(&letter)) {
+            return Some(font.to_string());
+        }
+        let fallback = self.config.fonts.get(font)?.fallback.clone();
+        fallback.iter().find_map(|f| self.font_for(f, letter, visited))
+    }
+
+    /// URL of the font file with the glyph of the initial
+    pub fn url(&self, initial: &Initial) -> Option<String> {
+        let font = self.config.fonts.get(initial.font.as_ref()?)?;
+        Some(font.src.replace(LETTER, &initial.letter.to_string()))
+    }
+
+    /// @font-face rule for the letter and the font of the dropcap
+    pub fn css(&self, initial: &Initial) -> String {
+        let mut rules = Vec::new();
+        match initial.font.as_ref().and_then(|f| Some((f, self.config.fonts.get(f)?))) {
+            Some((name, font)) => {
+                rules.push(format!(
+                    "@font-face {{\r\n    font-family: '{}';\r\n    src: url('{}') format('truetype');\r\n    font-display: swap;\r\n    unicode-range: U+{:04X};\r\n}}",
+                    font.family,
+                    self.url(initial).unwrap_or_default(),
+                    initial.letter as u32
+                ));
+                rules.push(format!(
+                    "@media screen and (min-width: 649px) {{\r\n    p.dropcap-{name} span.dropcap {{ font-family: '{}'; }}\r\n}}",
+                    font.family
+                ));
+            }
+            None => rules.push(
+                "@media screen and (min-width: 649px) {\r\n    p.dropcap-plain span.dropcap { font-family: var(--GW-serif-font-stack); font-weight: 600; }\r\n}"
+                    .to_string(),
+            ),
+        }
+        if !initial.preceding.is_empty() {
+            rules.push(
+                "@media screen and (min-width: 649px) {\r\n    .initial-preceding-punctuation { float: left; margin-left: -1ch; font-size: 1.5em; }\r\n}"
+                    .to_string(),
+            );
+        }
+        rules.join("\r\n")
+    }
+
+    /// Class of the paragraph with the dropcap ("dropcap-kanzlei")
+    pub fn class(&self, initial: &Initial) -> String {
+        format!("dropcap-{}", initial.font.as_deref().unwrap_or("plain"))
+    }
+
+    /// The initial fonts of all pages, for the service worker
+    pub fn precache_urls(&self, articles: &AnalyzedArticles, documents: &AnalyzedDocuments) -> Vec<String> {
+        let articles = articles.map.iter().flat_map(|(l, a)| a.values().map(move |a| (l, a)));
+        let documents = documents
+            .map
+            .iter()
+            .flat_map(|(l, d)| d.values().flat_map(|d| d.values()).map(move |a| (l, a)));
+        articles
+            .chain(documents)
+            .filter_map(|(l, a)| self.url(&self.resolve(l, a)?))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Text of the paragraph that starts with the dropcap, see body_abstract
+pub fn first_text(a: &ParsedArticleAnalyzed) -> Option<&str> {
+    a.summary.first()?.as_sentence()?.first()?.text().map(|s| s.as_str())
+}
+
+fn is_quote(c: char) -> bool {
+    matches!(c, '„' | '“' | '”' | '‚' | '‘' | '’' | '«' | '»' | '‹' | '›' | '¿' | '¡' | '「' | '『')
+}
+
+fn font_letters(cwd: &Path, src: &str) -> BTreeSet<char> {
+    let path = cwd.join(src.trim_start_matches('/'));
+    let Some((prefix, suffix)) = src.split_once(LETTER) else {
+        let Ok(bytes) = std::fs::read(&path) else {
+            return BTreeSet::new();
+        };
+        let Ok(face) = ttf_parser::Face::parse(&bytes, 0) else {
+            return BTreeSet::new();
+        };
+        let mut letters = BTreeSet::new();
+        for subtable in face.tables().cmap.iter().flat_map(|c| c.subtables) {
+            if subtable.is_unicode() {
+                subtable.codepoints(|c| letters.extend(char::from_u32(c).filter(|c| c.is_alphabetic())));
+            }
+        }
+        return letters;
+    };
+    let (dir, prefix) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+    let Ok(entries) = std::fs::read_dir(cwd.join(dir.trim_start_matches('/'))) else {
+        return BTreeSet::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let letter = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            let mut chars = letter.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_initials() {
+    use crate::{Paragraph, SentenceItem};
+
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let config = serde_json::from_str::<InitialsJson>(
+        r#"{
+            "default": "yinit",
+            "languages": { "ja": "none" },
+            "fonts": {
+                "yinit": { "family": "Yinit", "src": "/static/font/yinit/Yinit-{letter}.ttf", "fallback": ["kanzlei"] },
+                "kanzlei": { "family": "Kanzlei Initialen", "src": "/static/font/kanzlei/Kanzlei-Initialen-{letter}.ttf", "fallback": ["unifraktur", "yinit"] },
+                "unifraktur": { "family": "UnifrakturMaguntia", "src": "/static/font/unifraktur/UnifrakturMaguntia-Regular.ttf" }
+            }
+        }"#,
+    )
+    .unwrap();
+    let initials = Initials::new(&cwd, config);
+    assert!(initials.letters["yinit"].contains(&'T') && !initials.letters["yinit"].contains(&'A'));
+    assert!(initials.letters["unifraktur"].contains(&'Ü'));
+
+    let article = |text: &str| ParsedArticleAnalyzed {
+        summary: vec![Paragraph::Sentence { s: vec![SentenceItem::Text { text: text.to_string() }] }],
+        ..Default::default()
+    };
+    let resolve = |lang: &str, text: &str| initials.resolve(lang, &article(text)).map(|i| (i.preceding, i.letter, i.font));
+
+    assert_eq!(resolve("de", "Tugend"), Some((String::new(), 'T', Some("yinit".to_string()))));
+    assert_eq!(resolve("de", "„Am Anfang"), Some(("„".to_string(), 'A', Some("kanzlei".to_string()))));
+    assert_eq!(resolve("de", "Über"), Some((String::new(), 'Ü', Some("unifraktur".to_string()))));
+    assert_eq!(resolve("pl", "Święty"), Some((String::new(), 'Ś', None)));
+    assert_eq!(resolve("de", "1917"), None);
+    assert_eq!(resolve("ja", "Tugend"), None);
+
+    let a = ParsedArticleAnalyzed { initials: Some("kanzlei".to_string()), ..article("Tugend") };
+    let initial = initials.resolve("de", &a).unwrap();
+    assert_eq!(initials.url(&initial).as_deref(), Some("/static/font/kanzlei/Kanzlei-Initialen-T.ttf"));
+    assert_eq!(initials.class(&initial), "dropcap-kanzlei");
+    assert!(initials.css(&initial).contains("unicode-range: U+0054;"));
+}
@@ -0,0 +1,426 @@
+use serde_json::{json, Value};
+
+use crate::sitemap::hreflang;
+use crate::{author_page_id, gen_section_id, get_description, get_root_href, get_string, get_tag_page_link, par2text};
+use crate::{ArticleType, MetaJson, ParsedArticleAnalyzed};
+
+// FAQ answers are cut after this many characters
+const MAX_ANSWER_LEN: usize = 1000;
+
+/// <script type="application/ld+json"> with the schema.org data of a page
+///
+/// page_path: path of the page relative to /{lang}, see head()
+pub fn json_ld(
+    a: &ParsedArticleAnalyzed,
+    lang: &str,
+    page_path: &str,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    let graph = json_ld_graph(a, lang, page_path, meta)?;
+    if graph.is_empty() {
+        return Ok(String::new());
+    }
+
+    let ld = json!({
+        "@context": "https://schema.org",
+        "@graph": graph,
+    });
+
+    // "</script>" inside of a string would end the script tag
+    let s = serde_json::to_string(&ld).map_err(|e| e.to_string())?.replace("</", "<\\/");
+    Ok(format!("<script type=\"application/ld+json\">{s}</script>"))
+}
+
+fn json_ld_graph(
+    a: &ParsedArticleAnalyzed,
+    lang: &str,
+    page_path: &str,
+    meta: &MetaJson,
+) -> Result<Vec<Value>, String> {
+    let root_href = get_root_href();
+    let home = format!("{root_href}/{lang}");
+
+    if page_path.is_empty() {
+        return Ok(vec![website(lang, meta)?]);
+    }
+
+    if let Some(page_id) = page_path.strip_prefix("author/") {
+        return profile_page(lang, page_id, meta);
+    }
+
+    // special pages (topics, search, ...) are not articles
+    if a.sha256.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let url = format!("{home}/{page_path}");
+    let docs_path = get_string(meta, lang, "special-docs-path")?;
+    let collection = page_path
+        .strip_prefix(&format!("{docs_path}/"))
+        .and_then(|p| p.split_once('/'))
+        .map(|(c, _)| c.to_string());
+
+    let mut page = json!({
+        "@type": "CreativeWork",
+        "@id": url,
+        "url": url,
+        "name": a.title,
+        "headline": a.title.chars().take(110).collect::<String>(),
+        "description": get_description(lang, a, meta)?,
+        "inLanguage": hreflang(lang),
+        "author": a.authors.iter().map(|id| person(lang, id, meta)).collect::<Vec<_>>(),
+        "publisher": publisher(),
+        "mainEntityOfPage": url,
+        "license": "https://creativecommons.org/licenses/by/4.0/",
+        "version": a.sha256.chars().take(12).collect::<String>(),
+    });
+
+    let date = a.get_iso_date();
+    if !date.is_empty() {
+        page["datePublished"] = json!(date);
+    }
+    if !a.tags.is_empty() {
+        page["keywords"] = json!(a.tags.join(", "));
+    }
+    if let Some(img) = a.img.as_ref().filter(|i| i.href.contains("://")) {
+        page["image"] = json!(img.href);
+    }
+
+    let mut crumbs = vec![(get_string(meta, lang, "index-title")?, home.clone())];
+    let mut graph = Vec::new();
+
+    match collection {
+        Some(c) => {
+            let docs_title = get_string(meta, lang, "special-docs-title")?;
+            let collection_name = meta
+                .authors
+                .get(&c)
+                .map(|q| q.displayname.clone())
+                .unwrap_or_else(|| c.clone());
+            page["isPartOf"] = json!({
+                "@type": "CreativeWork",
+                "name": format!("{docs_title} - {collection_name}"),
+                "url": format!("{home}/{docs_path}/{c}"),
+            });
+            crumbs.push((docs_title, format!("{home}/{docs_path}")));
+            crumbs.push((collection_name, format!("{home}/{docs_path}/{c}")));
+        }
+        None => {
+            page["@type"] = json!(match a.atype {
+                ArticleType::Prayer => "CreativeWork",
+                _ if !a.bibliography.is_empty() => "ScholarlyArticle",
+                _ => "Article",
+            });
+            if a.atype == ArticleType::Prayer {
+                page["genre"] = json!("Prayer");
+            }
+            if let Some(t) = a.tags.first() {
+                let name = meta
+                    .tags
+                    .get(lang)
+                    .and_then(|q| q.tags.get(t))
+                    .cloned()
+                    .unwrap_or_else(|| t.clone());
+                crumbs.push((name, format!("{root_href}/{}", get_tag_page_link(lang, t, meta)?)));
+            }
+            if a.atype == ArticleType::Question {
+                if let Some(faq) = faq_page(a, &url) {
+                    graph.push(faq);
+                }
+            }
+        }
+    }
+
+    crumbs.push((a.title.clone(), url.clone()));
+    graph.insert(0, page);
+    graph.push(breadcrumbs(&crumbs));
+    Ok(graph)
+}
+
+fn publisher() -> Value {
+    json!({
+        "@type": "Organization",
+        "name": "dubia.cc",
+        "url": get_root_href(),
+        "logo": format!("{}/static/img/logo/logo-sm-192.avif", get_root_href()),
+    })
+}
+
+fn person(lang: &str, id: &str, meta: &MetaJson) -> Value {
+    let author = meta.authors.get(id);
+    let mut p = json!({
+        "@type": "Person",
+        "name": author.map(|q| q.displayname.clone()).unwrap_or_else(|| id.to_string()),
+        "url": format!("{}/{lang}/author/{}", get_root_href(), author_page_id(id)),
+    });
+    if let Some(contact) = author.and_then(|q| q.contact.as_ref()).filter(|c| c.starts_with("http")) {
+        p["sameAs"] = json!(contact);
+    }
+    p
+}
+
+// /{lang}/author/{id}: the author with biography, role and life dates
+fn profile_page(lang: &str, page_id: &str, meta: &MetaJson) -> Result<Vec<Value>, String> {
+    let root_href = get_root_href();
+    let Some((id, author)) = meta
+        .authors
+        .iter()
+        .find(|(id, _)| author_page_id(id) == page_id)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let url = format!("{root_href}/{lang}/author/{page_id}");
+    let mut p = person(lang, id, meta);
+    if let Some(bio) = author.get_bio(lang).first() {
+        p["description"] = json!(bio);
+    }
+    if let Some(role) = author.get_role(lang) {
+        p["jobTitle"] = json!(role);
+    }
+    if let Some(born) = author.born.as_ref() {
+        p["birthDate"] = json!(born);
+    }
+    if let Some(died) = author.died.as_ref() {
+        p["deathDate"] = json!(died);
+    }
+    if let Some(portrait) = author.portrait.as_ref() {
+        p["image"] = json!(format!("{root_href}/{}", portrait.trim_start_matches('/')));
+    }
+
+    let crumbs = [
+        (get_string(meta, lang, "index-title")?, format!("{root_href}/{lang}")),
+        (author.displayname.clone(), url.clone()),
+    ];
+
+    Ok(vec![
+        json!({
+            "@type": "ProfilePage",
+            "@id": url,
+            "url": url,
+            "name": author.displayname,
+            "inLanguage": hreflang(lang),
+            "mainEntity": p,
+        }),
+        breadcrumbs(&crumbs),
+    ])
+}
+
+fn breadcrumbs(crumbs: &[(String, String)]) -> Value {
+    json!({
+        "@type": "BreadcrumbList",
+        "itemListElement": crumbs.iter().enumerate().map(|(i, (name, url))| json!({
+            "@type": "ListItem",
+            "position": i + 1,
+            "name": name,
+            "item": url,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+// The title of a question article is the question, the text above the
+// title (subtitle) and the first section answer it
+fn faq_page(a: &ParsedArticleAnalyzed, url: &str) -> Option<Value> {
+    let section = a.sections.first();
+    let answer = a
+        .subtitle
+        .iter()
+        .chain(section.into_iter().flat_map(|s| s.pars.iter()))
+        .map(par2text)
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if answer.is_empty() {
+        return None;
+    }
+
+    let mut text = answer.chars().take(MAX_ANSWER_LEN).collect::<String>();
+    if text.len() < answer.len() {
+        if let Some(p) = text.rfind(char::is_whitespace) {
+            text.truncate(p);
+        }
+        text += " …";
+    }
+
+    let answer_url = match section {
+        Some(s) if !gen_section_id(&s.title).is_empty() => format!("{url}#{}", gen_section_id(&s.title)),
+        _ => url.to_string(),
+    };
+
+    Some(json!({
+        "@type": "FAQPage",
+        "url": url,
+        "mainEntity": [{
+            "@type": "Question",
+            "name": a.title,
+            "acceptedAnswer": {
+                "@type": "Answer",
+                "text": text,
+                "url": answer_url,
+            },
+        }],
+    }))
+}
+
+fn website(lang: &str, meta: &MetaJson) -> Result<Value, String> {
+    let home = format!("{}/{lang}", get_root_href());
+    Ok(json!({
+        "@type": "WebSite",
+        "@id": home,
+        "url": home,
+        "name": "dubia.cc",
+        "description": get_string(meta, lang, "index-desc")?,
+        "inLanguage": hreflang(lang),
+        "publisher": publisher(),
+        "potentialAction": {
+            "@type": "SearchAction",
+            "target": {
+                "@type": "EntryPoint",
+                "urlTemplate": format!("{home}/search?q={{search_term_string}}"),
+            },
+            "query-input": "required name=search_term_string",
+        },
+    }))
+}
+
+// Checks a JSON-LD value against the schema.org subset in schema-org.json:
+// known types, allowed properties (including inherited ones), required
+// properties and value types
+#[cfg(test)]
+fn validate(schema: &Value, v: &Value, expected: &[&str], path: &str) -> Result<(), String> {
+    let is_subtype = |t: &str, of: &str| {
+        let mut t = Some(t.to_string());
+        while let Some(q) = t {
+            if q == of {
+                return true;
+            }
+            t = schema[&q]["parent"].as_str().map(|s| s.to_string());
+        }
+        false
+    };
+
+    let ok = expected.iter().any(|e| match *e {
+        "Text" => v.is_string(),
+        "URL" => v.as_str().map(|s| s.starts_with("http://") || s.starts_with("https://")).unwrap_or(false),
+        "Date" => v
+            .as_str()
+            .map(|s| s.len() == 10 && s.chars().enumerate().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() }))
+            .unwrap_or(false),
+        "Integer" => v.is_u64(),
+        t => v["@type"].as_str().map(|q| is_subtype(q, t)).unwrap_or(false),
+    });
+    if !ok {
+        return Err(format!("{path}: expected {expected:?}, got {v}"));
+    }
+
+    let Some(t) = v["@type"].as_str() else {
+        return Ok(());
+    };
+
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    let mut q = Some(t.to_string());
+    while let Some(ty) = q {
+        let def = schema.get(&ty).ok_or_else(|| format!("{path}: unknown type {ty}"))?;
+        properties.extend(def["properties"].as_object().cloned().unwrap_or_default());
+        required.extend(def["required"].as_array().cloned().unwrap_or_default());
+        q = def["parent"].as_str().map(|s| s.to_string());
+    }
+
+    for r in required.iter().filter_map(|r| r.as_str()) {
+        if v.get(r).is_none() {
+            return Err(format!("{path}: {t} requires {r}"));
+        }
+    }
+
+    for (k, val) in v.as_object().into_iter().flatten() {
+        if k.starts_with('@') {
+            continue;
+        }
+        let allowed = properties
+            .get(k)
+            .and_then(|p| p.as_array())
+            .ok_or_else(|| format!("{path}: {t} has no property {k}"))?
+            .iter()
+            .filter_map(|s| s.as_str())
+            .collect::<Vec<_>>();
+        let values = match val {
+            Value::Array(a) => a.clone(),
+            other => vec![other.clone()],
+        };
+        for (i, item) in values.iter().enumerate() {
+            validate(schema, item, &allowed, &format!("{path}.{k}[{i}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_json_ld() {
+    use crate::{ArticleSection, Paragraph, SentenceItem};
+
+    let schema: Value = serde_json::from_str(include_str!("schema-org.json")).unwrap();
+    let meta = crate::read_meta_json(include_str!("../../config/meta.json"));
+    let text = |s: &str| Paragraph::Sentence { s: vec![SentenceItem::Text { text: s.to_string() }] };
+
+    let a = ParsedArticleAnalyzed {
+        title: "Sind Brot und Wein nur Symbole?".to_string(),
+        date: "2025-02-04".to_string(),
+        tags: vec!["katholisch".to_string()],
+        authors: vec!["schuett".to_string(), "hesse".to_string()],
+        atype: ArticleType::Question,
+        sha256: "abcdefghijklmnopqrstuvwxyz".to_string(),
+        subtitle: vec![text("Nein, sie werden gewandelt.")],
+        sections: vec![ArticleSection {
+            title: "Bibelverse".to_string(),
+            indent: 2,
+            pars: vec![text("Ich bin das lebendige </script> Brot.")],
+        }],
+        ..Default::default()
+    };
+
+    let check = |page_path: &str, a: &ParsedArticleAnalyzed| -> Value {
+        let s = json_ld(a, "de", page_path, &meta).unwrap();
+        assert_eq!(s.matches("</script>").count(), 1);
+        let s = s
+            .trim_start_matches("<script type=\"application/ld+json\">")
+            .trim_end_matches("</script>")
+            .replace("<\\/", "</");
+        let ld: Value = serde_json::from_str(&s).unwrap();
+        assert_eq!(ld["@context"], "https://schema.org");
+        for (i, node) in ld["@graph"].as_array().unwrap().iter().enumerate() {
+            validate(&schema, node, &["Thing"], &format!("{page_path}[{i}]")).unwrap();
+        }
+        ld
+    };
+
+    let ld = check("brot-wein-symbolisch", &a);
+    let types = ld["@graph"].as_array().unwrap().iter().map(|n| n["@type"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(types, ["Article", "FAQPage", "BreadcrumbList"]);
+    assert_eq!(ld["@graph"][0]["author"][1]["name"], "Fr. G. Hesse");
+    assert_eq!(ld["@graph"][2]["itemListElement"].as_array().unwrap().len(), 3);
+    let answer = ld["@graph"][1]["mainEntity"][0]["acceptedAnswer"]["text"].as_str().unwrap();
+    assert!(answer.starts_with("Nein, sie werden gewandelt.\n\nIch bin"));
+
+    let doc = ParsedArticleAnalyzed { atype: ArticleType::Tract, ..a.clone() };
+    let ld = check("dok/lefebvre/erklaerung-1974", &doc);
+    assert_eq!(ld["@graph"][0]["@type"], "CreativeWork");
+    assert_eq!(ld["@graph"][1]["itemListElement"].as_array().unwrap().len(), 4);
+
+    let ld = check("", &ParsedArticleAnalyzed::default());
+    assert_eq!(ld["@graph"][0]["potentialAction"]["@type"], "SearchAction");
+
+    assert!(json_ld(&ParsedArticleAnalyzed::default(), "de", "themen", &meta).unwrap().is_empty());
+
+    let ld = check("author/lefebvre", &ParsedArticleAnalyzed::default());
+    assert_eq!(ld["@graph"][0]["@type"], "ProfilePage");
+    assert_eq!(ld["@graph"][0]["mainEntity"]["birthDate"], "1905-11-29");
+
+    // the validator rejects unknown properties and wrong value types
+    let bad = json!({"@type": "Person", "name": "x", "headline": "y"});
+    assert!(validate(&schema, &bad, &["Thing"], "").is_err());
+    let bad = json!({"@type": "Article", "headline": "x", "datePublished": "04.02.2025"});
+    assert!(validate(&schema, &bad, &["Thing"], "").is_err());
+}
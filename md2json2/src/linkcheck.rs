@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::get_root_href;
+use crate::redirects::Redirects;
+
+/// Internal link that doesn't resolve to a file in /dist
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BrokenLink {
+    // path of the target, without root href and #fragment
+    pub target: String,
+    // pages (relative to /dist) that contain the link
+    pub pages: BTreeSet<String>,
+}
+
+/// Links to an old path that should be updated to the new one
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RedirectedLink {
+    pub target: String,
+    pub redirect: String,
+    pub pages: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkReport {
+    pub broken: Vec<BrokenLink>,
+    pub redirected: Vec<RedirectedLink>,
+}
+
+/// Checks all internal href="..." links of the html files in /dist
+///
+/// Links to /static aren't checked, they point to the fingerprinted
+/// copies of the asset pipeline (see assets.rs)
+pub fn check_links(dist: &Path, redirects: &Redirects) -> LinkReport {
+    let root_href = get_root_href();
+    let mut targets = BTreeMap::<String, BTreeSet<String>>::new();
+
+    for entry in walkdir::WalkDir::new(dist).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("html") {
+            continue;
+        }
+        let Ok(html) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        let page = path
+            .strip_prefix(dist)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        for href in get_hrefs(&html) {
+            let Some(target) = internal_path(href, root_href) else {
+                continue;
+            };
+            targets.entry(target).or_default().insert(page.clone());
+        }
+    }
+
+    let mut report = LinkReport::default();
+    for (target, pages) in targets {
+        if let Some(redirect) = redirects.map.get(&target) {
+            report.redirected.push(RedirectedLink {
+                target,
+                redirect: redirect.clone(),
+                pages,
+            });
+        } else if !exists(dist, &target) {
+            report.broken.push(BrokenLink { target, pages });
+        }
+    }
+
+    report
+}
+
+// values of href=, quoted or unquoted (minified html)
+fn get_hrefs(html: &str) -> Vec<&str> {
+    let mut hrefs = Vec::new();
+    let mut rest = html;
+    while let Some(pos) = rest.find("href=") {
+        rest = &rest[pos + 5..];
+        let (value, len) = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => {
+                let end = rest[1..].find(q).unwrap_or(rest.len() - 1);
+                (&rest[1..end + 1], end + 1)
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        hrefs.push(value);
+        rest = &rest[len..];
+    }
+    hrefs
+}
+
+// "/de/angelus" for "https://dubia.cc/de/angelus#x" or "/de/angelus?q=1",
+// None for external links and links into /static
+fn internal_path(href: &str, root_href: &str) -> Option<String> {
+    let path = match href.strip_prefix(root_href) {
+        Some(p) => p,
+        None if href.starts_with('/') && !href.starts_with("//") => href,
+        None => return None,
+    };
+    let path = path.split(['#', '?']).next().unwrap_or_default();
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        p => p,
+    };
+    if !path.starts_with('/') || path.starts_with("/static/") || path.contains("$$") || path.contains('{') {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+fn exists(dist: &Path, target: &str) -> bool {
+    let rel = target.trim_start_matches('/');
+    if rel.is_empty() {
+        return dist.join("index.html").is_file();
+    }
+    dist.join(rel).is_file()
+        || dist.join(format!("{rel}.html")).is_file()
+        || dist.join(rel).join("index.html").is_file()
+}
+
+#[test]
+fn test_get_hrefs() {
+    let html = "<a href='/de/a#x'>a</a><a href=\"https://dubia.cc/de/b\">b</a><link href=/de/c.atom rel=alternate>";
+    let hrefs = get_hrefs(html);
+    assert_eq!(hrefs, ["/de/a#x", "https://dubia.cc/de/b", "/de/c.atom"]);
+    let paths = hrefs.iter().filter_map(|h| internal_path(h, "https://dubia.cc")).collect::<Vec<_>>();
+    assert_eq!(paths, ["/de/a", "/de/b", "/de/c.atom"]);
+    assert_eq!(internal_path("https://example.com/de/a", "https://dubia.cc"), None);
+    assert_eq!(internal_path("/static/css/main.css", "https://dubia.cc"), None);
+}
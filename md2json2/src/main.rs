@@ -1,6 +1,8 @@
 use rosary::RosaryMysteries;
 use rosary::RosaryTemplates;
 use docs::AnalyzedDocuments;
+use citation::{BibEntry, BibSources, CitationStyle};
+use search::{FullTextIndex, FullTextManifest};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
@@ -10,15 +12,36 @@ mod langtrain;
 mod resistance;
 mod rosary;
 mod docs;
+mod citation;
+mod search;
+mod feed;
+mod sitemap;
+mod jsonld;
+mod redirects;
+mod linkcheck;
+mod fallback;
+mod epub;
+mod pdf;
+mod api;
+mod audio;
+mod narration;
+mod fmt;
+mod precache;
+mod assets;
+mod css;
+mod fonts;
+mod initials;
 
 #[derive(Debug, Default)]
 struct LoadedArticles {
     langs: BTreeMap<Lang, BTreeMap<Slug, String>>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum ArticleType {
     Question,
+    #[default]
     Tract,
     Prayer,
 }
@@ -41,9 +64,11 @@ struct AnalyzedArticles {
 }
 
 impl AnalyzedArticles {
-    pub fn get_chars(&self) -> BTreeSet<char> {
+    /// All characters of the articles in `lang`, see fonts::WebFonts
+    pub fn get_chars(&self, lang: &str) -> BTreeSet<char> {
         self.map
-            .values()
+            .get(lang)
+            .iter()
             .flat_map(|v| v.values().flat_map(|p| p.get_chars()))
             .collect()
     }
@@ -57,6 +82,10 @@ struct ParsedArticle {
     tags: Vec<String>,
     // map: lang => article slug
     translations: BTreeMap<String, String>,
+    // old slugs of this article
+    aliases: Vec<String>,
+    offline_audio: bool,
+    initials: Option<String>,
     authors: Vec<String>,
     sha256: String,
     img: Option<Image>,
@@ -113,7 +142,7 @@ impl ParsedArticle {
 }
 
 impl VectorizedArticles {
-    pub fn analyze(&self) -> AnalyzedArticles {
+    pub fn analyze(&self, bib: &BibSources, audio: &audio::AudioFiles) -> AnalyzedArticles {
         AnalyzedArticles {
             map: self
                 .map
@@ -161,6 +190,9 @@ impl VectorizedArticles {
                                         date: vectorized.parsed.date.clone(),
                                         tags: vectorized.parsed.tags.clone(),
                                         authors: vectorized.parsed.authors.clone(),
+                                        translations: vectorized.parsed.translations.clone(),
+                                        aliases: vectorized.parsed.aliases.clone(),
+                                        atype: vectorized.atype,
                                         sha256: vectorized.parsed.sha256.clone(),
                                         img: vectorized.parsed.img.clone(),
                                         subtitle: vectorized.parsed.summary.clone(),
@@ -168,8 +200,19 @@ impl VectorizedArticles {
                                         sections: vectorized.parsed.sections.clone(),
                                         similar: similar,
                                         backlinks: backlinks,
-                                        bibliography: vectorized.parsed.get_bibliography(),
+                                        bibliography: citation::resolve_bibliography(
+                                            &vectorized.parsed.get_bibliography(),
+                                            bib.get_article(lang, slug),
+                                            &bib.site,
+                                        ),
                                         footnotes: vectorized.parsed.footnotes.clone(),
+                                        audio: audio.get_article(
+                                            lang,
+                                            slug,
+                                            &vectorized.parsed.sections,
+                                            vectorized.parsed.offline_audio,
+                                        ),
+                                        initials: vectorized.parsed.initials.clone(),
                                     },
                                 )
                             })
@@ -203,6 +246,10 @@ fn normalize_char_to_string(c: char) -> String {
         'ß' => "ss".to_string(),
         'Æ' | 'æ' => "ae".to_string(),
         'Œ' | 'œ' => "oe".to_string(),
+        'Ł' | 'ł' => "l".to_string(),
+        'Ø' | 'ø' => "o".to_string(),
+        'Đ' | 'đ' => "d".to_string(),
+        'ı' => "i".to_string(),
         _ => c.nfd()
             .find(|&ch| ch.is_ascii() && ch.is_alphabetic())
             .map(|ch| ch.to_string())
@@ -210,6 +257,25 @@ fn normalize_char_to_string(c: char) -> String {
     }
 }
 
+/// `normalize_char_to_string` for whole words: additionally removes the
+/// diacritics of non-Latin scripts ("ἁγία" => "αγια"), used for searching
+fn normalize_str_to_string(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| normalize_char_to_string(c).chars().collect::<Vec<_>>())
+        .flat_map(|c| {
+            let d = c.nfd().collect::<Vec<_>>();
+            if d.iter().any(|q| unicode_normalization::char::is_combining_mark(*q)) {
+                d.into_iter()
+                    .filter(|q| !unicode_normalization::char::is_combining_mark(*q))
+                    .collect()
+            } else {
+                vec![c]
+            }
+        })
+        .map(|c| if c == 'ς' { 'σ' } else { c })
+        .collect()
+}
+
 fn nfc(s: &str) -> String {
     let mut chars = s.chars();
     let first = match chars.next() {
@@ -230,6 +296,15 @@ struct ParsedArticleAnalyzed {
     tags: Vec<String>,
     // authors
     authors: Vec<String>,
+    // map: lang => slug of the translated page
+    #[serde(default)]
+    translations: BTreeMap<String, String>,
+    // old slugs ("alter-name") or paths ("/de/alter-name") that redirect here
+    #[serde(default)]
+    aliases: Vec<String>,
+    // question, tract or prayer
+    #[serde(default)]
+    atype: ArticleType,
     // sha256 hash of the article contents
     sha256: String,
     // social media preview image
@@ -244,10 +319,16 @@ struct ParsedArticleAnalyzed {
     similar: Vec<SectionLink>,
     // Articles that link to this link
     backlinks: Vec<SectionLink>,
-    // all links used in this page
-    bibliography: Vec<Link>,
+    // all sources cited in this page
+    bibliography: Vec<BibEntry>,
     // footnote annotations
     footnotes: Vec<Footnote>,
+    // narration next to the index.md, see audio::AudioFiles
+    #[serde(default)]
+    audio: Option<audio::Audio>,
+    // font of the dropcap from the article config, see initials::Initials
+    #[serde(default)]
+    initials: Option<String>,
 }
 
 #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
@@ -333,13 +414,36 @@ impl ParsedArticleAnalyzed {
         c.extend(self.footnotes.iter().flat_map(|s| s.get_chars()));
         c
     }
+    // returns (year, month, day), dates are either yyyy-mm-dd or dd-mm-yyyy
     pub fn get_date(&self) -> Option<(&str, &str, &str)> {
         let mut iter = self.date.split("-");
         let year = iter.next()?;
         let month = iter.next()?;
         let day = iter.next()?;
+        if year.len() <= 2 && day.len() == 4 {
+            return Some((day, month, year));
+        }
         Some((year, month, day))
     }
+
+    // yyyy-mm-dd or an empty string
+    pub fn get_iso_date(&self) -> String {
+        self.get_date()
+            .map(|(y, m, d)| format!("{y}-{m}-{d}"))
+            .unwrap_or_default()
+    }
+}
+
+#[test]
+fn test_get_date() {
+    let a = |date: &str| ParsedArticleAnalyzed { date: date.to_string(), ..Default::default() };
+    assert_eq!(a("2024-03-05").get_date(), Some(("2024", "03", "05")));
+    assert_eq!(a("05-03-2024").get_date(), Some(("2024", "03", "05")));
+    assert_eq!(a("2024").get_date(), None);
+    assert_eq!(a("").get_date(), None);
+    assert_eq!(a("05-03-2024").get_iso_date(), "2024-03-05");
+    assert_eq!(a("2024-03-05").get_iso_date(), "2024-03-05");
+    assert_eq!(a("").get_iso_date(), "");
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -352,6 +456,14 @@ struct Config {
     authors: Vec<String>,
     #[serde(default)]
     translations: BTreeMap<String, String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    // precache the narration audio in the service worker
+    #[serde(default, rename = "offline-audio")]
+    offline_audio: bool,
+    // font of the dropcap ("yinit", "none"), see config/initials.json
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    initials: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -522,13 +634,13 @@ fn parse_image_align(s: &str) -> Option<ImageAlignment> {
         let chars = s
             .trim()
             .chars()
-            .skip(ar.len())
+            .skip(al.len())
             .take_while(|c| c.is_ascii_alphanumeric() && c.is_numeric())
             .collect::<String>();
 
         let width = chars.parse::<usize>().ok()?;
 
-        Some(ImageAlignment::Right(width))
+        Some(ImageAlignment::Left(width))
     } else if s.trim().starts_with(ar) {
         let chars = s
             .trim()
@@ -626,11 +738,11 @@ fn parse_paragraphs(s: &str) -> Vec<Paragraph> {
 }
 
 #[cfg(feature = "external")]
-fn sha256(s: &str) -> String {
+fn sha256(s: impl AsRef<[u8]>) -> String {
     use base64::Engine;
     use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
-    hasher.update(s.as_bytes());
+    hasher.update(s.as_ref());
     let result = hasher.finalize();
     base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(result)
 }
@@ -683,7 +795,7 @@ fn parse_article(s: &str) -> ParsedArticle {
         .next()
         .unwrap_or((0, String::new()));
 
-    let sha256 = sha256(&s);
+    let sha256 = sha256(s);
 
     let (config, lines_to_ignore) = extract_config(&lines);
 
@@ -771,6 +883,9 @@ fn parse_article(s: &str) -> ParsedArticle {
         date: config.date,
         tags: config.tags,
         translations: config.translations,
+        aliases: config.aliases,
+        offline_audio: config.offline_audio,
+        initials: config.initials,
         authors: config.authors,
         sha256: sha256,
         img: None,
@@ -1279,9 +1394,9 @@ impl Image {
 
         let href = rest.split_whitespace().nth(0)?.to_string();
         let title = rest
-            .split_whitespace()
-            .nth(1)
-            .map(|s| s.trim().replace("\"", "").replace("'", "").replace("`", ""))
+            .trim()
+            .split_once(char::is_whitespace)
+            .map(|(_, t)| t.trim().replace("\"", "").replace("'", "").replace("`", ""))
             .unwrap_or(alt.clone());
 
         Some(Self {
@@ -1317,6 +1432,17 @@ fn test_image() {
         })
     );
 
+    let s = "![alt :: align-left(250)](Isolated.png \"Long title\")";
+    assert_eq!(
+        Image::new(s),
+        Some(Image {
+            href: "Isolated.png".to_string(),
+            alt: "alt".to_string(),
+            title: "Long title".to_string(),
+            inline: Some(ImageAlignment::Left(250)),
+        })
+    );
+
     let s = "![Test)";
     assert_eq!(Image::new(s), None);
 }
@@ -1489,243 +1615,86 @@ fn get_root_href() -> &'static str {
     }
 }
 
-fn gen_serviceworker_js(cwd: &Path, articles: &AnalyzedArticles, meta: &MetaJson) -> String {
+fn gen_serviceworker_js(
+    cwd: &Path,
+    articles: &AnalyzedArticles,
+    precache: &precache::Precache,
+    docs_paths: &BTreeMap<String, String>,
+) -> String {
+    let langs = serde_json::to_string(&articles.map.keys().collect::<Vec<_>>()).unwrap_or_default();
     let mut s = include_str!("../../templates/sw.js").to_string();
-    s = s.replace(
-        "workbox.precaching.precacheAndRoute([]);",
-        &gen_sw_paths(cwd, articles, meta),
-    );
-    s = s.replace(
-        "importScripts('/static/js/workbox-sw.js');",
-        include_str!("../../static/js/workbox-sw.js"),
-    );
-    s
+    s = s.replace("workbox.precaching.precacheAndRoute([]);", &precache.to_js());
+    s += &include_str!("../../templates/sw-runtime.js")
+        .replace("$$LANGS$$", &serde_json::to_string(&docs_paths.keys().collect::<Vec<_>>()).unwrap_or_default())
+        .replace("$$OFFLINE_REVISIONS$$", &precache.revisions_json())
+        .replace("$$DOCS_PATHS$$", &serde_json::to_string(docs_paths).unwrap_or_default());
+    s += &include_str!("../../templates/sw-offline.js").replace("$$LANGS$$", &langs);
+    // only the workbox modules that the script above uses
+    let workbox = precache::workbox_js(cwd, include_str!("../../static/js/workbox-sw.js"), &s);
+    s.replace("importScripts('/static/js/workbox-sw.js');", &workbox)
 }
 
-fn gen_sw_paths(cwd: &Path, articles: &AnalyzedArticles, meta: &MetaJson) -> String {
-    let mut site_revision = Vec::new();
-    let mut a = articles
-        .map
-        .iter()
-        .flat_map(|(lang, v)| {
-            let mut q = v
-                .iter()
-                .map(move |(slug, a)| {
-                    format!(
-                        "    {{ url: '/{lang}/{slug}.html', revision: '{}' }}",
-                        a.sha256
-                    )
-                })
-                .collect::<Vec<_>>();
-
-            q.extend(v.iter().map(|(slug, a)| {
-                site_revision.push(a.sha256.clone());
-                format!(
-                    "    {{ url: '/articles/{lang}/{slug}/index.md', revision: '{}' }}",
-                    a.sha256
-                )
-            }));
-
-            q
-        })
-        .collect::<Vec<_>>();
-
-    let site_revision = sha256(&site_revision.join(" "));
-    for l in articles.map.keys() {
-        a.push(format!(
-            "    {{ url: '/{l}.html', revision: '{site_revision}' }}"
-        ));
-        a.push(format!(
-            "    {{ url: '/{l}/search.js', revision: '{site_revision}' }}"
-        ));
-        a.push(format!(
-            "    {{ url: '/{l}/search.html', revision: '{site_revision}' }}"
-        ));
-        a.push(format!(
-            "    {{ url: '/{l}/index.json', revision: '{site_revision}' }}"
-        ));
-    }
-
-    for author in meta.authors.keys() {
-        for lang in articles.map.keys() {
-            let q = author.replace(":", "-");
-            let meta_v = sha256(&serde_json::to_string(&meta).unwrap_or_default());
-            a.push(format!(
-                "    {{ url: '/{lang}/author/{q}.html', revision: '{meta_v}' }}"
-            ));
-        }
+#[test]
+fn test_serviceworker_js() {
+    use precache::{OfflineManifest, Precache, PrecacheEntry};
+
+    let entry = |url: &str, revision: &str| PrecacheEntry { url: url.to_string(), revision: revision.to_string(), bytes: 10 };
+    let mut articles = AnalyzedArticles::default();
+    articles.map.insert("de".to_string(), BTreeMap::new());
+    articles.map.insert("pl".to_string(), BTreeMap::new());
+    let precache = Precache {
+        entries: vec![entry("/static/js/head.0123abcd.js", "r1")],
+        languages: [
+            ("de".to_string(), OfflineManifest::new("de", "site", vec![entry("/de/index.html", "r2")])),
+            ("pl".to_string(), OfflineManifest::new("pl", "site", vec![entry("/pl/index.html", "r3")])),
+        ]
+        .into_iter()
+        .collect(),
+        collections: vec![OfflineManifest::new("de", "docs", vec![entry("/de/dok/hesse/papst.html", "r4")])],
+        budget_kb: None,
+    };
+    let docs_paths = [("de", "dok"), ("pl", "dokumenty")]
+        .into_iter()
+        .map(|(l, p)| (l.to_string(), p.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let sw = gen_serviceworker_js(&cwd, &articles, &precache, &docs_paths);
+    assert!(!sw.contains("$$"));
+    // shared files on install, the pages of each language later from its manifest
+    assert!(sw.contains("{ url: '/static/js/head.0123abcd.js', revision: 'r1' }"));
+    assert!(!sw.contains("/de/index.html"));
+    assert!(sw.contains(r#"const offlineLangs = ["de","pl"];"#));
+    assert!(sw.contains(r#"const docsPaths = {"de":"dok","pl":"dokumenty"};"#));
+    for m in precache.manifests() {
+        assert!(sw.contains(&format!(r#""{}/{}":"{}""#, m.lang, m.collection, m.revision)));
     }
 
-    for lang in articles.map.keys() {
-        for sp in get_special_pages(
-            lang,
-            meta,
-            &ArticlesByTag::default(),
-            &AnalyzedDocuments::default(),
-        )
-        .unwrap_or_default()
-        {
-            a.push(format!(
-                "    {{ url: '/{lang}/{}', revision: '{site_revision}' }}",
-                sp.filepath
-            ));
-        }
+    // only the workbox modules that the service worker uses
+    let modules = precache::workbox_js(Path::new("/nonexistent"), "", &sw);
+    for m in ["core", "precaching", "routing", "strategies", "cache-expiration", "cacheable-response"] {
+        assert!(modules.contains(&format!("workbox.loadModule('workbox-{m}');")), "{m}");
     }
-
-    // add all images from articles
-    let l = walkdir::WalkDir::new(cwd.join("articles"))
-        .max_depth(5)
-        .into_iter()
-        .filter_map(|entry| {
-            let entry = entry.map_err(|e| e.to_string()).ok()?;
-            let entry = entry.path();
-            let ext = entry.extension().and_then(|s| s.to_str());
-            let fname = entry.file_name()?.to_str()?.to_string();
-            let last_modified = format!("{:?}", entry.metadata().ok()?.modified().ok()?)
-                .chars()
-                .filter(|c| c.is_numeric())
-                .collect::<String>();
-
-            if ext == Some(".avif") || ext == Some("avif") {
-                let slug = entry.parent()?;
-                let lang = slug.parent()?;
-                let slug = slug.file_name()?.to_str()?.to_string();
-                let lang = lang.file_name()?.to_str()?.to_string();
-                Some((lang, slug, fname, last_modified))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
-
-    // /index.html
-    a.push(format!(
-        "    {{ url: '/index.html', revision: '{}' }}",
-        sha256(include_str!("../../index.html"))
-    ));
-
-    a.push(format!(
-        "    {{ url: '/static/js/head2.js', revision: '{}' }}",
-        sha256(include_str!("../../static/js/head2.js"))
-    ));
-
-    // author pages
-    // special pages
-
-    // fonts
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-Semibold.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-Bold.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-RegularItalic.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-Regular.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-BoldItalic.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssp/SourceSansPro-BASIC-SemiboldItalic.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-Regular.woff2', revision: '1' }}"
-    ));
-    a.push(format!("    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-RegularItalic.woff2', revision: '1' }}"));
-    a.push(format!(
-        "    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-Bold.woff2', revision: '1' }}"
-    ));
-    a.push(format!("    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-SemiboldItalic.woff2', revision: '1' }}"));
-    a.push(format!(
-        "    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-Semibold.woff2', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/font/ssfp/SourceSerifPro-BASIC-BoldItalic.woff2', revision: '1' }}"
-    ));
-
-    for i in 'A'..'Z' {
-        a.push(format!(
-            "    {{ url: '/static/font/kanzlei/Kanzlei-Initialen-{i}.ttf', revision: '1' }}",
-        )); // never changes
+    for m in ["background-sync", "broadcast-cache-update", "google-analytics", "range-requests", "streams"] {
+        assert!(!modules.contains(&format!("workbox-{m}")), "{m}");
+        assert!(!sw.contains(&format!("workbox-{m}")), "{m}");
     }
 
-    a.push(format!(
-        "    {{ url: '/static/js/search.js', revision: '{}' }}",
-        sha256(include_str!("../../static/js/search.js"))
-    ));
-    a.push(format!(
-        "    {{ url: '/static/js/head.js', revision: '{}' }}",
-        sha256(include_str!("../../static/js/head.js"))
-    ));
-    a.push(format!(
-        "    {{ url: '/static/img/logo/logo-smooth.svg', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/img/watercolor.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/death.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/icon/icons.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/books.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/donation.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/mensfashion.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/womansfashion.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/rosary.avif', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/ornament/asterism-triplewhitestar.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/ornament/sun-verginasun-black.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!("    {{ url: '/static/img/ornament/japanesecrest-tsukinihoshi-dottedmoon.svg', revision: '1' }}", )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/ornament/sequential-nav-icons-arabesque.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/ornament/three-wavy-lines-ornament-right.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/ornament/three-wavy-lines-ornament-left.svg', revision: '1' }}",
-    )); // never changes
-    a.push(format!(
-        "    {{ url: '/static/img/shop/rosary.avif', revision: '1' }}",
-    )); // never changes
-
-    a.push(format!(
-        "    {{ url: '/static/img/logo/logo-sm-32.avif', revision: '1' }}"
-    ));
-    a.push(format!(
-        "    {{ url: '/static/img/logo/logo-sm-dark-32.avif', revision: '1' }}"
-    ));
-    a.push(format!("    {{ url: '/death.html', revision: '1' }}"));
-    a.push(format!("    {{ url: '/manifest.json', revision: '1' }}"));
-
-    for (lang, slug, image, rev) in l {
-        a.push(format!(
-            "    {{ url: '/{lang}/{slug}/{image}', revision: '{rev}' }}"
-        ));
+    // the offline page of setCatchHandler is precached on install, the
+    // other pages of the language go into its manifest
+    let dir = std::env::temp_dir().join("md2json2-test-sw");
+    let _ = std::fs::remove_dir_all(&dir);
+    for f in ["dist/de/offline.html", "dist/de/index.html"] {
+        std::fs::create_dir_all(dir.join(f).parent().unwrap()).unwrap();
+        std::fs::write(dir.join(f), f).unwrap();
     }
-
-    format!(
-        "workbox.precaching.precacheAndRoute([\r\n{}\r\n]);",
-        a.join(",\r\n")
-    )
+    let config = precache::PrecacheJson::load(&cwd.join("config").join("precache.json")).unwrap();
+    let precache = Precache::collect(&dir, &config, &[], &docs_paths, &assets::Assets::default());
+    let _ = std::fs::remove_dir_all(&dir);
+    let sw = gen_serviceworker_js(&cwd, &articles, &precache, &docs_paths);
+    assert!(sw.contains("{ url: '/de/offline.html', revision: '"));
+    assert!(!sw.contains("/de/index.html"));
+    assert_eq!(precache.languages["de"].entries.iter().map(|e| e.url.as_str()).collect::<Vec<_>>(), ["/de/index.html"]);
 }
 
 fn generate_gitignore(articles: &LoadedArticles, meta: &MetaJson) -> String {
@@ -1741,6 +1710,7 @@ fn generate_gitignore(articles: &LoadedArticles, meta: &MetaJson) -> String {
         filenames.insert(format!("{lang}.html"));
     }
     filenames.insert("/dist".into());
+    filenames.insert("/.cache".into());
     filenames.insert("/venv".into());
     filenames.insert("*.md.json".into());
     filenames.insert("sw.js".into());
@@ -1843,125 +1813,11 @@ fn get_description(
     get_string(meta, lang, "index-desc")
 }
 
-fn generate_dropcap_css(a: &ParsedArticleAnalyzed) -> String {
-    if a.is_prayer() {
-        return String::new();
-    }
-
-    let try1 = a.summary.get(0).map(par2text).unwrap_or_default();
-    let sec1 = a
-        .sections
-        .get(0)
-        .and_then(|s| s.pars.get(0))
-        .map(par2text)
-        .unwrap_or_default();
-    let mut c = None;
-    if !try1.trim().is_empty() {
-        c = try1.trim().chars().next()
-    } else if !sec1.trim().is_empty() {
-        c = sec1.trim().chars().next();
-    }
-
-    let c = match c {
-        Some(s) => {
-            if s.is_ascii_alphabetic() {
-                s.to_ascii_uppercase()
-            } else {
-                return String::new();
-            }
-        }
-        _ => return String::new(),
-    };
-
-    let dropcap_map = &[
-        ('A', "U+0041"),
-        ('B', "U+0042"),
-        ('C', "U+0043"),
-        ('D', "U+0044"),
-        ('E', "U+0045"),
-        ('F', "U+0046"),
-        ('G', "U+0047"),
-        ('H', "U+0048"),
-        ('I', "U+0049"),
-        ('J', "U+004A"),
-        ('K', "U+004B"),
-        ('L', "U+004C"),
-        ('M', "U+004D"),
-        ('N', "U+004E"),
-        ('O', "U+004F"),
-        ('P', "U+0050"),
-        ('Q', "U+0051"),
-        ('R', "U+0052"),
-        ('S', "U+0053"),
-        ('T', "U+0054"),
-        ('U', "U+0055"),
-        ('V', "U+0056"),
-        ('W', "U+0057"),
-        ('X', "U+0058"),
-        ('Y', "U+0059"),
-        ('Z', "U+005A"),
-    ];
-
-    let unicode_range = match dropcap_map.iter().find(|s| c == s.0).map(|q| q.1) {
-        Some(s) => s,
-        None => return String::new(),
-    };
-
-    let text = vec![
-        "@font-face {".to_string(),
-        "    font-family: 'Kanzlei Initialen';".to_string(),
-        format!(
-            "    src: url('/static/font/kanzlei/Kanzlei-Initialen-{c}.ttf') format('truetype');"
-        ),
-        "    font-display: swap;".to_string(),
-        format!("    unicode-range: {unicode_range};"),
-        "}".to_string(),
-    ];
-
-    text.join("\r\n")
-}
-
-fn strip_comments(s: &str) -> String {
-    let mut inside = false;
-    let chars = s.chars().collect::<Vec<_>>();
-    let mut c = Vec::new();
-    let mut i = chars.iter().peekable();
-    while let Some(a) = i.next() {
-        if *a == '/' {
-            if i.peek().copied().copied() == Some('*') {
-                let _ = i.next();
-                inside = true;
-                continue;
-            }
-        } else if *a == '*' {
-            if i.peek().copied().copied() == Some('/') {
-                let _ = i.next();
-                inside = false;
-                continue;
-            }
-        }
-
-        if !inside {
-            c.push(*a);
-        }
-    }
-    c.into_iter().collect()
-}
-
-fn minify_css(s: &str) -> String {
-    let s = strip_comments(s);
-    /*/
-    use minifier::css;
-    let s = match css::minify(&s) {
-        Ok(o) => o.to_string(),
-        Err(e) => {
-            println!("error cssmin: {e:?}");
-            let _ = std::fs::write("./output.css", &s);
-            s.to_string()
-        },
-    };
-     */
-    s
+fn generate_dropcap_css(a: &ParsedArticleAnalyzed, lang: &str, meta: &MetaJson) -> String {
+    meta.initials
+        .resolve(lang, a)
+        .map(|i| meta.initials.css(&i))
+        .unwrap_or_default()
 }
 
 pub fn get_special_page_link(lang: &str, page: &str, meta: &MetaJson) -> Result<String, String> {
@@ -1969,6 +1825,11 @@ pub fn get_special_page_link(lang: &str, page: &str, meta: &MetaJson) -> Result<
     Ok(format!("{}/{}", lang, path))
 }
 
+pub fn get_tag_page_link(lang: &str, tag: &str, meta: &MetaJson) -> Result<String, String> {
+    let path = get_string(meta, lang, "special-topics-path")?;
+    Ok(format!("{}/{}/{}", lang, path, tag))
+}
+
 fn get_string(meta: &MetaJson, lang: &str, key: &str) -> Result<String, String> {
     Ok(meta
         .strings
@@ -1979,42 +1840,143 @@ fn get_string(meta: &MetaJson, lang: &str, key: &str) -> Result<String, String>
         .clone())
 }
 
+// Highwire Press / Google Scholar <meta name="citation_*"> tags for articles and documents,
+// the generic tags of the page for special, tag and author pages
+fn citation_meta(a: &ParsedArticleAnalyzed, lang: &str, page_href: &str, meta: &MetaJson) -> String {
+    if a.sha256.is_empty() {
+        return [
+            "<meta name=\"citation_title\" content=\"$$TITLE$$\">".to_string(),
+            "<meta name=\"citation_author\" content=\"$$AUTHOR$$\">".to_string(),
+            "<meta name=\"citation_publication_date\" content=\"$$DATE$$\">".to_string(),
+            format!("<meta name=\"citation_fulltext_html_url\" content=\"{page_href}\">"),
+            "<meta name=\"citation_fulltext_world_readable\" content=\"\">".to_string(),
+        ]
+        .join("\r\n    ");
+    }
+
+    let e = BibEntry::for_page("", a, page_href, meta);
+    let mut tags = vec![("citation_title", e.title.clone())];
+    tags.extend(e.authors.iter().map(|s| ("citation_author", s.clone())));
+    if let Some((y, m, d)) = a.get_date() {
+        tags.push(("citation_publication_date", format!("{y}/{m}/{d}")));
+    }
+    tags.push(("citation_publisher", "dubia.cc".to_string()));
+    tags.push(("citation_language", lang.to_string()));
+    tags.extend(a.tags.iter().map(|s| ("citation_keywords", s.clone())));
+    tags.push(("citation_fulltext_html_url", page_href.to_string()));
+    tags.push(("citation_fulltext_world_readable", String::new()));
+
+    tags.iter()
+        .map(|(k, v)| format!("<meta name=\"{k}\" content=\"{}\">", v.replace('"', "&quot;")))
+        .collect::<Vec<_>>()
+        .join("\r\n    ")
+}
+
+fn cite_this(
+    lang: &str,
+    id: &str,
+    a: &ParsedArticleAnalyzed,
+    page_href: &str,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    let e = BibEntry::for_page(id, a, page_href, meta);
+
+    let mut s = include_str!("../../templates/cite.html").to_string();
+    s = s.replace("$$CITE_TITLE$$", &get_string(meta, lang, "cite-this-title")?);
+    s = s.replace("$$CITE_DESC$$", &get_string(meta, lang, "cite-this-desc")?);
+    s = s.replace("$$CITE_APA$$", &e.format(CitationStyle::Apa));
+    s = s.replace("$$CITE_CHICAGO$$", &e.format(CitationStyle::Chicago));
+    s = s.replace("$$CITE_MLA$$", &e.format(CitationStyle::Mla));
+    s = s.replace("$$CITE_BIBTEX$$", &citation::html_escape(&e.to_bibtex()));
+    Ok(s)
+}
+
+#[test]
+fn test_citation_meta() {
+    let mut meta = MetaJson::default();
+    meta.strings.insert(
+        "de".to_string(),
+        [("cite-this-title", "Zitieren"), ("cite-this-desc", "Zitiervorschlag")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    );
+    let href = "https://dubia.cc/de/tugend";
+    let a = ParsedArticleAnalyzed {
+        title: "Tugend \"und\" Laster".to_string(),
+        authors: vec!["hesse".to_string()],
+        date: "2024-03-05".to_string(),
+        tags: vec!["moral".to_string()],
+        sha256: "0123456789abcdef".to_string(),
+        ..Default::default()
+    };
+
+    let tags = citation_meta(&a, "de", href, &meta);
+    assert!(tags.contains("<meta name=\"citation_title\" content=\"Tugend &quot;und&quot; Laster\">"));
+    assert!(tags.contains("<meta name=\"citation_author\" content=\"hesse\">"));
+    assert!(tags.contains("<meta name=\"citation_publication_date\" content=\"2024/03/05\">"));
+    assert!(tags.contains("<meta name=\"citation_keywords\" content=\"moral\">"));
+    assert!(tags.contains(&format!("<meta name=\"citation_fulltext_html_url\" content=\"{href}\">")));
+
+    // special pages keep the generic tags, filled in by head()
+    let special = citation_meta(&ParsedArticleAnalyzed::default(), "de", href, &meta);
+    assert!(special.contains("<meta name=\"citation_title\" content=\"$$TITLE$$\">"));
+    assert!(special.contains(&format!("<meta name=\"citation_fulltext_html_url\" content=\"{href}\">")));
+
+    let cite = cite_this("de", "tugend", &a, href, &meta).unwrap();
+    assert!(cite.contains("Zitieren") && cite.contains("Zitiervorschlag"));
+    assert!(cite.contains(&format!("<a href='{href}'>")));
+    assert!(cite.contains("Version 0123456789ab"));
+    assert!(!cite.contains("$$CITE_"));
+}
+
+// page_path: path of the page relative to /{lang}, without .html (empty for the index page)
 fn head(
     a: &ParsedArticleAnalyzed,
     lang: &str,
     title_id: &str,
+    page_path: &str,
     meta: &MetaJson,
 ) -> Result<String, String> {
     let license = include_str!("../../templates/license.html");
     let darklight = include_str!("../../templates/darklight.html");
-    let head_css = include_str!("../../static/css/head2.css").to_string();
-    let toc = include_str!("../../static/css/TOC.css");
-    let page_toolbar = include_str!("../../static/css/PAGE_TOOLBAR.css");
-    let img_css = include_str!("../../static/css/FIGURE.css");
-    let floating_header = include_str!("../../static/css/FLOATING_HEADER.css");
     let noscript_style = include_str!("../../static/css/noscript.css");
-    let footnotes = if a.footnotes.is_empty() {
-        String::new()
-    } else {
-        include_str!("../../static/css/FOOTNOTE.css").to_string()
-    };
 
-    let final_css = head_css + page_toolbar + toc + img_css + floating_header + &footnotes;
-    let critical_css = minify_css(&final_css);
-    let critical_css_2 = "<style id='critical-css'>".to_string() + &critical_css + "    </style>";
+    // the full stylesheet is inlined here and pruned to the rules of the page
+    // once the page is written, see css::split_critical_css
+    let critical_css_2 = "<style id='critical-css'>".to_string() + css::site_css() + "    </style>";
+    // without scripts the inlined rules already cover every element of the page
+    let site_css = format!(
+        "<link rel='stylesheet' href='{}' media='print' onload=\"this.media='all'\">",
+        css::SITE_CSS_URL
+    );
 
     let title = get_title(lang, a, meta)?;
     let description = get_description(lang, a, meta)?.replace("\"", "'");
     let og_description = description.replace("<p>", "").replace("</p>", "").replace("&lt;p&gt;", "").replace("&lt;/p&gt;", "");
 
-    let drc = format!("<style>{}</style>", generate_dropcap_css(a));
-    let page_href = get_root_href().to_string() + "/" + lang + "/" + title_id;
+    let drc = format!("<style>{}</style>", generate_dropcap_css(a, lang, meta));
+    let page_href = if page_path.is_empty() {
+        get_root_href().to_string() + "/" + lang
+    } else {
+        get_root_href().to_string() + "/" + lang + "/" + page_path
+    };
 
     let mut head = include_str!("../../templates/head.html").to_string();
     head = head.replace("<!-- LICENSE_FULL -->", license);
     head = head.replace("<!-- DARKLIGHT_STYLES -->", &darklight);
     head = head.replace("<!-- CRITICAL_CSS -->", &critical_css_2);
+    head = head.replace("<!-- SITE_CSS -->", &site_css);
+    let (font_preload, font_css) = meta
+        .fonts
+        .head_html(lang)
+        .unwrap_or_else(|| (include_str!("../../templates/font-preload.html").trim().to_string(), String::new()));
+    head = head.replace("<!-- FONT_PRELOAD -->", &font_preload);
+    head = head.replace("<!-- FONT_CSS -->", &font_css);
     head = head.replace("<!-- DROPCAP_CSS -->", &drc);
+    head = head.replace("<!-- CITATION_META -->", &citation_meta(a, lang, &page_href, meta));
+    head = head.replace("<!-- FEED_LINKS -->", &feed::feed_links(a, lang, page_path, meta)?);
+    head = head.replace("<!-- JSON_LD -->", &jsonld::json_ld(a, lang, page_path, meta)?);
     head = head.replace(
         "<!-- NOSCRIPT -->",
         &format!("<style>{}</style>", noscript_style),
@@ -2043,7 +2005,7 @@ fn head(
         &get_string(meta, lang, "page-smc")?,
     );
     head = head.replace("$$CONTACT_URL$$", &get_special_page_link(lang, "about", meta)?);
-    head = head.replace("$$SLUG$$", title_id);
+    head = head.replace("$$SLUG$$", page_path);
 
     Ok(head)
 }
@@ -2086,7 +2048,6 @@ fn link_tags(lang: &str, tags: &[String], meta: &MetaJson) -> Result<String, Str
     let root_href = get_root_href();
 
     let t_descr_string = get_string(meta, lang, "link-tags-descr")?;
-    let t_url = get_special_page_link(lang, "topics", meta)?;
     
     // Get the tag display names mapping for this language
     let tag_display_names = meta
@@ -2104,11 +2065,12 @@ fn link_tags(lang: &str, tags: &[String], meta: &MetaJson) -> Result<String, Str
             .clone();
         
         let t_descr = t_descr_string.replace("$$TAG$$", &display_name);
-        let t1 = format!("<a href='{root_href}/{t_url}#{t}'");
+        let t_url = get_tag_page_link(lang, t, meta)?;
+        let t1 = format!("<a href='{root_href}/{t_url}' ");
         let t2 = "class='link-tag link-page link-annotated icon-not has-annotation spawns-popup' rel='tag' ";
         let t3 = format!(" data-attribute-title='{t_descr}'>{display_name}</a>");
-        t1 + t2 + &t3
-    }).collect::<Vec<_>>().join(", ");
+        Ok(t1 + t2 + &t3)
+    }).collect::<Result<Vec<_>, String>>()?.join(", ");
 
     Ok(format!(
         "<div class='link-tags' style='margin: 10px 0px;'><p>{tags_str}</p></div>"
@@ -2233,11 +2195,28 @@ struct Tags {
     ibelievein: Vec<IBelieveIn>,
     iwanttolearn: BTreeMap<Slug, IwantToLearn>,
     tags: BTreeMap<String, String>,
+    // optional description and header image of the /{lang}/{topics}/{tag} pages
+    #[serde(default, rename = "tag-pages")]
+    tag_pages: BTreeMap<String, TagPage>,
     ressources: Vec<TagSection1>,
     shop: Vec<TagSection2>,
     about: Vec<TagSection3>,
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct TagPage {
+    // paragraphs of the long description
+    #[serde(default)]
+    description: Vec<String>,
+    // header image, relative to the root
+    #[serde(default)]
+    img: Option<String>,
+    // the same tag in other languages, lang => tag ("en": "judaism"), for the
+    // hreflang links of the sitemap; tags with the same id are always grouped
+    #[serde(default)]
+    translations: BTreeMap<Lang, String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct IBelieveIn {
     title: String,
@@ -2284,6 +2263,35 @@ pub struct MetaJson {
     authors: BTreeMap<String, Author>,
     #[serde(default)]
     tags: BTreeMap<Lang, Tags>,
+    // citation style of the bibliography ("chicago-notes", "chicago", "apa", "mla")
+    #[serde(default, rename = "citation-style")]
+    citation_style: Option<String>,
+    // subsetted web fonts per language, built in main()
+    #[serde(skip)]
+    fonts: fonts::WebFonts,
+    // fonts of the dropcaps and the letters they have, built in main()
+    #[serde(skip)]
+    initials: initials::Initials,
+    // characters of the site that the PDF fonts print in every style, built in main()
+    #[serde(skip)]
+    pdf_chars: BTreeSet<char>,
+}
+
+impl MetaJson {
+    /// Characters of the translations, tags and authors on the pages of `lang`
+    pub fn get_chars(&self, lang: &str) -> BTreeSet<char> {
+        serde_json::to_string(&(self.strings.get(lang), self.tags.get(lang), &self.authors))
+            .unwrap_or_default()
+            .chars()
+            .collect()
+    }
+
+    pub fn get_citation_style(&self) -> CitationStyle {
+        self.citation_style
+            .as_deref()
+            .and_then(CitationStyle::new)
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -2293,6 +2301,44 @@ struct Author {
     contact: Option<String>,
     #[serde(default)]
     donate: BTreeMap<String, String>,
+    // biography paragraphs per language
+    #[serde(default)]
+    bio: BTreeMap<Lang, Vec<String>>,
+    // "Archbishop", "Priest", ... per language
+    #[serde(default)]
+    role: BTreeMap<Lang, String>,
+    // portrait image, relative to the root
+    #[serde(default)]
+    portrait: Option<String>,
+    // life dates (ISO 8601, "1905-11-29" or "1225")
+    #[serde(default)]
+    born: Option<String>,
+    #[serde(default)]
+    died: Option<String>,
+}
+
+impl Author {
+    // falls back to English, then to any other language
+    fn localized<'a, T>(map: &'a BTreeMap<Lang, T>, lang: &str) -> Option<&'a T> {
+        map.get(lang).or_else(|| map.get("en")).or_else(|| map.values().next())
+    }
+
+    pub fn get_bio(&self, lang: &str) -> &[String] {
+        Self::localized(&self.bio, lang).map(|v| v.as_slice()).unwrap_or_default()
+    }
+
+    pub fn get_role(&self, lang: &str) -> Option<&str> {
+        Self::localized(&self.role, lang).map(|s| s.as_str())
+    }
+
+    pub fn get_life_dates(&self) -> Option<String> {
+        match (self.born.as_deref(), self.died.as_deref()) {
+            (None, None) => None,
+            (Some(b), None) => Some(format!("* {b}")),
+            (None, Some(d)) => Some(format!("† {d}")),
+            (Some(b), Some(d)) => Some(format!("{b} – {d}")),
+        }
+    }
 }
 
 fn read_meta_json(s: &str) -> MetaJson {
@@ -2360,7 +2406,9 @@ pub fn add_translation_keys(
     Ok(meta)
 }
 
-fn page_metadata(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Result<String, String> {
+// epub: the e-book that contains this page, see epub::generate_ebooks()
+// page_path: path of the page relative to /{lang}, for the PDF links
+fn page_metadata(lang: &str, a: &ParsedArticleAnalyzed, epub: &epub::EbookKind, page_path: &str, meta: &MetaJson) -> Result<String, String> {
     if a.is_prayer() {
         return Ok(String::new());
     }
@@ -2372,7 +2420,7 @@ fn page_metadata(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Resu
 
     let authors_link = a.authors.iter().map(|s| {
 
-        let id = s.replace(":", "-");
+        let id = author_page_id(s);
         let name = meta.authors.get(s).map(|q| &q.displayname)
         .ok_or_else(|| format!("author {s} not found for article {}", a.title))?;
 
@@ -2417,6 +2465,26 @@ fn page_metadata(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Resu
         page_meta = page_meta.replace("<!-- BIBLIOGRAPHY_DOTTED -->", &bl);
     }
 
+    let epub_desc = match epub {
+        epub::EbookKind::Article(_) => get_string(meta, lang, "epub-desc")?,
+        epub::EbookKind::Tag(_) | epub::EbookKind::Collection(_) => get_string(meta, lang, "epub-collection-desc")?,
+    };
+    let mut e = include_str!("../../templates/page-metadata.epub.html").to_string();
+    e = e.replace("$$EPUB_HREF$$", &epub.url(lang));
+    e = e.replace("$$EPUB_DESC$$", &epub_desc);
+    e = e.replace("$$EPUB_TITLE$$", &get_string(meta, lang, "epub-title")?);
+    page_meta = page_meta.replace("<!-- EPUB_DOTTED -->", &e);
+
+    if pdf::printable(lang, a, meta) {
+        let mut p = include_str!("../../templates/page-metadata.pdf.html").to_string();
+        p = p.replace("$$PDF_A4_HREF$$", &pdf::PageSize::A4.url(lang, page_path));
+        p = p.replace("$$PDF_A5_HREF$$", &pdf::PageSize::A5.url(lang, page_path));
+        p = p.replace("$$PDF_DESC$$", &get_string(meta, lang, "pdf-desc")?);
+        p = p.replace("$$PDF_TITLE$$", &get_string(meta, lang, "pdf-title")?);
+        p = p.replace("$$PDF_A5$$", &get_string(meta, lang, "pdf-a5")?);
+        page_meta = page_meta.replace("<!-- PDF_DOTTED -->", &p);
+    }
+
     page_meta = page_meta.replace("<!-- AUTHORS -->", &authors_link);
 
     Ok(page_meta)
@@ -2549,27 +2617,40 @@ fn render_image(i: &Image) -> String {
         .replace("$$IMG_CAPTION$$", &i.title)
 }
 
-fn body_abstract(lang: &str, article_id: &str, is_prayer: bool, summary: &[Paragraph]) -> String {
+fn body_abstract(lang: &str, article_id: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> String {
     let mut target = String::new();
+    let summary = &a.summary;
 
     if summary.is_empty() {
         return target;
     }
 
     // body_abstract
+    let is_prayer = a.is_prayer();
     if !is_prayer {
         target += "<blockquote class='blockquote-level-1 block' style='display:flex;flex-direction:column;'>";
-        if let Some(first) = summary.get(0).and_then(|q| q.as_sentence()?.get(0)?.text()) {
-            let drc = first.chars().next().unwrap_or(' ');
-            let rest = first.chars().skip(1).collect::<String>();
-            target += "<p class='first-block first-graf intro-graf dropcap-kanzlei' style='--bsm: 0;display:inline;float:left;min-height:7em;'>";
-            target += &format!("<span class='dropcap'>{drc}</span>");
-            target += &rest;
-            target += "</p>";
+    }
+
+    let initial = meta.initials.resolve(lang, a);
+    if let (Some(initial), Some(first)) = (initial.as_ref(), initials::first_text(a)) {
+        let rest = first
+            .char_indices()
+            .nth(initial.preceding.chars().count() + 1)
+            .map(|(i, _)| &first[i..])
+            .unwrap_or_default();
+        target += &format!(
+            "<p class='first-block first-graf intro-graf {}' style='--bsm: 0;display:inline;float:left;min-height:7em;'>",
+            meta.initials.class(initial)
+        );
+        if !initial.preceding.is_empty() {
+            target += &format!("<span class='initial-preceding-punctuation'>{}</span>", initial.preceding);
         }
+        target += &format!("<span class='dropcap'>{}</span>", initial.letter);
+        target += rest;
+        target += "</p>";
     }
 
-    for par in summary.iter().skip(if is_prayer { 0 } else { 1 }) {
+    for par in summary.iter().skip(if initial.is_some() { 1 } else { 0 }) {
         target += &render_paragraph(lang, par, true, article_id);
     }
 
@@ -2584,6 +2665,7 @@ fn render_section(
     lang: &str,
     a: &ArticleSection,
     slug: &str,
+    audio: Option<&audio::Audio>,
     meta: &MetaJson,
 ) -> Result<String, String> {
     let mut section = include_str!("../../templates/section.html").to_string();
@@ -2612,6 +2694,11 @@ fn render_section(
     section = section.replace("$$SECTION_DESCR$$", &section_descr);
     section = section.replace("$$SECTION_TITLE$$", &header);
     section = section.replace("<!-- FIRST_PARAGRAPH -->", &first_par);
+    let section_audio = match audio {
+        Some(au) => audio::render_section_link(lang, au, &section_id, meta)?,
+        None => String::new(),
+    };
+    section = section.replace("<!-- SECTION_AUDIO -->", &section_audio);
 
     let with_clearfix = format!(
         "<div class='content-section' style='clear:both;'>{}{}</div>",
@@ -2626,11 +2713,12 @@ fn body_content(
     lang: &str,
     slug: &str,
     sections: &[ArticleSection],
+    audio: Option<&audio::Audio>,
     meta: &MetaJson,
 ) -> Result<String, String> {
     Ok(sections
         .iter()
-        .map(|q| render_section(lang, q, slug, meta))
+        .map(|q| render_section(lang, q, slug, audio, meta))
         .collect::<Result<Vec<_>, _>>()?
         .join("\r\n"))
 }
@@ -2664,7 +2752,7 @@ fn donate(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Result<Stri
         .map(|(id, a)| {
             format!(
                 "<a href='/{lang}/author/{}'>{}</a>",
-                id.replace(":", "-"),
+                author_page_id(id),
                 a.displayname
             )
         })
@@ -2734,7 +2822,7 @@ fn site_author_donation(lang: &str, meta: &MetaJson) -> Result<String, String> {
 
     let all_authors = format!(
         "<a href='/{lang}/author/{}'>{}</a>",
-        author_id.replace(":", "-"),
+        author_page_id(author_id),
         dn_author.displayname
     );
 
@@ -2798,58 +2886,44 @@ fn bibliography(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Resul
         return Ok(String::new());
     }
 
-    // Group links by href (URL)
-    let mut url_map: BTreeMap<String, Link> = BTreeMap::new();
-    
-    for link in &a.bibliography {
-        let url = link.href.clone();
-        
-        // If this URL already exists in our map, keep the one with longer text
-        if let Some(existing) = url_map.get(&url) {
-            // Compare lengths to keep the one with more descriptive text
-            let existing_len = existing.text.len().max(existing.title.len());
-            let current_len = link.text.len().max(link.title.len());
-            
-            // Only replace if this entry has a longer description
-            if current_len > existing_len {
-                url_map.insert(url, link.clone());
-            }
-        } else {
-            // First occurrence of this URL
-            url_map.insert(url, link.clone());
-        }
-    }
-    
-    // Convert back to vector and sort
-    let mut unique_links: Vec<Link> = url_map.into_values().collect();
-    unique_links.sort_by(|a, b| a.text.cmp(&b.text));
-    
-    // Create section links from unique links
-    let bib = unique_links
+    let style = meta.get_citation_style();
+    let mut items = a
+        .bibliography
         .iter()
-        .map(|l| SectionLink {
-            id: Some(l.id.clone()),
-            slug: l.href.clone(),
-            title: if l.title.is_empty() {
-                l.text.clone()
-            } else {
-                l.title.clone()
-            },
+        .map(|e| {
+            format!(
+                "<li class='block' id='{lang}-{}'><p class='in-list first-graf block' style='--bsm: 0;text-indent: 0px;'>{}</p></li>",
+                gen_section_id(&e.id),
+                e.format(style)
+            )
         })
         .collect::<Vec<_>>();
 
+    // .bib / .ris files are written next to the page
+    let download = get_string(meta, lang, "bibliography-download")?;
+    items.push(format!(
+        "<p style='text-indent: 0px;'>{download}: <a href='$$PAGE_HREF$$.bib' download>BibTeX</a> / <a href='$$PAGE_HREF$$.ris' download>RIS</a></p>"
+    ));
+
     let s = get_string(meta, lang, "bibliography-title")?;
 
-    Ok(render_index_section(
-        lang,
+    Ok(render_index_section_texts(
         "bibliography",
         "",
         &s,
-        &bib,
-        true,
+        &items,
     ))
 }
 
+// Writes the bibliography of a page as `{slug}.bib` and `{slug}.ris` next to the HTML
+fn write_bibliography_files(dir: &Path, slug: &str, a: &ParsedArticleAnalyzed) {
+    if a.bibliography.is_empty() {
+        return;
+    }
+    let _ = std::fs::write(dir.join(format!("{slug}.bib")), citation::entries_to_bibtex(&a.bibliography));
+    let _ = std::fs::write(dir.join(format!("{slug}.ris")), citation::entries_to_ris(&a.bibliography));
+}
+
 fn body_footer(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Result<String, String> {
     let home = get_string(meta, lang, "special-homepage-desc")?;
     let top = get_string(meta, lang, "go-to-top")?;
@@ -2891,6 +2965,19 @@ fn rosary_template(lang: &str) -> rosary::RosaryTemplates {
     }
 }
 
+// file name of the rosary page, without ".html"
+fn rosary_slug(lang: &str) -> &'static str {
+    match lang {
+        "de" => "rosenkranz",
+        "en" => "rosary",
+        "fr" => "rosaire",
+        "es" => "rosario",
+        "br" => "rosario",
+        "pl" => "rozaniec",
+        _ => "rosary",
+    }
+}
+
 fn rosary_mysteries() -> rosary::RosaryMysteries {
     match serde_json::from_str(include_str!("../../config/mysteries.json")) {
         Ok(o) => o,
@@ -2906,10 +2993,9 @@ fn article2html(
     slug: &str,
     a: &ParsedArticleAnalyzed,
     articles_by_tag: &mut ArticlesByTag,
+    articles_by_date: &mut ArticlesByDate,
     meta: &MetaJson,
 ) -> Result<String, String> {
-    static HTML: &str = include_str!("../../templates/lorem.html");
-
     if a.tags.is_empty() {
         println!("article {lang}/{slug} has no tags");
     }
@@ -2927,18 +3013,48 @@ fn article2html(
             });
     }
 
-    let title_id = lang.to_string() + "-" + slug;
+    if let Some((year, month, day)) = a.get_date() {
+        articles_by_date
+            .entry(lang.to_string())
+            .or_default()
+            .entry(year.to_string())
+            .or_default()
+            .entry(month.to_string())
+            .or_default()
+            .entry(day.to_string())
+            .or_default()
+            .push(SectionLink {
+                slug: slug.to_string(),
+                title: a.title.to_string(),
+                id: None,
+            });
+    }
+
+    render_article(lang, slug, a, meta)
+}
+
+// Fills the article template, pages without a sha256 (author pages)
+// have no page metadata and no citation block
+fn render_article(
+    lang: &str,
+    slug: &str,
+    a: &ParsedArticleAnalyzed,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    static HTML: &str = include_str!("../../templates/lorem.html");
+
+    let title_id = lang.to_string() + "-" + &slug.replace("/", "-");
     let logo_svg = include_str!("../../static/img/logo/full.svg")
         .replace("<svg ", "<svg style='max-height:50px;' ");
 
     let mut a = a.clone();
 
-    let content = body_content(lang, &slug, &a.sections, meta)?;
+    let content = body_content(lang, slug, &a.sections, a.audio.as_ref(), meta)?;
 
     let a = &a;
     let html = HTML.replace(
         "<!-- HEAD_TEMPLATE_HTML -->",
-        &head(a, lang, title_id.as_str(), meta)?,
+        &head(a, lang, title_id.as_str(), slug, meta)?,
     );
     let html = html.replace(
         "<!-- HEADER_NAVIGATION -->",
@@ -2950,10 +3066,20 @@ fn article2html(
         "<!-- PAGE_DESCRIPTION -->",
         &page_desciption(lang, &a, meta)?,
     );
-    let html = html.replace("<!-- PAGE_METADATA -->", &page_metadata(lang, &a, meta)?);
+    let html = match a.sha256.is_empty() {
+        true => html.replace("<!-- PAGE_METADATA -->", ""),
+        false => html.replace(
+            "<!-- PAGE_METADATA -->",
+            &page_metadata(lang, a, &epub::EbookKind::Article(slug.to_string()), slug, meta)?,
+        ),
+    };
+    let html = match a.audio.as_ref() {
+        Some(au) => html.replace("<!-- PAGE_AUDIO -->", &audio::render_player(lang, slug, au, meta)?),
+        None => html.replace("<!-- PAGE_AUDIO -->", ""),
+    };
     let html = html.replace(
         "<!-- BODY_ABSTRACT -->",
-        &body_abstract(lang, slug, a.is_prayer(), &a.summary),
+        &body_abstract(lang, slug, a, meta),
     );
     let html = html.replace("<!-- BODY_CONTENT -->", &content);
     let html = html.replace("<!-- DONATE -->", &donate(lang, &a, meta)?);
@@ -2962,6 +3088,13 @@ fn article2html(
     let html = html.replace("<!-- BACKLINKS -->", &backlinks(lang, a, meta)?);
     let html = html.replace("<!-- SIMILARS -->", &similars(lang, a, meta)?);
     let html = html.replace("<!-- BIBLIOGRAPHY -->", &bibliography(lang, a, meta)?);
+    let html = match a.sha256.is_empty() {
+        true => html.replace("<!-- CITE_THIS -->", ""),
+        false => html.replace(
+            "<!-- CITE_THIS -->",
+            &cite_this(lang, &title_id, a, &(get_root_href().to_string() + "/" + lang + "/" + slug), meta)?,
+        ),
+    };
     let html = html.replace("<!-- SVG_LOGO_INLINE -->", &logo_svg);
     let html = html.replace("<!-- BODY_FOOTER -->", &body_footer(lang, a, meta)?);
 
@@ -2988,18 +3121,31 @@ fn article2html(
     Ok(html)
 }
 
+/// Name of the author page, /{lang}/author/{id}
+pub fn author_page_id(id: &str) -> String {
+    id.to_lowercase().replace(":", "-")
+}
+
 fn render_page_author_pages(
     articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
     meta: &MetaJson,
 ) -> Result<BTreeMap<String, Vec<(String, String)>>, String> {
+    let root_href = get_root_href();
     let mut finalmap = BTreeMap::new();
-    for lang in articles.map.keys() {
+    // languages with only documents link their bylines to the author pages, too
+    let langs = articles.map.keys().chain(documents.map.keys()).collect::<BTreeSet<_>>();
+    for lang in langs {
         let contact_str = get_string(meta, lang, "author-contact")?;
         let donate_str = get_string(meta, lang, "author-donate")?;
+        let articles_str = get_string(meta, lang, "author-articles")?;
+        let no_articles_str = get_string(meta, lang, "author-no-articles")?;
+        let docs_title = get_string(meta, lang, "special-docs-title")?;
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
 
         for (id, v) in meta.authors.iter() {
             let name = &v.displayname;
-            let contact_url = v.contact.as_deref();
+            let page_id = author_page_id(id);
             let mut dn = String::new();
             for (platform, link) in v.donate.iter() {
                 let s = match platform.as_str() {
@@ -3020,33 +3166,160 @@ fn render_page_author_pages(
                 dn.push_str(&s);
             }
 
-            let mut t = format!("<!doctype html><html><head><title>{name}</title></head><body>");
-            t += &format!("<h1>{name}</h1>");
-            if let Some(contact_url) = contact_url {
-                t += &format!("<h2>{contact_str}</h2>");
-                t += &format!("<a href='{contact_url}'>{contact_url}</a>");
-            }
+            // articles in this language first, then all other languages
+            let mut by_lang = articles
+                .map
+                .iter()
+                .map(|(l, arts)| {
+                    let mut links = arts
+                        .iter()
+                        .filter(|(_, a)| a.authors.contains(id))
+                        .map(|(slug, a)| (a.get_iso_date(), a.title.clone(), format!("{root_href}/{l}/{slug}")))
+                        .collect::<Vec<_>>();
+                    links.sort_by(|a, b| b.cmp(a));
+                    (l.clone(), links)
+                })
+                .filter(|(_, links)| !links.is_empty())
+                .collect::<Vec<_>>();
+            by_lang.sort_by_key(|(l, _)| l != lang);
 
+            let docs = documents
+                .map
+                .get(lang)
+                .and_then(|d| d.get(id))
+                .into_iter()
+                .flatten()
+                .map(|(slug, d)| (d.get_iso_date(), d.title.clone(), format!("{root_href}/{lang}/{docs_path}/{id}/{slug}")))
+                .collect::<Vec<_>>();
+
+            let html = |s: String| Paragraph::Markdown { html: s, s: Vec::new() };
+            let text = |s: &str| Paragraph::Sentence { s: vec![SentenceItem::Text { text: s.to_string() }] };
+            let list = |links: &[(String, String, String)]| {
+                let items = links
+                    .iter()
+                    .map(|(_, title, href)| format!("<li><p class='in-list first-graf block'><a href='{href}' class='link-annotated link-page'>{title}</a></p></li>"))
+                    .collect::<Vec<_>>()
+                    .join("");
+                html(format!("<ul class='list'>{items}</ul>"))
+            };
+
+            let mut sections = Vec::new();
+            let has_articles = by_lang.first().map(|(l, _)| l == lang).unwrap_or(false);
+            // same condition as in feed::generate_feeds
+            let has_feed = has_articles
+                || documents
+                    .map
+                    .get(lang)
+                    .into_iter()
+                    .flat_map(|d| d.values().flat_map(|s| s.values()))
+                    .any(|d| d.authors.contains(id));
+            if !has_articles {
+                sections.push(ArticleSection { title: articles_str.clone(), indent: 2, pars: vec![text(&no_articles_str)] });
+            }
+            for (l, links) in by_lang.iter() {
+                let title = match l == lang {
+                    true => articles_str.clone(),
+                    false => format!("{articles_str} ({})", get_string(meta, l, "lang-name")?),
+                };
+                sections.push(ArticleSection { title, indent: 2, pars: vec![list(links)] });
+            }
+            if !docs.is_empty() {
+                sections.push(ArticleSection { title: docs_title.clone(), indent: 2, pars: vec![list(&docs)] });
+            }
+            if let Some(contact_url) = v.contact.as_deref() {
+                let href = match contact_url.contains('@') && !contact_url.starts_with("http") {
+                    true => format!("mailto:{contact_url}"),
+                    false => contact_url.to_string(),
+                };
+                let link = format!("<p><a href='{href}'>{contact_url}</a></p>");
+                sections.push(ArticleSection { title: contact_str.clone(), indent: 2, pars: vec![html(link)] });
+            }
             if !dn.is_empty() {
-                t += &format!("<h2>{donate_str}</h2>");
-                t += &dn;
+                sections.push(ArticleSection { title: donate_str.clone(), indent: 2, pars: vec![html(dn)] });
+            }
+
+            let subtitle = [v.get_role(lang).map(|s| s.to_string()), v.get_life_dates()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let mut summary = v.get_bio(lang).iter().map(|p| text(p)).collect::<Vec<_>>();
+            if summary.is_empty() {
+                summary.push(text(&get_string(meta, lang, "author-page-desc")?.replace("$$AUTHOR$$", name)));
+            }
+            if let Some(p) = v.portrait.as_deref() {
+                let src = format!("{root_href}/{}", p.trim_start_matches('/'));
+                summary.push(html(format!(
+                    "<img src='{src}' alt='{}' style='max-width: 200px; margin: 10px auto; display: block;'>",
+                    citation::html_escape(name)
+                )));
             }
-            t += &format!("</body></html>");
+
+            let a = ParsedArticleAnalyzed {
+                title: name.clone(),
+                subtitle: if subtitle.is_empty() { Vec::new() } else { vec![text(&subtitle)] },
+                summary,
+                sections,
+                // only link the author feed if there is one in this language
+                authors: if has_feed { vec![id.clone()] } else { Vec::new() },
+                img: v.portrait.as_ref().map(|p| Image {
+                    href: format!("{root_href}/{}", p.trim_start_matches('/')),
+                    alt: name.clone(),
+                    title: name.clone(),
+                    inline: None,
+                }),
+                ..Default::default()
+            };
+
+            let t = render_article(lang, &format!("author/{page_id}"), &a, meta)?;
 
             finalmap
                 .entry(lang.clone())
                 .or_insert_with(|| Vec::new())
-                .push((id.to_lowercase().replace(":", "-"), t));
+                .push((page_id, t));
         }
     }
 
     Ok(finalmap)
 }
 
+#[test]
+fn test_author_pages() {
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let meta = read_meta_json(&std::fs::read_to_string(cwd.join("config").join("meta.json")).unwrap());
+    let page = |title: &str| ParsedArticleAnalyzed {
+        title: title.to_string(),
+        authors: vec!["lefebvre".to_string()],
+        ..Default::default()
+    };
+    let mut articles = AnalyzedArticles::default();
+    articles.map.entry("de".to_string()).or_default().insert("a".to_string(), page("Artikel"));
+    // no articles in "it", only a document
+    let mut documents = docs::AnalyzedDocuments::default();
+    documents
+        .map
+        .entry("it".to_string())
+        .or_default()
+        .entry("lefebvre".to_string())
+        .or_default()
+        .insert("lettera".to_string(), page("Lettera"));
+
+    let pages = render_page_author_pages(&articles, &documents, &meta).unwrap();
+    let page = |lang: &str| pages[lang].iter().find(|(id, _)| id == "lefebvre").map(|(_, html)| html.clone());
+    assert!(page("de").is_some());
+    let it = page("it").unwrap();
+    assert!(it.contains("/it/") && it.contains("/lefebvre/lettera"));
+    assert!(it.contains("Artikel"));
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct SearchIndex {
     git: String,
     articles: BTreeMap<Slug, SearchIndexArticle>,
+    // shards and entry chunks written to /{lang}/fulltext/
+    #[serde(default)]
+    fulltext: FullTextManifest,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -3056,30 +3329,49 @@ struct SearchIndexArticle {
     // "document" for docs, None for regular articles
     #[serde(default, skip_serializing_if = "Option::is_none")]
     doc_type: Option<String>,
-    // author for documents
+    // author for documents, also the doc collection (docs/{lang}/{author}/)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     author: Option<String>,
+    // tag ids, localized names are rendered into the search page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<Tag>,
+    // author ids (keys of meta.authors)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<String>,
+    #[serde(default)]
+    atype: ArticleType,
+    // yyyy-mm-dd
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    date: String,
 }
 
 fn generate_search_index(
     articles: &AnalyzedArticles, 
     documents: &docs::AnalyzedDocuments,
     meta: &MetaJson
-) -> BTreeMap<Lang, SearchIndex> {
+) -> Result<BTreeMap<Lang, (SearchIndex, FullTextIndex)>, String> {
     let def = BTreeMap::new();
     
     meta.strings.keys()
         .map(|lang| {
+            let docs_folder = get_string(meta, lang, "special-docs-path")?;
+            let mut fulltext = FullTextIndex::new(lang);
+
             // Articles entries
             let arts = articles.map.get(lang).unwrap_or(&def);
             let article_entries = arts
                 .iter()
                 .map(|(slug, article)| {
+                    fulltext.add_page(slug, &format!("/{lang}/{slug}"), article);
                     let sia = SearchIndexArticle {
                         title: article.title.clone(),
                         sha256: article.sha256.clone(),
                         doc_type: None,
                         author: None,
+                        tags: article.tags.clone(),
+                        authors: article.authors.clone(),
+                        atype: article.atype,
+                        date: article.get_iso_date(),
                     };
                     (slug.clone(), sia)
                 })
@@ -3092,12 +3384,17 @@ fn generate_search_index(
                     for (slug, doc) in docs {
                         // Create a special slug format for documents
                         let doc_slug = format!("docs/{}/{}", author, slug);
+                        fulltext.add_page(&doc_slug, &format!("/{lang}/{docs_folder}/{author}/{slug}"), doc);
                         
                         let sia = SearchIndexArticle {
                             title: doc.title.clone(),
                             sha256: doc.sha256.clone(),
                             doc_type: Some("document".to_string()),
                             author: Some(author.clone()),
+                            tags: doc.tags.clone(),
+                            authors: doc.authors.clone(),
+                            atype: doc.atype,
+                            date: doc.get_iso_date(),
                         };
                         
                         doc_entries.insert(doc_slug, sia);
@@ -3109,25 +3406,42 @@ fn generate_search_index(
             let mut all_entries = article_entries;
             all_entries.extend(doc_entries);
             
-            // Generate version hash including all content
-            let s = all_entries
-                .values()
-                .map(|r| r.sha256.clone())
-                .collect::<Vec<_>>()
-                .join(" ");
-            let version = sha256(&s);
-            
-            (
-                lang.clone(),
-                SearchIndex {
-                    git: version,
-                    articles: all_entries,
-                },
-            )
+            let index = SearchIndex {
+                git: search_index_version(lang, articles, documents),
+                articles: all_entries,
+                fulltext: fulltext.manifest(),
+            };
+
+            Ok((lang.clone(), (index, fulltext)))
         })
         .collect()
 }
 
+// Bump when the layout of the index.json or the /{lang}/fulltext/ files
+// changes, so that clients drop their cached copy
+const SEARCH_INDEX_FORMAT: &str = "4";
+
+// Version of the search index, shared by the index.json and the search.js
+fn search_index_version(
+    lang: &str,
+    articles: &AnalyzedArticles,
+    documents: &docs::AnalyzedDocuments,
+) -> String {
+    // Generate version hash that includes both articles and documents
+    let mut all_hashes = vec![SEARCH_INDEX_FORMAT.to_string()];
+    if let Some(a) = articles.map.get(lang) {
+        all_hashes.extend(a.values().map(|r| r.sha256.clone()));
+    }
+    if let Some(lang_docs) = documents.map.get(lang) {
+        for authors in lang_docs.values() {
+            for doc in authors.values() {
+                all_hashes.push(doc.sha256.clone());
+            }
+        }
+    }
+    sha256(all_hashes.join(" "))
+}
+
 type SearchHtmlResult = BTreeMap<Lang, (String, String, String)>;
 
 // Lang => (SearchBarHtml, SearchJS)
@@ -3136,31 +3450,9 @@ fn search_html(
     documents: &docs::AnalyzedDocuments,
     meta: &MetaJson
 ) -> Result<SearchHtmlResult, String> {
-    let def = BTreeMap::new();
-    
     meta.strings.keys()
         .map(|lang| {
-            // Generate version hash that includes both articles and documents
-            let a = articles.map.get(lang).unwrap_or(&def);
-            let article_hashes = a
-                .values()
-                .map(|r| r.sha256.clone())
-                .collect::<Vec<_>>();
-                
-            let mut document_hashes = Vec::new();
-            if let Some(lang_docs) = documents.map.get(lang) {
-                for (_, authors) in lang_docs {
-                    for (_, doc) in authors {
-                        document_hashes.push(doc.sha256.clone());
-                    }
-                }
-            }
-            
-            // Combine all hashes for version
-            let mut all_hashes = article_hashes;
-            all_hashes.extend(document_hashes);
-            let s = all_hashes.join(" ");
-            let version = sha256(&s);
+            let version = search_index_version(lang, articles, documents);
 
             // Get translated strings
             let searchbar_placeholder = get_string(meta, lang, "searchbar-placeholder")?;
@@ -3177,7 +3469,14 @@ fn search_html(
 
             // Generate search page HTML
             let mut search_html = include_str!("../../templates/search.html").to_string();
-            search_html = search_html.replace("<!-- SEARCH -->", &searchbar_html);
+            search_html = search_html.replace(
+                "<!-- SEARCH -->",
+                &searchbar_html.replace(
+                    "<!-- SEARCH_FACETS -->",
+                    &search_facets(lang, articles, documents, meta)?,
+                ),
+            );
+            searchbar_html = searchbar_html.replace("<!-- SEARCH_FACETS -->", "");
             search_html = search_html.replace(
                 "<!-- HEADER_NAVIGATION -->",
                 &header_navigation(lang, true, meta)?,
@@ -3198,7 +3497,7 @@ fn search_html(
             
             search_html = search_html.replace(
                 "<!-- HEAD_TEMPLATE_HTML -->",
-                &head(&parsed, lang, &format!("{lang}-search"), meta)?,
+                &head(&parsed, lang, &format!("{lang}-search"), "search", meta)?,
             );
             search_html = search_html.replace("$$TITLE$$", &searchpage_title);
 
@@ -3206,6 +3505,7 @@ fn search_html(
             search_js = search_js.replace("$$LANG$$", lang);
             search_js = search_js.replace("$$VERSION$$", &version);
             search_js = search_js.replace("$$NO_RESULTS$$", &no_results);
+            search_js = search_js.replace("$$DID_YOU_MEAN$$", &get_string(meta, lang, "search-did-you-mean")?);
             search_js = search_js.replace("$$DOC_FOLDER$$", &get_string(meta, lang, "special-docs-path")?);
 
             // Return the tuple for this language
@@ -3214,6 +3514,137 @@ fn search_html(
         .collect()
 }
 
+// Renders the filter form of the search page, the options only list
+// the tags, authors and doc collections that exist in this language
+fn search_facets(
+    lang: &str,
+    articles: &AnalyzedArticles,
+    documents: &docs::AnalyzedDocuments,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    let def = BTreeMap::new();
+    let arts = articles.map.get(lang).unwrap_or(&def);
+    let docs = documents.map.get(lang);
+
+    let tag_names = meta.tags.get(lang).map(|t| &t.tags);
+    let tags = arts
+        .values()
+        .chain(docs.iter().flat_map(|d| d.values().flat_map(|s| s.values())))
+        .flat_map(|a| a.tags.iter())
+        .map(|t| {
+            let name = tag_names.and_then(|n| n.get(t)).cloned().unwrap_or_else(|| t.clone());
+            (t.clone(), name)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let author_name = |id: &String| {
+        meta.authors
+            .get(id)
+            .map(|a| a.displayname.clone())
+            .unwrap_or_else(|| id.clone())
+    };
+
+    let authors = arts
+        .values()
+        .chain(docs.iter().flat_map(|d| d.values().flat_map(|s| s.values())))
+        .flat_map(|a| a.authors.iter())
+        .map(|a| (a.clone(), author_name(a)))
+        .collect::<BTreeMap<_, _>>();
+
+    let collections = docs
+        .iter()
+        .flat_map(|d| d.keys())
+        .map(|a| (a.clone(), author_name(a)))
+        .collect::<BTreeMap<_, _>>();
+
+    // sorted by display name, not by id
+    let options = |map: BTreeMap<String, String>| {
+        let mut v = map.into_iter().collect::<Vec<_>>();
+        v.sort_by_key(|(_, name)| name.to_lowercase());
+        v.into_iter()
+            .map(|(id, name)| format!("<option value='{id}'>{name}</option>"))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    };
+
+    let mut html = include_str!("../../templates/search-facets.html").to_string();
+    html = html.replace("<!-- TAG_OPTIONS -->", &options(tags));
+    html = html.replace("<!-- AUTHOR_OPTIONS -->", &options(authors));
+    html = html.replace("<!-- COLLECTION_OPTIONS -->", &options(collections));
+
+    for (placeholder, key) in [
+        ("$$FACET_KIND$$", "search-facet-kind"),
+        ("$$FACET_ALL$$", "search-facet-all"),
+        ("$$KIND_ARTICLE$$", "search-kind-article"),
+        ("$$KIND_DOCUMENT$$", "search-kind-document"),
+        ("$$FACET_TYPE$$", "search-facet-type"),
+        ("$$TYPE_QUESTION$$", "search-type-question"),
+        ("$$TYPE_TRACT$$", "search-type-tract"),
+        ("$$TYPE_PRAYER$$", "search-type-prayer"),
+        ("$$FACET_TAG$$", "search-facet-tag"),
+        ("$$FACET_AUTHOR$$", "search-facet-author"),
+        ("$$FACET_COLLECTION$$", "search-facet-collection"),
+        ("$$FACET_DATE$$", "search-facet-date"),
+        ("$$DATE_FROM$$", "search-date-from"),
+        ("$$DATE_TO$$", "search-date-to"),
+        ("$$SORT$$", "search-sort"),
+        ("$$SORT_RELEVANCE$$", "search-sort-relevance"),
+        ("$$SORT_DATE$$", "search-sort-date"),
+        ("$$RESET$$", "search-reset"),
+    ] {
+        html = html.replace(placeholder, &get_string(meta, lang, key)?);
+    }
+
+    Ok(html)
+}
+
+#[test]
+fn test_search_facets() {
+    let mut meta = MetaJson::default();
+    let keys = include_str!("../../templates/search-facets.html")
+        .split("$$")
+        .skip(1)
+        .step_by(2)
+        .map(|k| ("search-".to_string() + &k.to_lowercase().replace('_', "-"), k.to_string()));
+    meta.strings.insert("de".to_string(), keys.collect());
+    meta.authors = serde_json::from_str(
+        r#"{ "lefebvre": { "displayname": "Marcel Lefebvre" }, "hesse": { "displayname": "Gregorius Hesse" } }"#,
+    )
+    .unwrap();
+
+    let page = |tags: &[&str], authors: &[&str]| ParsedArticleAnalyzed {
+        tags: tags.iter().map(|s| s.to_string()).collect(),
+        authors: authors.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+    };
+    let mut articles = AnalyzedArticles::default();
+    let de = articles.map.entry("de".to_string()).or_default();
+    de.insert("a".to_string(), page(&["moral", "papst"], &["hesse"]));
+    de.insert("b".to_string(), page(&["moral"], &["hesse"]));
+    articles.map.entry("en".to_string()).or_default().insert("c".to_string(), page(&["english"], &["other"]));
+    let mut documents = docs::AnalyzedDocuments::default();
+    documents
+        .map
+        .entry("de".to_string())
+        .or_default()
+        .entry("lefebvre".to_string())
+        .or_default()
+        .insert("d".to_string(), page(&["papst"], &["lefebvre"]));
+
+    let html = search_facets("de", &articles, &documents, &meta).unwrap();
+    let count = |s: &str| html.matches(s).count();
+    // every tag, author and collection once, only those of the language
+    assert_eq!(count("<option value='moral'>"), 1);
+    assert_eq!(count("<option value='papst'>"), 1);
+    assert_eq!(count("<option value='english'>"), 0);
+    assert_eq!(count("<option value='other'>"), 0);
+    assert_eq!(count("<option value='hesse'>Gregorius Hesse</option>"), 1);
+    assert_eq!(count("<option value='lefebvre'>Marcel Lefebvre</option>"), 2);
+    // sorted by display name
+    assert!(html.find("Gregorius Hesse").unwrap() < html.find("Marcel Lefebvre").unwrap());
+    assert!(!html.contains("$$"));
+}
+
 struct SpecialPage {
     id: String,
     filepath: String,
@@ -3223,11 +3654,300 @@ struct SpecialPage {
     special_content: String,
 }
 
+// One page per tag: description, header image, articles grouped by type
+// (newest first) and the tags that are most often used together with it
+fn get_tag_pages(
+    lang: &str,
+    meta: &MetaJson,
+    by_tag: &ArticlesByTag,
+    articles: &AnalyzedArticles,
+) -> Result<Vec<(Tag, SpecialPage)>, String> {
+    let tags = meta
+        .tags
+        .get(lang)
+        .ok_or_else(|| format!("unknown language {lang} not found in tags.json"))?;
+    let tag_name = |t: &String| tags.tags.get(t).cloned().unwrap_or_else(|| t.clone());
+    let topics_path = get_string(meta, lang, "special-topics-path")?;
+    let related_title = get_string(meta, lang, "tag-related")?;
+    let root_href = get_root_href();
+
+    let default = BTreeMap::new();
+    let arts = articles.map.get(lang).unwrap_or(&default);
+
+    let mut pages = Vec::new();
+    for (tag, links) in by_tag.get(lang).into_iter().flatten() {
+        let name = tag_name(tag);
+        let tag_page = tags.tag_pages.get(tag).cloned().unwrap_or_default();
+
+        let mut content = String::new();
+        for (atype, key) in [
+            (ArticleType::Question, "search-type-question"),
+            (ArticleType::Tract, "search-type-tract"),
+            (ArticleType::Prayer, "search-type-prayer"),
+        ] {
+            let mut items = links
+                .iter()
+                .filter_map(|l| Some((arts.get(&l.slug)?, l)))
+                .filter(|(a, _)| a.atype == atype)
+                .collect::<Vec<_>>();
+            if items.is_empty() {
+                continue;
+            }
+            items.sort_by(|(a, la), (b, lb)| {
+                b.get_iso_date().cmp(&a.get_iso_date()).then_with(|| la.title.cmp(&lb.title))
+            });
+            let links = items.into_iter().map(|(_, l)| l.clone()).collect::<Vec<_>>();
+            let title = get_string(meta, lang, key)?;
+            let id = format!("{tag}-{}", key.trim_start_matches("search-type-"));
+            content += &render_index_section(lang, &id, "", &title, &links, false);
+        }
+
+        let related = related_tags(tag, links, arts)
+            .into_iter()
+            .map(|t| SectionLink {
+                id: Some(format!("related-{t}")),
+                slug: format!("{topics_path}/{t}"),
+                title: tag_name(&t),
+            })
+            .collect::<Vec<_>>();
+        if !related.is_empty() {
+            content += &render_index_section(lang, &format!("{tag}-related"), "", &related_title, &related, true);
+        }
+
+        let img = match tag_page.img.as_deref() {
+            Some(src) => format!(
+                "<img src='{root_href}/{}' alt='{}' style='width: 100%; margin-bottom: 20px;'>",
+                src.trim_start_matches('/'),
+                citation::html_escape(&name)
+            ),
+            None => String::new(),
+        };
+        let description = match tag_page.description.first() {
+            Some(s) => s.clone(),
+            None => get_string(meta, lang, "tag-page-desc")?.replace("$$TAG$$", &name),
+        };
+        let epub = format!(
+            "<p style='text-indent: 0px;'><a href='{}' download>{}</a></p>",
+            epub::EbookKind::Tag(tag.clone()).url(lang),
+            get_string(meta, lang, "epub-tag-desc")?.replace("$$TAG$$", &name),
+        );
+
+        pages.push((tag.clone(), SpecialPage {
+            title: name.clone(),
+            filepath: format!("{topics_path}/{tag}"),
+            id: format!("{lang}-{topics_path}-{tag}"),
+            description,
+            content,
+            special_content: format!(
+                "<style>#special-contents {{ display: block; }}</style>{img}{}{epub}",
+                render_section_items_texts(&tag_page.description)
+            ),
+        }));
+    }
+
+    Ok(pages)
+}
+
+// The tags used most often together with `tag`, most frequent first
+fn related_tags(tag: &str, links: &[SectionLink], arts: &BTreeMap<Slug, ParsedArticleAnalyzed>) -> Vec<Tag> {
+    const MAX_RELATED_TAGS: usize = 8;
+
+    let mut related = BTreeMap::new();
+    for a in links.iter().filter_map(|l| arts.get(&l.slug)) {
+        for t in a.tags.iter().filter(|t| *t != tag) {
+            *related.entry(t.clone()).or_insert(0_usize) += 1;
+        }
+    }
+    let mut related = related.into_iter().collect::<Vec<_>>();
+    related.sort_by(|(a, na), (b, nb)| nb.cmp(na).then_with(|| a.cmp(b)));
+    related.into_iter().take(MAX_RELATED_TAGS).map(|(t, _)| t).collect()
+}
+
+#[test]
+fn test_tag_pages() {
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let meta = read_meta_json(&std::fs::read_to_string(cwd.join("config").join("meta.json")).unwrap());
+    assert_eq!(get_tag_page_link("de", "moral", &meta).unwrap(), format!("de/{}/moral", get_string(&meta, "de", "special-topics-path").unwrap()));
+    assert!(get_tag_page_link("xx", "moral", &meta).is_err());
+
+    // "a" is used with b once, with c..k twice each
+    let page = |tags: &[&str]| ParsedArticleAnalyzed { tags: tags.iter().map(|s| s.to_string()).collect(), ..Default::default() };
+    let others = ["c", "d", "e", "f", "g", "h", "i", "j", "k"];
+    let mut arts = BTreeMap::new();
+    arts.insert("1".to_string(), page(&["a", "b"]));
+    arts.insert("2".to_string(), page(&[&["a"][..], &others].concat()));
+    arts.insert("3".to_string(), page(&[&["a"][..], &others].concat()));
+    arts.insert("4".to_string(), page(&["b", "c"]));
+    let links = ["1", "2", "3"]
+        .iter()
+        .map(|s| SectionLink { id: None, slug: s.to_string(), title: s.to_string() })
+        .collect::<Vec<_>>();
+    assert_eq!(related_tags("a", &links, &arts), vec!["c", "d", "e", "f", "g", "h", "i", "j"]);
+    assert_eq!(related_tags("a", &links[..1], &arts), vec!["b"]);
+
+    // hreflang groups: the same id, or the translations in meta.json
+    let mut by_tag = ArticlesByTag::default();
+    for (lang, tag) in [("de", "judentum"), ("en", "judaism"), ("fr", "judaisme"), ("de", "islam"), ("en", "islam"), ("de", "moral")] {
+        by_tag.entry(lang.to_string()).or_default().insert(tag.to_string(), Vec::new());
+    }
+    let groups = sitemap::tag_groups(&by_tag, &meta);
+    let group = |lang: &str, tag: &str| groups[&(lang.to_string(), tag.to_string())].clone();
+    assert_eq!(group("de", "judentum"), group("en", "judaism"));
+    assert_eq!(group("fr", "judaisme"), group("en", "judaism"));
+    assert_eq!(group("de", "islam"), group("en", "islam"));
+    assert_ne!(group("de", "islam"), group("de", "judentum"));
+    assert_ne!(group("de", "moral"), group("en", "judaism"));
+}
+
+// Articles grouped by year and month, newest first
+fn render_archive(lang: &str, by_date: &ArticlesByDate, meta: &MetaJson) -> Result<String, String> {
+    const MONTHS: [&str; 12] = [
+        "january", "february", "march", "april", "may", "june",
+        "july", "august", "september", "october", "november", "december",
+    ];
+
+    let default = BTreeMap::new();
+    let years = by_date.get(lang).unwrap_or(&default);
+    let count = |m: &BTreeMap<Day, Vec<SectionLink>>| m.values().map(|v| v.len()).sum::<usize>();
+
+    let year_links = years
+        .iter()
+        .rev()
+        .map(|(y, months)| {
+            let n = months.values().map(count).sum::<usize>();
+            format!("<a href='#archive-{y}'>{y}</a> ({n})")
+        })
+        .collect::<Vec<_>>()
+        .join(" · ");
+
+    let mut content = format!("<p style='text-indent: 0px;'>{year_links}</p>");
+    for (y, months) in years.iter().rev() {
+        let n = months.values().map(count).sum::<usize>();
+        content += &format!("<h2 id='archive-{y}' class='heading-level-1'>{y} ({n})</h2>");
+        for (m, days) in months.iter().rev() {
+            let month_name = match m.parse::<usize>().ok().and_then(|i| MONTHS.get(i.wrapping_sub(1))) {
+                Some(k) => get_string(meta, lang, &format!("month-{k}"))?,
+                None => m.clone(),
+            };
+            let links = days.values().rev().flatten().cloned().collect::<Vec<_>>();
+            let title = format!("{month_name} {y} ({})", links.len());
+            content += &render_index_section(lang, &format!("archive-{y}-{m}"), "", &title, &links, false);
+        }
+    }
+
+    Ok(content)
+}
+
+// Most recently published or updated articles and documents
+fn render_newest(
+    lang: &str,
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    sitemap: &sitemap::Sitemap,
+    meta: &MetaJson,
+) -> Result<String, String> {
+    const MAX_NEWEST: usize = 30;
+
+    let published = get_string(meta, lang, "special-newest-published")?;
+    let updated = get_string(meta, lang, "special-newest-updated")?;
+    let docs_path = get_string(meta, lang, "special-docs-path")?;
+
+    let mut pages = Vec::new();
+    for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+        pages.push((format!("/{lang}/{slug}"), a));
+    }
+    for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+        for (slug, d) in docs {
+            pages.push((format!("/{lang}/{docs_path}/{collection}/{slug}"), d));
+        }
+    }
+
+    let mut items = pages
+        .into_iter()
+        .filter_map(|(url, a)| {
+            let lastmod = sitemap.lastmod(&url)?.to_string();
+            let what = if lastmod == a.get_iso_date() { &published } else { &updated };
+            Some((lastmod, a.get_iso_date(), what, url, a.title.clone()))
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| (&b.0, &b.1).cmp(&(&a.0, &a.1)).then_with(|| a.4.cmp(&b.4)));
+    items.truncate(MAX_NEWEST);
+
+    let root_href = get_root_href();
+    let list = items
+        .iter()
+        .map(|(date, _, what, url, title)| {
+            let mut li = "<li class='link-modified-recently-list-item dark-mode-invert'>".to_string();
+            li += "<p class='in-list first-graf block'>";
+            li += &format!("<a href='{root_href}{url}' class='link-annotated link-page'>{title}</a>");
+            li += &format!(" <em>({what} {date})</em></p></li>");
+            li
+        })
+        .collect::<Vec<_>>()
+        .join("");
+
+    Ok(format!("<ul class='list'>{list}</ul>"))
+}
+
+#[test]
+fn test_newest() {
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let meta = read_meta_json(&std::fs::read_to_string(cwd.join("config").join("meta.json")).unwrap());
+    let dir = std::env::temp_dir().join(format!("md2json2-newest-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("dist").join("de")).unwrap();
+    let lastmod_file = dir.join("lastmod.json");
+    std::fs::write(
+        &lastmod_file,
+        r#"{ "/de/a": { "hash": "hash-a", "lastmod": "2024-05-01" }, "/de/d": { "hash": "hash-d-old", "lastmod": "2024-01-05" } }"#,
+    )
+    .unwrap();
+
+    let page = |date: &str, sha256: &str| ParsedArticleAnalyzed {
+        title: sha256.to_string(),
+        date: date.to_string(),
+        sha256: sha256.to_string(),
+        ..Default::default()
+    };
+    let mut articles = AnalyzedArticles::default();
+    let de = articles.map.entry("de".to_string()).or_default();
+    // unchanged historic text, undated page, new page, changed page
+    de.insert("a".to_string(), page("1870-07-18", "hash-a"));
+    de.insert("b".to_string(), page("", "hash-b"));
+    de.insert("c".to_string(), page("2025-01-02", "hash-c"));
+    de.insert("d".to_string(), page("2024-01-05", "hash-d"));
+
+    let mut sitemap = sitemap::Sitemap::new(&dir.join("dist"), &["de".to_string()], &lastmod_file);
+    sitemap.set_source_dates([("hash-b".to_string(), "2024-06-01".to_string())].into_iter().collect());
+    for (slug, a) in articles.map["de"].iter() {
+        sitemap.write(&dir.join("dist").join("de").join(format!("{slug}.html")), "", "", Some(a));
+    }
+    let today = sitemap.lastmod("/de/d").unwrap().to_string();
+    assert_eq!(sitemap.lastmod("/de/a"), Some("2024-05-01"));
+    assert_eq!(sitemap.lastmod("/de/b"), Some("2024-06-01"));
+    assert_eq!(sitemap.lastmod("/de/c"), Some("2025-01-02"));
+    assert!(today.as_str() > "2025-01-02");
+
+    let published = get_string(&meta, "de", "special-newest-published").unwrap();
+    let updated = get_string(&meta, "de", "special-newest-updated").unwrap();
+    let html = render_newest("de", &articles, &docs::AnalyzedDocuments::default(), &sitemap, &meta).unwrap();
+    let entry = |slug: &str| html.find(&format!("/de/{slug}'")).unwrap();
+    assert!(entry("d") < entry("c") && entry("c") < entry("b") && entry("b") < entry("a"));
+    assert!(html.contains(&format!("({updated} {today})")));
+    assert!(html.contains(&format!("({published} 2025-01-02)")));
+    assert!(html.contains(&format!("({updated} 2024-06-01)")));
+    assert_eq!(html.matches(&today).count(), 1);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 fn get_special_pages(
     lang: &str,
     meta: &MetaJson,
     by_tag: &ArticlesByTag,
+    by_date: &ArticlesByDate,
+    articles: &AnalyzedArticles,
     documents: &AnalyzedDocuments,
+    sitemap: &sitemap::Sitemap,
 ) -> Result<Vec<SpecialPage>, String> {
     let tags = meta
         .tags
@@ -3235,21 +3955,37 @@ fn get_special_pages(
         .ok_or_else(|| format!("unknown language {lang} not found in tags.json"))?;
 
     let default = BTreeMap::new();
-    let topics_content = render_index_sections(
-        lang,
-        by_tag
-            .get(lang)
-            .unwrap_or(&default)
-            .iter()
-            .filter_map(|(k, v)| {
-                let id = k.clone();
-                let title = tags.tags.get(&id)?;
-                Some(((id.to_string(), title.to_string()), v.clone()))
-            })
-            .collect(),
+    let by_tag = by_tag.get(lang).unwrap_or(&default);
+    let tag_name = |t: &String| tags.tags.get(t).cloned().unwrap_or_else(|| t.clone());
+
+    // sorted by display name, the ids keep the old /{lang}/{topics}#{tag} anchors working
+    let mut topics = by_tag.iter().map(|(t, v)| (tag_name(t), t, v.len())).collect::<Vec<_>>();
+    topics.sort();
+    let root_href = get_root_href();
+    let topics_list = topics
+        .iter()
+        .map(|(name, t, n)| {
+            let url = get_tag_page_link(lang, t, meta)?;
+            let mut li = format!("<li id='{t}' class='link-modified-recently-list-item dark-mode-invert'>");
+            li += "<p class='in-list first-graf block'>";
+            li += &format!("<a href='{root_href}/{url}' class='link-tag link-page' rel='tag'>{name}</a> ({n})");
+            li += "</p></li>";
+            Ok(li)
+        })
+        .collect::<Result<Vec<_>, String>>()?
+        .join("");
+    let topics_content = format!("<ul class='list'>{topics_list}</ul>");
+
+    // redirect old anchor links to the tag pages
+    let tag_ids = serde_json::to_string(&by_tag.keys().collect::<Vec<_>>()).unwrap_or_default();
+    let topics_path = get_string(meta, lang, "special-topics-path")?;
+    let topics_redirect = format!(
+        "<script>(function() {{ var t = decodeURIComponent(window.location.hash.slice(1)); \
+        if ({tag_ids}.indexOf(t) !== -1) {{ window.location.replace('{root_href}/{lang}/{topics_path}/' + t); }} }})();</script>"
     );
 
-    let docs_content = docs::get_document_index_content(
+    let docs_content = precache::render_download(lang, Some("docs"), meta)?
+        + &docs::get_document_index_content(
         lang, 
         documents, 
         meta,
@@ -3265,10 +4001,13 @@ fn get_special_pages(
     };
 
     let (topics_title, topics_path, topics_id, topics_desc) = get_page_info("topics")?;
+    let (archive_title, archive_path, archive_id, archive_desc) = get_page_info("archive")?;
+    let (newest_title, newest_path, newest_id, newest_desc) = get_page_info("newest")?;
     let (docs_title, docs_path, docs_id, docs_desc) = get_page_info("docs")?;
     let (resources_title, resources_path, resources_id, resources_desc) = get_page_info("resources")?;
     let (shop_title, shop_path, shop_id, shop_desc) = get_page_info("shop")?;
     let (about_title, about_path, about_id, about_desc) = get_page_info("about")?;
+    let root_href = get_root_href();
 
     Ok(vec![
         SpecialPage {
@@ -3277,7 +4016,25 @@ fn get_special_pages(
             id: topics_id,
             description: topics_desc,
             content: topics_content,
-            special_content: String::new(),
+            special_content: format!(
+                "{topics_redirect}<p style='text-indent: 0px;'><a href='{root_href}/{lang}/{archive_path}'>{archive_title}</a> · <a href='{root_href}/{lang}/{newest_path}'>{newest_title}</a></p>"
+            ),
+        },
+        SpecialPage {
+            title: archive_title.clone(),
+            filepath: archive_path.clone(),
+            id: archive_id,
+            description: archive_desc,
+            content: render_archive(lang, by_date, meta)?,
+            special_content: "<style>#special-contents { display: block; }</style>".to_string(),
+        },
+        SpecialPage {
+            title: newest_title.clone(),
+            filepath: newest_path.clone(),
+            id: newest_id,
+            description: newest_desc,
+            content: render_newest(lang, articles, documents, sitemap, meta)?,
+            special_content: "<style>#special-contents { display: block; } #special-contents ul li { margin-bottom: 0px; }</style>".to_string(),
         },
         SpecialPage {
             title: docs_title,
@@ -3331,7 +4088,7 @@ fn special2html(
     };
     special = special.replace(
         "<!-- HEAD_TEMPLATE_HTML -->",
-        &head(&a, lang, &page.id, meta)?,
+        &head(&a, lang, &page.id, &page.filepath, meta)?,
     );
     special = special.replace("<!-- BODY_NOSCRIPT -->", &page.special_content);
     special = special.replace("<!-- BODY_ABSTRACT -->", &page.content);
@@ -3491,13 +4248,6 @@ fn render_index_section_img(
     section_html
 }
 
-fn render_index_sections(lang: &str, s: Vec<((String, String), Vec<SectionLink>)>) -> String {
-    s.iter()
-        .map(|((id, title), links)| render_index_section(lang, id, "", title, links, false))
-        .collect::<Vec<_>>()
-        .join("\r\n")
-}
-
 fn render_resources_sections(lang: &str, s: &Vec<TagSection1>) -> String {
     s.iter()
         .map(|s| {
@@ -3734,6 +4484,10 @@ fn render_index_html(
         &render_other_index_sections(lang, tags, articles)?,
     );
     index_body_html = index_body_html.replace("<!-- SEARCHBAR -->", &searchbar_html);
+    index_body_html = index_body_html.replace(
+        "<!-- OFFLINE_DOWNLOAD -->",
+        &precache::render_download(lang, None, meta)?,
+    );
 
     let title_id = format!("{lang}-index");
     let mut index_html = include_str!("../../templates/index.html").to_string();
@@ -3742,7 +4496,7 @@ fn render_index_html(
     index_html = index_html.replace("<!-- SVG_LOGO_INLINE -->", logo_svg);
     index_html = index_html.replace(
         "<!-- HEAD_TEMPLATE_HTML -->",
-        &head(&a, lang, &title_id, meta)?,
+        &head(&a, lang, &title_id, "", meta)?,
     );
     index_html = index_html.replace("<!-- PAGE_HELP -->", &page_help);
     index_html = index_html.replace(
@@ -3778,7 +4532,7 @@ fn render_index_html(
 
 pub fn minify(input: &str) -> Vec<u8> {
     let s = include_str!("../../templates/sw-inject.js");
-    // let input = input.replace("<!-- INJECT_SW -->", &format!("<script>{s}</script>"));
+    let input = input.replace("<!-- INJECT_SW -->", &format!("<script>{s}</script>"));
     let mut minified = vec![];
     html5minify::Minifier::new(&mut minified)
         .minify(&mut input.as_bytes())
@@ -3801,17 +4555,27 @@ fn main() -> Result<(), String> {
             .to_path_buf();
     }
 
+    // `md2json2 fmt [--check]`: format the Markdown sources instead of building
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|s| s.as_str()) == Some("fmt") {
+        return fmt::run(&cwd, &args[2..]);
+    }
+
     let meta = std::fs::read_to_string(&cwd.join("config").join("meta.json")).map_err(|e| e.to_string())?;
-    let meta_map = read_meta_json(&meta);
+    let mut meta_map = read_meta_json(&meta);
 
     let dir = cwd.join("articles");
 
     let _ = std::fs::create_dir_all(cwd.join("dist"));
 
+    // Load bibliography files
+    let bib = BibSources::load(&cwd)?;
+
     // Load, parse and analyze articles
     let articles = load_articles(&dir)?;
     let vectorized = articles.vectorize();
-    let analyzed = vectorized.analyze();
+    let audio_files = audio::AudioFiles::load(&cwd)?;
+    let analyzed = vectorized.analyze(&bib, &audio_files);
     
     // Load and process documents
     let docs_dir = cwd.join("docs");
@@ -3820,10 +4584,42 @@ fn main() -> Result<(), String> {
     } else {
         docs::LoadedDocuments::default()
     };
-    let analyzed_documents = docs::process_documents(&documents)?;
-    
+    let analyzed_documents = docs::process_documents(&documents, &bib)?;
+
+    // Every html page is written through the sitemap
+    let langs = meta_map.strings.keys().cloned().collect::<Vec<_>>();
+
+    // Subset the web fonts to the characters of each language
+    let fonts_config = fonts::FontsJson::load(&cwd.join("config").join("fonts.json"))?;
+    let chars = langs
+        .iter()
+        .map(|l| {
+            let mut c = analyzed.get_chars(l);
+            c.extend(analyzed_documents.get_chars(l));
+            c.extend(meta_map.get_chars(l));
+            (l.clone(), c)
+        })
+        .collect();
+    meta_map.fonts = fonts::WebFonts::build(&cwd, &cwd.join("dist"), &fonts_config, &chars)?;
+    for l in meta_map.fonts.report() {
+        println!("{l}");
+    }
+
+    // Fonts of the dropcaps, per language or per article
+    let initials_config = initials::InitialsJson::load(&cwd.join("config").join("initials.json"))?;
+    meta_map.initials = initials::Initials::new(&cwd, initials_config);
+    // Characters the PDFs take from the fallback faces
+    meta_map.pdf_chars = pdf::FontFiles::load(&cwd)?.printable_chars(chars.values().flatten().copied())?;
+    let mut sitemap = sitemap::Sitemap::new(&cwd.join("dist"), &langs, &cwd.join(".cache").join("lastmod.json"));
+    let redirects = redirects::Redirects::load(&cwd.join("config").join("redirects.json"), &analyzed)?;
+    sitemap.set_redirects(&redirects.map);
+    sitemap.set_source_dates(sitemap::source_dates(&cwd));
+    let article_groups = sitemap::article_groups(&analyzed);
+    let document_groups = sitemap::document_groups(&analyzed_documents);
+
     // Render and write articles
     let mut articles_by_tag = ArticlesByTag::default();
+    let mut articles_by_date = ArticlesByDate::default();
     for (lang, articles) in analyzed.map.iter() {
         for (slug, a) in articles {
             let s = article2html(
@@ -3831,6 +4627,7 @@ fn main() -> Result<(), String> {
                 &slug,
                 &a,
                 &mut articles_by_tag,
+                &mut articles_by_date,
                 &meta_map,
             );
             
@@ -3838,7 +4635,9 @@ fn main() -> Result<(), String> {
                 Ok(s) => {
                     let path = cwd.join("dist").join(lang);
                     let _ = std::fs::create_dir_all(&path);
-                    let _ = std::fs::write(path.join(slug.to_string() + ".html"), &minify(&s));
+                    let group = &article_groups[&(lang.clone(), slug.clone())];
+                    sitemap.write(&path.join(slug.to_string() + ".html"), minify(&s), group, Some(a));
+                    write_bibliography_files(&path, slug, a);
                 }
                 Err(e) if e.is_empty() => {}
                 Err(q) => return Err(q),
@@ -3889,7 +4688,7 @@ fn main() -> Result<(), String> {
                 special_content: "<style>#special-contents { display: block; } #special-contents ul li { margin-bottom: 0px; }</style>".to_string(),
             };
             let (_, html) = special2html(&lang, &sp, &meta_map)?;
-            let _ = std::fs::write(author_file, html);
+            sitemap.write(&author_file, html, &format!("docs:{author}"), None);
 
             for (slug, doc) in docs {
                 let output_dir = docs_dir.join(author);
@@ -3897,38 +4696,104 @@ fn main() -> Result<(), String> {
                 
                 let html = docs::document2html(lang, author, slug, doc, &meta_map)?;
                 let output_path = output_dir.join(format!("{}.html", slug));
-                let _ = std::fs::write(output_path, &minify(&html));
+                let group = &document_groups[&(lang.clone(), format!("{author}/{slug}"))];
+                sitemap.write(&output_path, minify(&html), group, Some(doc));
+                write_bibliography_files(&output_dir, slug, doc);
             }
         }
     }
 
     // Write author pages
-    let author_pages = render_page_author_pages(&analyzed, &meta_map)?;
+    let author_pages = render_page_author_pages(&analyzed, &analyzed_documents, &meta_map)?;
     for (lang, authors) in author_pages.iter() {
         let _ = std::fs::create_dir_all(cwd.join("dist").join(&lang).join("author"));
         for (a, v) in authors {
-            let _ = std::fs::write(
-                cwd.join("dist").join(&lang).join("author").join(&format!("{a}.html")),
+            sitemap.write(
+                &cwd.join("dist").join(lang).join("author").join(format!("{a}.html")),
                 &minify(&v),
+                &format!("author:{a}"),
+                None,
             );
         }
     }
 
+    // Write Atom and JSON feeds
+    let feeds = feed::generate_feeds(&analyzed, &analyzed_documents, &articles_by_tag, &meta_map)?;
+    for feed in feeds.values().flatten() {
+        feed.write(&cwd.join("dist"))?;
+    }
+
+    // Write EPUB e-books
+    let ebooks = epub::generate_ebooks(&cwd, &analyzed, &analyzed_documents, &articles_by_tag, &meta_map)?;
+    for ebook in ebooks.values().flatten() {
+        ebook.write(&cwd.join("dist"), &meta_map)?;
+    }
+
+    // Write printable A4 / A5 PDFs
+    pdf::write_pdfs(&cwd, &cwd.join("dist"), &analyzed, &analyzed_documents, &meta_map)?;
+
+    // Write the versioned JSON of all pages, manifests and schemas
+    let api = api::generate_api(&analyzed, &analyzed_documents, &meta_map)?;
+    for export in api.values() {
+        export.write(&cwd.join("dist"))?;
+    }
+    api::write_schemas(&cwd.join("dist"))?;
+
+    // Write SSML and plain narration scripts for text-to-speech
+    let narrations = narration::generate_narrations(&analyzed, &analyzed_documents, &meta_map)?;
+    for n in narrations.values().flatten() {
+        n.write(&cwd.join("dist"))?;
+    }
+
+    // Copy narration audio and write the podcast feeds
+    audio_files.copy(&cwd.join("dist"))?;
+    for p in audio::generate_podcasts(&analyzed, &meta_map)? {
+        p.write(&cwd.join("dist"))?;
+    }
+
     // Generate search index
-    let si = generate_search_index(&analyzed, &analyzed_documents, &meta_map);
-    for (lang, si) in si.iter() {
+    let si = generate_search_index(&analyzed, &analyzed_documents, &meta_map)?;
+    for (lang, (si, fulltext)) in si.iter() {
         let json = serde_json::to_string(&si).unwrap_or_default();
         let _ = std::fs::write(cwd.join("dist").join(lang).join("index.json"), json);
+        fulltext.write(&cwd.join("dist").join(lang).join("fulltext"))?;
     }
     
     // Write special pages
-    let langs = meta_map.strings.keys().cloned().collect::<Vec<_>>();
+    let tag_groups = sitemap::tag_groups(&articles_by_tag, &meta_map);
     for l in langs.iter() {
-        let sp = get_special_pages(&l, &meta_map, &articles_by_tag, &analyzed_documents)?;
-        for s in sp.iter() {
+        let sp = get_special_pages(
+            l,
+            &meta_map,
+            &articles_by_tag,
+            &articles_by_date,
+            &analyzed,
+            &analyzed_documents,
+            &sitemap,
+        )?;
+        // same order as in get_special_pages
+        let groups = ["topics", "archive", "newest", "docs", "resources", "shop", "about"];
+        for (s, group) in sp.iter().zip(groups) {
             let (filename, html) = special2html(&l, s, &meta_map)?;
             let path = cwd.join("dist").join(l).join(filename);
-            let _ = std::fs::write(path, &minify(&html));
+            sitemap.write(&path, minify(&html), group, None);
+        }
+
+        // Write tag pages
+        let known_tags = meta_map.tags.get(l).map(|t| &t.tags);
+        for (tag, links) in articles_by_tag.get(l).into_iter().flatten() {
+            if !known_tags.map(|t| t.contains_key(tag)).unwrap_or(false) {
+                let slugs = links.iter().map(|q| q.slug.as_str()).collect::<Vec<_>>();
+                println!("unknown tag {l}/{tag} (missing in meta.json tags), used in: {}", slugs.join(", "));
+            }
+        }
+        for (tag, s) in get_tag_pages(l, &meta_map, &articles_by_tag, &analyzed)? {
+            let (filename, html) = special2html(l, &s, &meta_map)?;
+            let path = cwd.join("dist").join(l).join(filename);
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            sitemap.write(&path, minify(&html), &tag_groups[&(l.clone(), tag)], None);
         }
 
         let missal_path = cwd.join("dist").join(l).join(match l.as_str() {
@@ -3942,21 +4807,13 @@ fn main() -> Result<(), String> {
         });
 
         // Write missal
-        let _ = std::fs::write(missal_path, MISSAL.replace(
+        sitemap.write(&missal_path, MISSAL.replace(
             "let currentLanguage = \"en\"", 
             &format!("let currentLanguage = \"{l}\""),
-        ));
+        ), "missal", None);
 
         // Write rosary
-        let r = match l.as_str() {
-            "de" => "rosenkranz.html",
-            "en" => "rosary.html",
-            "fr" => "rosaire.html",
-            "es" => "rosario.html",
-            "br" => "rosario.html",
-            "pl" => "rozaniec.html",
-            _ => "rosary.html",
-        };
+        let r = &format!("{}.html", rosary_slug(l));
 
         let rosary_path = cwd.join("dist").join(l).join(r);
         let rosary_content = rosary::generate_rosary(
@@ -3972,7 +4829,7 @@ fn main() -> Result<(), String> {
             special_content: "<style>#special-contents { display:block !important; }</style>".to_string(),
         };
         let (filename, html) = special2html(l, &special_page, &meta_map).unwrap_or_default();
-        let _ = std::fs::write(rosary_path, &html);
+        sitemap.write(&rosary_path, &html, "rosary", None);
 
         // Write latin trainer
         let latin_file = match l.as_str() {
@@ -4011,7 +4868,7 @@ fn main() -> Result<(), String> {
             special_content: "<style>#special-contents { display:block !important; }</style>".to_string(),
         };
         let (filename, html) = special2html(l, &special_page, &meta_map).unwrap_or_default();
-        let _ = std::fs::write(latin_path, &html);
+        sitemap.write(&latin_path, &html, "latin", None);
     }
 
     // Write index + /search pages
@@ -4019,35 +4876,88 @@ fn main() -> Result<(), String> {
     for (lang, (_searchbar_html, search_html, search_js)) in si.iter() {
         let _ = std::fs::create_dir_all(cwd.join("dist").join(lang));
         let _ = std::fs::write(cwd.join("dist").join(lang).join("search.js"), search_js);
-        let _ = std::fs::write(cwd.join("dist").join(lang).join("search.html"), &minify(&search_html));
+        sitemap.write(&cwd.join("dist").join(lang).join("search.html"), minify(search_html), "search", None);
         let index_html = render_index_html(lang, &analyzed, &meta_map, &si)?;
-        let _ = std::fs::write(cwd.join("dist").join(&format!("{lang}.html")), &minify(&index_html));
+        sitemap.write(&cwd.join("dist").join(format!("{lang}.html")), minify(&index_html), "index", None);
     }
 
+    // Write 404 and offline pages
+    let searchbars = si.iter().map(|(l, (s, _, _))| (l.clone(), s.clone())).collect();
+    fallback::write_fallback_pages(&cwd.join("dist"), &analyzed, &analyzed_documents, &searchbars, &meta_map)?;
+
     // Generate map pages
-    resistance::generate_resistance_pages(&cwd, &meta_map)?;
+    resistance::generate_resistance_pages(&cwd, &meta_map, &mut sitemap)?;
 
     // Write gitignore
     let _ = std::fs::write(cwd.join(".gitignore"), generate_gitignore(&articles, &meta_map));
 
-    // Write serviceworker
-    let _ = std::fs::write(
-        cwd.join("dist").join("sw.js"),
-        gen_serviceworker_js(&cwd, &analyzed, &meta_map),
-    );
-
     // Write index.html and CNAME
+    sitemap.write(&cwd.join("dist").join("index.html"), INDEX, "index", None);
+    sitemap.write(&cwd.join("dist").join("death.html"), DEATH, "death", None);
     let _ = std::fs::write(
-        cwd.join("dist").join("index.html"),
-        INDEX,
+        cwd.join("dist").join("CNAME"),
+        "dubia.cc",
     );
-    let _ = std::fs::write(
-        cwd.join("dist").join("death.html"),
-        DEATH,
+
+    // Write redirect pages for old paths, then check all internal links
+    redirects.write(&cwd.join("dist"), &sitemap)?;
+
+    // Inline only the CSS each page uses, the rest comes from /site.css
+    let css_report = css::split_critical_css(&cwd.join("dist"), css::site_css())?;
+    for l in css_report.summary() {
+        println!("{l}");
+    }
+    css_report.write(&cwd.join("dist"))?;
+
+    // Copy /static with content-hashed names and point the generated files to them
+    let assets = assets::Assets::build(&cwd, &cwd.join("dist"))?;
+    assets.rewrite_dist(&cwd.join("dist"))?;
+    assets.write(&cwd.join("dist"))?;
+
+    // Write serviceworker, precaching the files that are now in /dist
+    let precache_config = precache::PrecacheJson::load(&cwd.join("config").join("precache.json"))?;
+    let docs_paths = meta_map
+        .strings
+        .keys()
+        .map(|l| Ok((l.clone(), get_string(&meta_map, l, "special-docs-path")?)))
+        .collect::<Result<BTreeMap<_, _>, String>>()?;
+    let precache = precache::Precache::collect(
+        &cwd,
+        &precache_config,
+        &[
+            audio::precache_urls(&analyzed),
+            meta_map.initials.precache_urls(&analyzed, &analyzed_documents),
+        ]
+        .concat(),
+        &docs_paths,
+        &assets,
     );
+    for l in precache.report() {
+        println!("{l}");
+    }
+    precache.write(&cwd.join("dist"))?;
     let _ = std::fs::write(
-        cwd.join("dist").join("CNAME"),
-        "dubia.cc",
+        cwd.join("dist").join("sw.js"),
+        assets.rewrite(&gen_serviceworker_js(&cwd, &analyzed, &precache, &docs_paths)),
     );
+
+    let links = linkcheck::check_links(&cwd.join("dist"), &redirects);
+    let pages = |p: &BTreeSet<String>| {
+        let first = p.iter().take(3).cloned().collect::<Vec<_>>().join(", ");
+        match p.len() {
+            0..=3 => first,
+            n => format!("{first} and {} more", n - 3),
+        }
+    };
+    for l in links.broken.iter() {
+        println!("broken link {} in {}", l.target, pages(&l.pages));
+    }
+    for l in links.redirected.iter() {
+        println!("link {} redirects to {}, update {}", l.target, l.redirect, pages(&l.pages));
+    }
+
+    // Write sitemap.xml and robots.txt
+    sitemap.write_sitemaps()?;
+
     Ok(())
 }
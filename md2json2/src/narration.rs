@@ -0,0 +1,462 @@
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::citation::xml_escape;
+use crate::docs::AnalyzedDocuments;
+use crate::rosary::{generate_rosary, RosaryMysteries, RosaryTemplates};
+use crate::sitemap::hreflang;
+use crate::{gen_section_id, get_root_href, get_string, uuid};
+use crate::{AnalyzedArticles, MetaJson, Paragraph, ParsedArticleAnalyzed, SentenceItem};
+
+// pauses after a segment, in milliseconds
+const PAUSE_PARAGRAPH: u32 = 600;
+const PAUSE_HEADING: u32 = 900;
+const PAUSE_QUOTE: u32 = 1000;
+const PAUSE_SECTION: u32 = 1500;
+const PAUSE_PRAYER: u32 = 2000;
+// between "Versicle" / "Response" and the spoken line
+const PAUSE_MARKER: u32 = 300;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentKind {
+    Title,
+    Heading,
+    Paragraph,
+    Quote,
+    Prayer,
+}
+
+/// One sentence of a segment, optionally started by a spoken
+/// versicle / response marker (from "[V]:" / "[R]:")
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    pub text: String,
+}
+
+/// One recorded unit: a heading, paragraph, quote or prayer. `id` stays the
+/// same as long as the text doesn't change, `anchor` is the section on the page
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    pub id: String,
+    pub kind: SegmentKind,
+    pub anchor: String,
+    pub lines: Vec<Line>,
+    pub pause_ms: u32,
+}
+
+/// Narration of one page, written as SSML, plain script and segment index
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Narration {
+    pub lang: String,
+    pub path: String,
+    pub url: String,
+    pub title: String,
+    pub segments: Vec<Segment>,
+}
+
+// collapses whitespace, drops inline HTML, bare URLs and the "..." of unfinished prayer lines
+fn clean(s: &str) -> String {
+    strip_tags(s)
+        .split_whitespace()
+        .filter(|w| !(w.starts_with("http://") || w.starts_with("https://") || w.starts_with("www.")))
+        .filter(|w| *w != "..." && *w != "…")
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("**", "")
+}
+
+// splits a text on "[V]:" / "[R]:" so that every versicle is its own line
+fn spoken_lines(lang: &str, text: &str, meta: &MetaJson) -> Vec<Line> {
+    let markers = [
+        ("[V]:", get_string(meta, lang, "narration-versicle").unwrap_or_default()),
+        ("[R]:", get_string(meta, lang, "narration-response").unwrap_or_default()),
+    ];
+
+    let mut lines = Vec::new();
+    let mut marker = None;
+    let mut rest = text;
+    loop {
+        let next = markers
+            .iter()
+            .filter_map(|(m, spoken)| rest.find(m).map(|p| (p, m.len(), spoken)))
+            .min_by_key(|(p, _, _)| *p);
+        let end = next.map(|(p, _, _)| p).unwrap_or(rest.len());
+        let text = clean(&rest[..end]);
+        if !text.is_empty() {
+            lines.push(Line { marker: marker.take().filter(|m: &String| !m.is_empty()), text });
+        }
+        let Some((p, len, spoken)) = next else {
+            break;
+        };
+        marker = Some(spoken.clone());
+        rest = &rest[p + len..];
+    }
+    lines
+}
+
+// link texts are spoken, URLs and footnote references are not
+fn sentence_text(s: &[SentenceItem]) -> String {
+    s.iter()
+        .map(|i| match i {
+            SentenceItem::Text { text } => text.as_str(),
+            SentenceItem::Link { l } => l.text.as_str(),
+            SentenceItem::Footnote { .. } => "",
+        })
+        .collect()
+}
+
+fn paragraph_lines(lang: &str, p: &Paragraph, meta: &MetaJson) -> Vec<Line> {
+    match p {
+        Paragraph::Sentence { s } | Paragraph::Markdown { s, .. } => {
+            spoken_lines(lang, &sentence_text(s), meta)
+        }
+        Paragraph::Quote { q } => {
+            let mut lines = spoken_lines(lang, &q.title, meta);
+            lines.extend(q.quote.iter().flat_map(|p| paragraph_lines(lang, p, meta)));
+            let attribution = [&q.author, &q.source]
+                .iter()
+                .filter_map(|l| l.as_ref().map(|l| clean(&l.text)))
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !attribution.is_empty() {
+                lines.push(Line { marker: None, text: attribution });
+            }
+            lines
+        }
+        Paragraph::Image { .. } => Vec::new(),
+    }
+}
+
+impl Narration {
+    fn new(lang: &str, path: &str, title: &str) -> Self {
+        Self {
+            lang: lang.to_string(),
+            path: path.to_string(),
+            url: format!("{}/{lang}/{path}", get_root_href()),
+            title: title.to_string(),
+            segments: Vec::new(),
+        }
+    }
+
+    // the id is derived from the text, repeated texts (litanies) are numbered
+    fn push(&mut self, kind: SegmentKind, anchor: &str, lines: Vec<Line>, pause_ms: u32) {
+        if lines.is_empty() {
+            return;
+        }
+        let text = lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n");
+        let seed = format!("{}/{}#{anchor}\n{text}", self.lang, self.path);
+        let ids = self.segments.iter().map(|s| s.id.clone()).collect::<BTreeSet<_>>();
+        let mut id = uuid(&seed);
+        let mut n = 1;
+        while ids.contains(&id) {
+            n += 1;
+            id = uuid(&format!("{seed}\n{n}"));
+        }
+        self.segments.push(Segment { id, kind, anchor: anchor.to_string(), lines, pause_ms });
+    }
+
+    // longer pause before headings and around quotes
+    fn pause_before(&mut self, ms: u32) {
+        if let Some(last) = self.segments.last_mut() {
+            last.pause_ms = last.pause_ms.max(ms);
+        }
+    }
+
+    /// Narration of an article or document, `path` is the page path without ".html"
+    pub fn from_article(lang: &str, path: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> Self {
+        let mut n = Self::new(lang, path, &a.title);
+        let title = vec![Line { marker: None, text: clean(&a.title) }];
+        n.push(SegmentKind::Title, "", title, PAUSE_SECTION);
+
+        for p in a.subtitle.iter().chain(a.summary.iter()) {
+            n.add_paragraph(lang, "", p, meta);
+        }
+        for s in a.sections.iter() {
+            let anchor = gen_section_id(&s.title);
+            n.pause_before(PAUSE_SECTION);
+            let heading = vec![Line { marker: None, text: clean(&s.title) }];
+            n.push(SegmentKind::Heading, &anchor, heading, PAUSE_HEADING);
+            for p in s.pars.iter() {
+                n.add_paragraph(lang, &anchor, p, meta);
+            }
+        }
+        n
+    }
+
+    fn add_paragraph(&mut self, lang: &str, anchor: &str, p: &Paragraph, meta: &MetaJson) {
+        let lines = paragraph_lines(lang, p, meta);
+        if let Paragraph::Quote { .. } = p {
+            if !lines.is_empty() {
+                self.pause_before(PAUSE_QUOTE);
+            }
+            self.push(SegmentKind::Quote, anchor, lines, PAUSE_QUOTE);
+        } else {
+            self.push(SegmentKind::Paragraph, anchor, lines, PAUSE_PARAGRAPH);
+        }
+    }
+
+    /// Narration of the rosary page: one segment per section of the rendered
+    /// page, with the section id as segment id, and the name of each mystery
+    /// announced before its Our Father
+    pub fn from_rosary(
+        lang: &str,
+        path: &str,
+        templates: &RosaryTemplates,
+        mysteries: &RosaryMysteries,
+        meta: &MetaJson,
+    ) -> Self {
+        let html = generate_rosary(lang, templates, mysteries, meta);
+        let title = get_string(meta, lang, "narration-rosary").unwrap_or_default();
+        let mut n = Self::new(lang, path, &title);
+        for (id, pars) in rosary_sections(&html) {
+            let decade = id
+                .strip_prefix("decade-")
+                .and_then(|s| s.strip_suffix("-ourfather"))
+                .and_then(|i| mysteries.mysteries.get(i));
+            if let Some(m) = decade {
+                let name = clean(&m.decade.get(lang).cloned().unwrap_or_default());
+                let lines = if name.is_empty() { Vec::new() } else { vec![Line { marker: None, text: name }] };
+                n.pause_before(PAUSE_SECTION);
+                n.push_with_id(&id.replace("-ourfather", ""), SegmentKind::Heading, &id, lines, PAUSE_HEADING);
+            }
+            let lines = pars.iter().flat_map(|p| spoken_lines(lang, p, meta)).collect();
+            n.push_with_id(&id, SegmentKind::Prayer, &id, lines, PAUSE_PRAYER);
+        }
+        n
+    }
+
+    fn push_with_id(&mut self, id: &str, kind: SegmentKind, anchor: &str, lines: Vec<Line>, pause_ms: u32) {
+        if lines.is_empty() || self.segments.iter().any(|s| s.id == id) {
+            return;
+        }
+        self.segments.push(Segment { id: id.to_string(), kind, anchor: anchor.to_string(), lines, pause_ms });
+    }
+
+    /// SSML 1.1 with a <mark> per segment, so that the timestamps reported by
+    /// the speech engine map back to the segment ids
+    pub fn to_ssml(&self) -> String {
+        let mut s = String::new();
+        s += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+        s += &format!(
+            "<speak version=\"1.1\" xmlns=\"http://www.w3.org/2001/10/synthesis\" xml:lang=\"{}\">\n",
+            hreflang(&self.lang)
+        );
+        for seg in self.segments.iter() {
+            s += &format!("<mark name=\"{}\"/>\n", xml_escape(&seg.id));
+            s += "<p>";
+            for l in seg.lines.iter() {
+                let text = match seg.kind {
+                    SegmentKind::Title | SegmentKind::Heading => {
+                        format!("<emphasis level=\"moderate\">{}</emphasis>", xml_escape(&l.text))
+                    }
+                    _ => xml_escape(&l.text),
+                };
+                match l.marker.as_ref() {
+                    Some(m) => {
+                        s += &format!("<s>{}<break time=\"{PAUSE_MARKER}ms\"/>{text}</s>", xml_escape(m))
+                    }
+                    None => s += &format!("<s>{text}</s>"),
+                }
+            }
+            s += "</p>\n";
+            s += &format!("<break time=\"{}ms\"/>\n", seg.pause_ms);
+        }
+        s += "</speak>\n";
+        s
+    }
+
+    /// Plain narration script for reading aloud or for engines without SSML
+    pub fn to_script(&self) -> String {
+        self.segments
+            .iter()
+            .map(|seg| {
+                let lines = seg
+                    .lines
+                    .iter()
+                    .map(|l| match l.marker.as_ref() {
+                        Some(m) => format!("{m}: {}", l.text),
+                        None => l.text.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("[{}]\n{lines}\n", seg.id)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Writes /{lang}/{path}.ssml, .narration.txt and .narration.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let base = dist.join(&self.lang).join(&self.path);
+        if let Some(parent) = base.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let files = [
+            ("ssml", self.to_ssml()),
+            ("narration.txt", self.to_script()),
+            ("narration.json", json),
+        ];
+        for (ext, contents) in files {
+            let path = base.with_file_name(format!("{}.{ext}", self.path.rsplit('/').next().unwrap_or_default()));
+            std::fs::write(&path, contents).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn strip_tags(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        out += &rest[..start];
+        match rest[start..].split_once('>') {
+            Some((_, r)) => rest = r,
+            // a "<" in the text, not a tag
+            None => return out + &rest[start..],
+        }
+    }
+    out + rest
+}
+
+// text of an HTML fragment, links are dropped with their text
+fn html_text(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("<a ") {
+        out += &rest[..start];
+        rest = rest[start..].split_once("</a>").map(|(_, r)| r).unwrap_or_default();
+    }
+    decode_entities(&strip_tags(&(out + rest)))
+}
+
+// (section id, paragraph texts) of the sections with a prayer, skips the navigation
+fn rosary_sections(html: &str) -> Vec<(String, Vec<String>)> {
+    html.split("<section id=\"")
+        .skip(1)
+        .filter_map(|s| {
+            let (id, body) = s.split_once('"')?;
+            let body = body.split("</section>").next()?;
+            if !body.contains("class=\"rosary-img\"") {
+                return None;
+            }
+            let pars = body
+                .split("<p class=\"first-graf")
+                .skip(1)
+                .filter_map(|p| Some(html_text(&format!("<p{}", p.split_once("</p>")?.0))))
+                .collect::<Vec<_>>();
+            Some((id.to_string(), pars))
+        })
+        .collect()
+}
+
+pub fn generate_narrations(
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    meta: &MetaJson,
+) -> Result<BTreeMap<String, Vec<Narration>>, String> {
+    let mut narrations = BTreeMap::new();
+
+    for lang in meta.strings.keys() {
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
+        let mut v = Vec::new();
+
+        for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+            v.push(Narration::from_article(lang, slug, a, meta));
+        }
+
+        for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+            for (slug, d) in docs {
+                let path = format!("{docs_path}/{collection}/{slug}");
+                v.push(Narration::from_article(lang, &path, d, meta));
+            }
+        }
+
+        let rosary = Narration::from_rosary(
+            lang,
+            crate::rosary_slug(lang),
+            &crate::rosary_template(lang),
+            &crate::rosary_mysteries(),
+            meta,
+        );
+        if !rosary.segments.is_empty() {
+            v.push(rosary);
+        }
+
+        narrations.insert(lang.clone(), v);
+    }
+
+    Ok(narrations)
+}
+
+#[test]
+fn test_narration() {
+    use crate::{ArticleSection, Link, Quote};
+
+    let text = |t: &str| SentenceItem::Text { text: t.to_string() };
+    let a = ParsedArticleAnalyzed {
+        title: "Angelus".to_string(),
+        summary: vec![Paragraph::Sentence {
+            s: vec![
+                text("[V]: Angelus Domini nuntiavit Mariae. [R]: Et concepit "),
+                SentenceItem::Link { l: Link { text: "de Spiritu Sancto".to_string(), href: "https://example.com".to_string(), ..Default::default() } },
+                SentenceItem::Footnote { id: "1".to_string() },
+                text(". See https://example.com"),
+            ],
+        }],
+        sections: vec![ArticleSection {
+            title: "Oratio".to_string(),
+            indent: 2,
+            pars: vec![
+                Paragraph::Sentence { s: vec![text("Amen.")] },
+                Paragraph::Quote { q: Quote { quote: vec![Paragraph::Sentence { s: vec![text("Amen.")] }], author: Some(Link { text: "Pius X".to_string(), ..Default::default() }), ..Default::default() } },
+                Paragraph::Sentence { s: vec![text("Amen.")] },
+            ],
+        }],
+        ..Default::default()
+    };
+
+    let n = Narration::from_article("la", "angelus", &a, &MetaJson::default());
+    let kinds = n.segments.iter().map(|s| s.kind).collect::<Vec<_>>();
+    let expected = [
+        SegmentKind::Title,
+        SegmentKind::Paragraph,
+        SegmentKind::Heading,
+        SegmentKind::Paragraph,
+        SegmentKind::Quote,
+        SegmentKind::Paragraph,
+    ];
+    assert_eq!(kinds, expected);
+
+    // markers split the lines, link text is spoken, footnotes and URLs are not
+    let texts = n.segments[1].lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(texts, vec!["Angelus Domini nuntiavit Mariae.", "Et concepit de Spiritu Sancto. See"]);
+
+    // pauses before the heading and around the quote
+    assert_eq!(n.segments[1].pause_ms, PAUSE_SECTION);
+    assert_eq!(n.segments[3].pause_ms, PAUSE_QUOTE);
+    assert_eq!(n.segments[4].lines[1].text, "Pius X");
+
+    // same text in the same section gets its own id, ids stay the same between builds
+    assert_ne!(n.segments[3].id, n.segments[5].id);
+    assert_eq!(n, Narration::from_article("la", "angelus", &a, &MetaJson::default()));
+
+    let ssml = n.to_ssml();
+    assert!(ssml.contains("xml:lang=\"la\""));
+    assert!(ssml.contains(&format!("<mark name=\"{}\"/>", n.segments[3].id)));
+    assert!(!ssml.contains("example.com"));
+}
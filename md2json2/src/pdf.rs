@@ -0,0 +1,1289 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+
+use pdf_writer::types::{ActionType, AnnotationType, CidFontType, FontFlags, SystemInfo};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use ttf_parser::{Face, GlyphId};
+
+use crate::docs::AnalyzedDocuments;
+use crate::{get_root_href, get_string};
+use crate::{AnalyzedArticles, ImageAlignment, MetaJson, Paragraph, ParsedArticleAnalyzed, SentenceItem};
+
+// indices into the loaded fonts, the fallback faces follow in the same
+// order, then the Kanzlei initials in A-Z order
+const REGULAR: usize = 0;
+const ITALIC: usize = 1;
+const BOLD: usize = 2;
+const BOLD_ITALIC: usize = 3;
+const FALLBACK: usize = 4;
+const INITIALS: usize = 8;
+
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PageSize {
+    A4,
+    A5,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 2] = [PageSize::A4, PageSize::A5];
+
+    // width, height in pt
+    fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::A5 => (419.53, 595.28),
+        }
+    }
+
+    // top / bottom, left / right
+    fn margins(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (62.0, 68.0),
+            PageSize::A5 => (44.0, 46.0),
+        }
+    }
+
+    fn font_size(&self) -> f32 {
+        match self {
+            PageSize::A4 => 11.0,
+            PageSize::A5 => 9.5,
+        }
+    }
+
+    /// Path of the PDF relative to /{lang}, without the .pdf extension
+    ///
+    /// page_path: path of the page relative to /{lang} ("angelus", "dokumente/lefebvre/x")
+    pub fn path(&self, page_path: &str) -> String {
+        match self {
+            PageSize::A4 => format!("pdf/{page_path}"),
+            PageSize::A5 => format!("pdf/{page_path}-a5"),
+        }
+    }
+
+    pub fn url(&self, lang: &str, page_path: &str) -> String {
+        format!("{}/{lang}/{}.pdf", get_root_href(), self.path(page_path))
+    }
+}
+
+/// The site fonts from /static/font and the fallback faces of
+/// config/fonts.json for the characters the "BASIC" fonts don't have
+pub struct FontFiles {
+    files: Vec<Vec<u8>>,
+}
+
+impl FontFiles {
+    pub fn load(cwd: &Path) -> Result<Self, String> {
+        let dir = cwd.join("static").join("font");
+        let config = crate::fonts::FontsJson::load(&cwd.join("config").join("fonts.json"))?;
+        let mut paths = [
+            "SourceSerifPro-BASIC-Regular.ttf",
+            "SourceSerifPro-BASIC-RegularItalic.ttf",
+            "SourceSerifPro-BASIC-Bold.ttf",
+            "SourceSerifPro-BASIC-BoldItalic.ttf",
+        ]
+        .iter()
+        .map(|f| dir.join("ssfp").join(f))
+        .collect::<Vec<_>>();
+        // without fallback faces the slots get the "BASIC" faces again
+        let fallback = match config.pdf_fallback.len() {
+            0 => paths.clone(),
+            4 => config.pdf_fallback.iter().map(|f| cwd.join(f)).collect(),
+            _ => return Err("config/fonts.json: pdf-fallback needs a regular, italic, bold and bold italic face".to_string()),
+        };
+        paths.extend(fallback);
+        paths.extend(('A'..='Z').map(|c| dir.join("kanzlei").join(format!("Kanzlei-Initialen-{c}.ttf"))));
+
+        let files = paths
+            .iter()
+            .map(|p| std::fs::read(p).map_err(|e| format!("{}: {e}", p.display())))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { files })
+    }
+
+    /// The characters of `chars` that the fonts can print in every style,
+    /// see missing_chars
+    pub fn printable_chars(&self, chars: impl IntoIterator<Item = char>) -> Result<BTreeSet<char>, String> {
+        let faces = self.faces()?;
+        let printable = |c: char| {
+            (REGULAR..FALLBACK).all(|font| glyphs(&faces, font, &c.to_string()).iter().all(|(_, _, g)| g.is_some()))
+        };
+        Ok(chars.into_iter().filter(|c| printable(*c)).collect())
+    }
+
+    fn faces(&self) -> Result<Vec<Face<'_>>, String> {
+        self.files
+            .iter()
+            .map(|f| Face::parse(f, 0).map_err(|e| e.to_string()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    size: f32,
+    // superscript (footnote references)
+    sup: bool,
+}
+
+impl Style {
+    fn new(size: f32) -> Self {
+        Self { bold: false, italic: false, size, sup: false }
+    }
+
+    fn font(&self) -> usize {
+        match (self.bold, self.italic) {
+            (false, false) => REGULAR,
+            (false, true) => ITALIC,
+            (true, false) => BOLD,
+            (true, true) => BOLD_ITALIC,
+        }
+    }
+
+    fn glyph_size(&self) -> f32 {
+        if self.sup { self.size * 0.65 } else { self.size }
+    }
+
+    fn rise(&self) -> f32 {
+        if self.sup { self.size * 0.38 } else { 0.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inline {
+    Text(String),
+    // text, href
+    Link(String, String),
+    Note(String),
+}
+
+// part of a word with the same style
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    style: Style,
+    text: String,
+    width: f32,
+    link: Option<String>,
+    note: Option<String>,
+}
+
+type Word = Vec<Piece>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Align {
+    Justify,
+    Left,
+    Center,
+}
+
+// text at an absolute position: x from the left, y (baseline) from the top
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    x: f32,
+    y: f32,
+    font: usize,
+    size: f32,
+    text: String,
+}
+
+/// Decoded image, embedded as flate compressed RGB
+#[derive(Debug, Clone, PartialEq)]
+struct PdfImage {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+// [x, y, w, h] rectangle, y of the top edge
+type Area = [f32; 4];
+
+/// Laid out line (or block: image, QR code), all positions relative to the top left
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Line {
+    spans: Vec<Span>,
+    // filled rectangles (QR code modules)
+    rects: Vec<Area>,
+    // x1, x2, y
+    rules: Vec<[f32; 3]>,
+    links: Vec<(Area, String)>,
+    images: Vec<(Area, Rc<PdfImage>)>,
+    height: f32,
+    space_before: f32,
+    // footnotes referenced in this line
+    notes: Vec<String>,
+    // don't put a page break after this line (headings, dropcaps)
+    keep_with_next: bool,
+}
+
+impl Line {
+    fn spacer(height: f32) -> Self {
+        Self { height, ..Default::default() }
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        for s in self.spans.iter_mut() {
+            s.x += dx;
+            s.y += dy;
+        }
+        for r in self.rects.iter_mut().chain(self.links.iter_mut().map(|(r, _)| r)).chain(self.images.iter_mut().map(|(r, _)| r)) {
+            r[0] += dx;
+            r[1] += dy;
+        }
+        for r in self.rules.iter_mut() {
+            r[0] += dx;
+            r[1] += dx;
+            r[2] += dy;
+        }
+    }
+}
+
+/// One finished page, positions are absolute (from the top left)
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Page {
+    content: Line,
+}
+
+impl Page {
+    fn add(&mut self, mut line: Line, x: f32, y: f32) {
+        line.translate(x, y);
+        self.content.spans.append(&mut line.spans);
+        self.content.rects.append(&mut line.rects);
+        self.content.rules.append(&mut line.rules);
+        self.content.links.append(&mut line.links);
+        self.content.images.append(&mut line.images);
+    }
+}
+
+// Markdown emphasis: "**bold**", "*italic*" => (text, bold, italic)
+fn emphasis(text: &str) -> Vec<(String, bool, bool)> {
+    let mut out = Vec::new();
+    let (mut bold, mut italic) = (false, false);
+    let mut cur = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '*' {
+            cur.push(c);
+            continue;
+        }
+        if !cur.is_empty() {
+            out.push((std::mem::take(&mut cur), bold, italic));
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            bold = !bold;
+        } else {
+            italic = !italic;
+        }
+    }
+    if !cur.is_empty() {
+        out.push((cur, bold, italic));
+    }
+    out
+}
+
+// replacements for common characters that the "BASIC" fonts don't have
+fn fallback(c: char) -> Option<&'static str> {
+    Some(match c {
+        '-' | '‑' => "‐",
+        '„' => "“",
+        '‟' => "”",
+        '‚' => "‘",
+        '…' => "...",
+        '†' | '✠' => "+",
+        'œ' => "oe",
+        'Œ' => "OE",
+        '℟' => "R",
+        '℣' => "V",
+        '\u{a0}' | '\u{202f}' => " ",
+        '\u{ad}' | '\u{200b}' => "",
+        _ => return None,
+    })
+}
+
+// (char, font, glyph) of the text in `font`: characters missing in the font
+// are replaced by their fallback, else taken from the fallback face
+fn glyphs(faces: &[Face<'_>], font: usize, text: &str) -> Vec<(char, usize, Option<GlyphId>)> {
+    let face = &faces[font];
+    let fallback_face = faces.get(font + FALLBACK).filter(|_| font < FALLBACK);
+    let mut out = Vec::new();
+    for c in text.chars() {
+        if let Some(g) = face.glyph_index(c) {
+            out.push((c, font, Some(g)));
+        } else if let Some(f) = fallback(c).filter(|f| f.chars().all(|c| face.glyph_index(c).is_some())) {
+            out.extend(f.chars().map(|c| (c, font, face.glyph_index(c))));
+        } else if let Some(g) = fallback_face.and_then(|f| f.glyph_index(c)) {
+            out.push((c, font + FALLBACK, Some(g)));
+        } else {
+            out.push((c, font, None));
+        }
+    }
+    out
+}
+
+fn absolute_href(href: &str) -> String {
+    match href.starts_with('/') {
+        true => format!("{}{href}", get_root_href()),
+        false => href.to_string(),
+    }
+}
+
+fn inlines(s: &[SentenceItem]) -> Vec<Inline> {
+    s.iter()
+        .map(|i| match i {
+            SentenceItem::Text { text } => Inline::Text(text.replace("[R]: ", "℟. ").replace("[V]: ", "℣. ")),
+            SentenceItem::Link { l } => Inline::Link(l.text.clone(), absolute_href(&l.href)),
+            SentenceItem::Footnote { id } => Inline::Note(id.clone()),
+        })
+        .collect()
+}
+
+/// Lays out one article for one page size
+struct Layouter<'a> {
+    faces: &'a [Face<'a>],
+    size: PageSize,
+    // text width
+    width: f32,
+    // font size of the body text
+    base: f32,
+}
+
+impl<'a> Layouter<'a> {
+    fn new(faces: &'a [Face<'a>], size: PageSize) -> Self {
+        let (w, _) = size.dimensions();
+        let (_, ml) = size.margins();
+        Self {
+            faces,
+            size,
+            width: w - 2.0 * ml,
+            base: size.font_size(),
+        }
+    }
+
+    fn leading(&self, size: f32) -> f32 {
+        size * 1.4
+    }
+
+    fn measure(&self, font: usize, text: &str, size: f32) -> f32 {
+        let w = glyphs(self.faces, font, text)
+            .into_iter()
+            .filter_map(|(_, f, g)| {
+                let face = &self.faces[f];
+                Some(face.glyph_hor_advance(g?)? as f32 / face.units_per_em() as f32)
+            })
+            .sum::<f32>();
+        w * size
+    }
+
+    fn words(&mut self, inlines: &[Inline], base: Style) -> Vec<Word> {
+        fn push(word: &mut Word, c: char, style: Style, link: &Option<String>) {
+            match word.last_mut() {
+                Some(p) if p.style == style && p.link == *link && p.note.is_none() => p.text.push(c),
+                _ => word.push(Piece {
+                    style,
+                    text: c.to_string(),
+                    width: 0.0,
+                    link: link.clone(),
+                    note: None,
+                }),
+            }
+        }
+
+        let mut words = Vec::new();
+        let mut cur = Word::new();
+        for i in inlines {
+            let (text, link) = match i {
+                Inline::Text(t) => (t, None),
+                Inline::Link(t, href) => (t, Some(href.clone())),
+                Inline::Note(id) => {
+                    cur.push(Piece {
+                        style: Style { sup: true, ..base },
+                        text: id.clone(),
+                        width: 0.0,
+                        link: None,
+                        note: Some(id.clone()),
+                    });
+                    continue;
+                }
+            };
+            for (seg, bold, italic) in emphasis(text) {
+                let style = Style {
+                    bold: base.bold || bold,
+                    italic: base.italic ^ italic,
+                    ..base
+                };
+                for c in seg.chars() {
+                    if c.is_whitespace() {
+                        if !cur.is_empty() {
+                            words.push(std::mem::take(&mut cur));
+                        }
+                    } else {
+                        push(&mut cur, c, style, &link);
+                    }
+                }
+            }
+        }
+        if !cur.is_empty() {
+            words.push(cur);
+        }
+
+        for w in words.iter_mut() {
+            for p in w.iter_mut() {
+                p.width = self.measure(p.style.font(), &p.text, p.style.glyph_size());
+            }
+        }
+        words
+    }
+
+    // splits words that are wider than the line (URLs) into parts
+    fn split_long(&mut self, word: Word, width: f32) -> Vec<Word> {
+        if word.iter().map(|p| p.width).sum::<f32>() <= width {
+            return vec![word];
+        }
+        let mut parts = Vec::new();
+        let mut cur = Word::new();
+        let mut cur_w = 0.0;
+        for p in word {
+            for c in p.text.chars() {
+                let w = self.measure(p.style.font(), &c.to_string(), p.style.glyph_size());
+                if cur_w + w > width && !cur.is_empty() {
+                    parts.push(std::mem::take(&mut cur));
+                    cur_w = 0.0;
+                }
+                match cur.last_mut() {
+                    Some(last) if last.style == p.style && last.link == p.link => {
+                        last.text.push(c);
+                        last.width += w;
+                    }
+                    _ => cur.push(Piece { text: c.to_string(), width: w, ..p.clone() }),
+                }
+                cur_w += w;
+            }
+        }
+        if !cur.is_empty() {
+            parts.push(cur);
+        }
+        parts
+    }
+
+    // greedy line breaking, indents: left indent of the first lines (dropcap)
+    fn lines(&mut self, words: Vec<Word>, width: f32, indents: &[f32], align: Align, size: f32) -> Vec<Line> {
+        let space = self.measure(REGULAR, " ", size);
+        let leading = self.leading(size);
+        let indent = |i: usize| indents.get(i).copied().unwrap_or_default();
+
+        let mut rows: Vec<Vec<Word>> = Vec::new();
+        let mut cur: Vec<Word> = Vec::new();
+        let mut cur_w = 0.0;
+        for word in words {
+            for word in self.split_long(word, width - indent(rows.len())) {
+                let w = word.iter().map(|p| p.width).sum::<f32>();
+                let avail = width - indent(rows.len());
+                if !cur.is_empty() && cur_w + space + w > avail {
+                    rows.push(std::mem::take(&mut cur));
+                    cur_w = 0.0;
+                }
+                cur_w += if cur.is_empty() { w } else { space + w };
+                cur.push(word);
+            }
+        }
+        if !cur.is_empty() {
+            rows.push(cur);
+        }
+
+        let count = rows.len();
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let avail = width - indent(i);
+                let natural = row.iter().flatten().map(|p| p.width).sum::<f32>() + space * (row.len().saturating_sub(1)) as f32;
+                let (mut x, gap) = match align {
+                    Align::Justify if i + 1 < count && row.len() > 1 => {
+                        (indent(i), space + (avail - natural) / (row.len() - 1) as f32)
+                    }
+                    Align::Center => (indent(i) + (avail - natural) / 2.0, space),
+                    _ => (indent(i), space),
+                };
+                let baseline = leading * 0.78;
+                let mut line = Line { height: leading, ..Default::default() };
+                for word in row {
+                    for p in word {
+                        if let Some(href) = p.link.as_ref() {
+                            line.links.push(([x, baseline - p.style.size, p.width, p.style.size * 1.2], href.clone()));
+                        }
+                        if let Some(n) = p.note.as_ref() {
+                            line.notes.push(n.clone());
+                        }
+                        line.spans.push(Span {
+                            x,
+                            y: baseline - p.style.rise(),
+                            font: p.style.font(),
+                            size: p.style.glyph_size(),
+                            text: p.text,
+                        });
+                        x += p.width;
+                    }
+                    x += gap;
+                }
+                line
+            })
+            .collect()
+    }
+
+    fn paragraph(&mut self, inlines: &[Inline], style: Style, align: Align, indent: f32, space_before: f32) -> Vec<Line> {
+        let words = self.words(inlines, style);
+        let mut lines = self.lines(words, self.width - indent, &[], align, style.size);
+        for l in lines.iter_mut() {
+            l.translate(indent, 0.0);
+        }
+        if let Some(first) = lines.first_mut() {
+            first.space_before = space_before;
+        }
+        lines
+    }
+
+    // first paragraph with a Kanzlei initial over three lines
+    fn dropcap_paragraph(&mut self, inlines: &[Inline]) -> Option<Vec<Line>> {
+        let Some(Inline::Text(first)) = inlines.first() else {
+            return None;
+        };
+        let c = first.chars().next()?.to_ascii_uppercase();
+        if !c.is_ascii_uppercase() {
+            return None;
+        }
+        let font = INITIALS + (c as u8 - b'A') as usize;
+        let leading = self.leading(self.base);
+        let size = leading * 3.0;
+        let w = self.measure(font, &c.to_string(), size) + 4.0;
+
+        let mut rest = inlines.to_vec();
+        rest[0] = Inline::Text(first.chars().skip(1).collect());
+        let words = self.words(&rest, Style::new(self.base));
+        let mut lines = self.lines(words, self.width, &[w, w, w], Align::Justify, self.base);
+        while lines.len() < 3 {
+            lines.push(Line::spacer(leading));
+        }
+        lines[0].spans.push(Span {
+            x: 0.0,
+            y: leading * 2.78,
+            font,
+            size,
+            text: c.to_string(),
+        });
+        lines[0].keep_with_next = true;
+        lines[1].keep_with_next = true;
+        Some(lines)
+    }
+
+    fn image(&mut self, i: &crate::Image, img_dir: &Path) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let decoded = std::fs::read(img_dir.join(&i.href))
+            .ok()
+            .and_then(|b| image::load_from_memory(&b).ok());
+        if let Some(img) = decoded {
+            let rgba = img.to_rgba8();
+            // transparent pixels on white paper
+            let rgb = rgba
+                .pixels()
+                .flat_map(|p| {
+                    let a = p[3] as u32;
+                    [0, 1, 2].map(|c| ((p[c] as u32 * a + 255 * (255 - a)) / 255) as u8)
+                })
+                .collect::<Vec<_>>();
+            let max_w = match i.inline.unwrap_or(ImageAlignment::FullWidth) {
+                ImageAlignment::FullWidth => self.width,
+                ImageAlignment::Left(px) | ImageAlignment::Right(px) => (px as f32 * 0.75).min(self.width),
+            };
+            let (_, h) = self.size.dimensions();
+            let ratio = rgba.height() as f32 / rgba.width().max(1) as f32;
+            let mut w = max_w;
+            if w * ratio > h * 0.4 {
+                w = h * 0.4 / ratio;
+            }
+            let image = PdfImage { width: rgba.width(), height: rgba.height(), rgb };
+            lines.push(Line {
+                images: vec![([(self.width - w) / 2.0, 0.0, w, w * ratio], Rc::new(image))],
+                height: w * ratio + 4.0,
+                space_before: self.base * 0.6,
+                ..Default::default()
+            });
+        }
+        if !i.title.is_empty() {
+            let style = Style { italic: true, ..Style::new(self.base * 0.85) };
+            let mut caption = self.paragraph(&[Inline::Text(i.title.clone())], style, Align::Center, 0.0, 2.0);
+            if let Some(l) = lines.last_mut() {
+                l.keep_with_next = true;
+            }
+            lines.append(&mut caption);
+        }
+        lines
+    }
+
+    fn block(&mut self, p: &Paragraph, img_dir: &Path, indent: f32, quote: bool) -> Vec<Line> {
+        let size = if quote { self.base * 0.92 } else { self.base };
+        let space = self.base * 0.45;
+        match p {
+            Paragraph::Sentence { s } | Paragraph::Markdown { s, .. } => {
+                self.paragraph(&inlines(s), Style::new(size), Align::Justify, indent, space)
+            }
+            Paragraph::Quote { q } => {
+                let mut lines = Vec::new();
+                let indent = indent + self.base * 1.6;
+                if !q.title.is_empty() {
+                    let style = Style { bold: true, ..Style::new(size) };
+                    lines.extend(self.paragraph(&[Inline::Text(q.title.clone())], style, Align::Left, indent, space));
+                }
+                for p in q.quote.iter() {
+                    lines.extend(self.block(p, img_dir, indent, true));
+                }
+                let attribution = [q.author.as_ref(), q.source.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|l| Inline::Link(l.text.clone(), absolute_href(&l.href)))
+                    .collect::<Vec<_>>();
+                if !attribution.is_empty() {
+                    let mut inl = vec![Inline::Text("— ".to_string())];
+                    for (i, a) in attribution.into_iter().enumerate() {
+                        if i > 0 {
+                            inl.push(Inline::Text(", ".to_string()));
+                        }
+                        inl.push(a);
+                    }
+                    let style = Style { italic: true, ..Style::new(size) };
+                    lines.extend(self.paragraph(&inl, style, Align::Left, indent, 2.0));
+                }
+                if let Some(first) = lines.first_mut() {
+                    first.space_before = self.base * 0.8;
+                }
+                if let Some(last) = lines.last_mut() {
+                    last.height += self.base * 0.4;
+                }
+                lines
+            }
+            Paragraph::Image { i } => self.image(i, img_dir),
+        }
+    }
+
+    fn heading(&mut self, title: &str, level: usize) -> Vec<Line> {
+        let size = match level {
+            0 | 1 => self.base * 1.9,
+            2 => self.base * 1.35,
+            3 => self.base * 1.15,
+            _ => self.base,
+        };
+        let style = Style { bold: true, ..Style::new(size) };
+        let space = if level <= 1 { 0.0 } else { self.base * 1.3 };
+        let mut lines = self.paragraph(&[Inline::Text(title.to_string())], style, Align::Left, 0.0, space);
+        for l in lines.iter_mut() {
+            l.keep_with_next = true;
+        }
+        if let Some(l) = lines.last_mut() {
+            l.height += self.base * 0.3;
+        }
+        lines
+    }
+
+    fn footnote(&mut self, id: &str, text: &[SentenceItem]) -> Vec<Line> {
+        let size = self.base * 0.8;
+        let mut inl = vec![Inline::Text(format!("{id} "))];
+        inl.extend(inlines(text));
+        self.paragraph(&inl, Style::new(size), Align::Justify, 0.0, 0.0)
+    }
+
+    fn qr_code(&mut self, url: &str, label: &str) -> Vec<Line> {
+        let Ok(code) = qrcode::QrCode::new(url.as_bytes()) else {
+            return Vec::new();
+        };
+        let size = self.base * 6.0;
+        let n = code.width();
+        let module = size / n as f32;
+        let colors = code.to_colors();
+        let mut line = Line {
+            height: size,
+            space_before: self.base * 2.0,
+            ..Default::default()
+        };
+        for (i, c) in colors.iter().enumerate() {
+            if *c == qrcode::Color::Dark {
+                let (x, y) = ((i % n) as f32, (i / n) as f32);
+                line.rects.push([x * module, y * module, module, module]);
+            }
+        }
+        line.links.push(([0.0, 0.0, size, size], url.to_string()));
+
+        let style = Style::new(self.base * 0.8);
+        let inl = [Inline::Text(label.to_string()), Inline::Link(url.to_string(), url.to_string())];
+        let words = self.words(&inl, style);
+        let text = self.lines(words, self.width - size - 12.0, &[], Align::Left, style.size);
+        for (i, mut t) in text.into_iter().enumerate() {
+            t.translate(size + 12.0, size / 2.0 - self.leading(style.size) + i as f32 * self.leading(style.size));
+            line.spans.append(&mut t.spans);
+            line.links.append(&mut t.links);
+        }
+        vec![line]
+    }
+
+    // distributes the lines on pages, footnotes go to the bottom of the page
+    // with the first reference
+    fn paginate(&mut self, body: Vec<Line>, notes: &BTreeMap<String, Vec<Line>>, title: &str, header: &str) -> Vec<Page> {
+        let (w, h) = self.size.dimensions();
+        let (mt, ml) = self.size.margins();
+        let (top, bottom) = (mt, h - mt);
+        let note_sep = self.base;
+        let notes_height = |ids: &[String]| -> f32 {
+            ids.iter()
+                .filter_map(|id| notes.get(id))
+                .flatten()
+                .map(|l| l.height)
+                .sum()
+        };
+
+        let mut pages = Vec::new();
+        let mut page = Page::default();
+        let mut cursor = top;
+        let mut page_notes: Vec<String> = Vec::new();
+        let mut placed = BTreeSet::new();
+
+        let finish = |page: &mut Page, page_notes: &mut Vec<String>, pages: &mut Vec<Page>| {
+            let mut y = bottom - notes_height(page_notes);
+            if !page_notes.is_empty() {
+                page.content.rules.push([ml, ml + 80.0, y - note_sep * 0.5]);
+            }
+            for id in page_notes.drain(..) {
+                for l in notes.get(&id).into_iter().flatten() {
+                    page.add(l.clone(), ml, y);
+                    y += l.height;
+                }
+            }
+            pages.push(std::mem::take(page));
+        };
+
+        let mut i = 0;
+        while i < body.len() {
+            // lines that have to stay together: headings with the next line,
+            // the three lines next to a dropcap
+            let mut group = vec![&body[i]];
+            while group.last().map(|l| l.keep_with_next).unwrap_or(false) && i + group.len() < body.len() {
+                group.push(&body[i + group.len()]);
+            }
+
+            let new_notes = group
+                .iter()
+                .flat_map(|l| l.notes.iter())
+                .filter(|n| notes.contains_key(*n) && !placed.contains(*n) && !page_notes.contains(*n))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let mut need = group.iter().map(|l| l.space_before + l.height).sum::<f32>();
+            if cursor == top {
+                need -= group[0].space_before;
+            }
+            let mut notes_need = notes_height(&page_notes) + notes_height(&new_notes);
+            if notes_need > 0.0 {
+                notes_need += note_sep;
+            }
+            if cursor > top && cursor + need + notes_need > bottom {
+                finish(&mut page, &mut page_notes, &mut pages);
+                cursor = top;
+            }
+
+            for (j, l) in group.iter().enumerate() {
+                if cursor > top || j > 0 {
+                    cursor += l.space_before;
+                }
+                page.add((*l).clone(), ml, cursor);
+                cursor += l.height;
+            }
+            for n in new_notes {
+                placed.insert(n.clone());
+                page_notes.push(n);
+            }
+            i += group.len();
+        }
+        finish(&mut page, &mut page_notes, &mut pages);
+
+        // running headers from the second page on, page numbers on all pages
+        let size = self.base * 0.75;
+        let count = pages.len();
+        for (n, p) in pages.iter_mut().enumerate() {
+            if n > 0 {
+                let y = top - self.base * 1.6;
+                let mut title = title.to_string();
+                while self.measure(ITALIC, &title, size) > (w - 2.0 * ml) * 0.6 && title.chars().count() > 1 {
+                    title = title.chars().take(title.chars().count() - 2).collect::<String>() + "…";
+                }
+                let tw = self.measure(ITALIC, &title, size);
+                p.content.spans.push(Span { x: ml, y, font: REGULAR, size, text: header.to_string() });
+                p.content.spans.push(Span { x: w - ml - tw, y, font: ITALIC, size, text: title });
+                p.content.rules.push([ml, w - ml, y + size * 0.5]);
+            }
+            let number = format!("{} / {count}", n + 1);
+            let nw = self.measure(REGULAR, &number, size);
+            p.content.spans.push(Span { x: (w - nw) / 2.0, y: bottom + mt * 0.5, font: REGULAR, size, text: number });
+        }
+
+        pages
+    }
+}
+
+/// Characters of the page that the PDF fonts can't print in every style
+/// (MetaJson::pdf_chars)
+pub fn missing_chars(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> BTreeSet<char> {
+    let mut text = a.get_chars().into_iter().collect::<String>();
+    text.push_str(&get_string(meta, lang, "pdf-online").unwrap_or_default());
+    text.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control() && !meta.pdf_chars.contains(c))
+        .collect()
+}
+
+/// Whether the page gets a PDF: pages with characters that no font has
+/// are skipped instead of printed with empty boxes
+pub fn printable(lang: &str, a: &ParsedArticleAnalyzed, meta: &MetaJson) -> bool {
+    missing_chars(lang, a, meta).is_empty()
+}
+
+fn render(
+    faces: &[Face<'_>],
+    size: PageSize,
+    lang: &str,
+    url: &str,
+    a: &ParsedArticleAnalyzed,
+    img_dir: &Path,
+    meta: &MetaJson,
+) -> Result<Vec<u8>, String> {
+    let mut l = Layouter::new(faces, size);
+    let base = l.base;
+    let mut body = Vec::new();
+
+    body.extend(l.heading(&a.title, 1));
+    for p in a.subtitle.iter() {
+        let style = Style { italic: true, ..Style::new(base * 1.1) };
+        body.extend(l.paragraph(&inlines(p.as_sentence().unwrap_or_default()), style, Align::Left, 0.0, 0.0));
+    }
+    let authors = a
+        .authors
+        .iter()
+        .map(|id| meta.authors.get(id).map(|q| q.displayname.clone()).unwrap_or_else(|| id.clone()))
+        .chain(Some(a.date.clone()).filter(|d| !d.is_empty()))
+        .collect::<Vec<_>>()
+        .join(" · ");
+    if !authors.is_empty() {
+        let style = Style::new(base * 0.85);
+        body.extend(l.paragraph(&[Inline::Text(authors)], style, Align::Left, 0.0, base * 0.4));
+    }
+    body.push(Line {
+        rules: vec![[0.0, l.width, base * 0.7]],
+        height: base * 1.4,
+        ..Default::default()
+    });
+
+    for (i, p) in a.summary.iter().enumerate() {
+        let dropcap = match (i, p.as_sentence()) {
+            (0, Some(s)) if !a.is_prayer() => l.dropcap_paragraph(&inlines(s)),
+            _ => None,
+        };
+        match dropcap {
+            Some(lines) => body.extend(lines),
+            None => body.extend(l.block(p, img_dir, 0.0, false)),
+        }
+    }
+    for s in a.sections.iter() {
+        if !s.title.trim().is_empty() {
+            body.extend(l.heading(&s.title, s.indent.max(2)));
+        }
+        for p in s.pars.iter() {
+            body.extend(l.block(p, img_dir, 0.0, false));
+        }
+    }
+    body.extend(l.qr_code(url, &format!("{} ", get_string(meta, lang, "pdf-online")?)));
+
+    let notes = a
+        .footnotes
+        .iter()
+        .map(|f| (f.id.clone(), l.footnote(&f.id, &f.text)))
+        .collect::<BTreeMap<_, _>>();
+
+    let pages = l.paginate(body, &notes, &a.title, "dubia.cc");
+    let authors = a
+        .authors
+        .iter()
+        .map(|id| meta.authors.get(id).map(|q| q.displayname.clone()).unwrap_or_else(|| id.clone()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    write_pdf(faces, size, lang, &a.title, &authors, &pages)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
+
+fn write_pdf(
+    faces: &[Face<'_>],
+    size: PageSize,
+    lang: &str,
+    title: &str,
+    authors: &str,
+    pages: &[Page],
+) -> Result<Vec<u8>, String> {
+    let (w, h) = size.dimensions();
+    let mut next = Ref::new(1);
+    let catalog_id = next.bump();
+    let page_tree_id = next.bump();
+    let info_id = next.bump();
+
+    // glyph id => char, per font
+    let mut used = BTreeMap::<usize, BTreeMap<u16, char>>::new();
+    for p in pages {
+        for s in p.content.spans.iter() {
+            for (c, font, g) in glyphs(faces, s.font, &s.text) {
+                let used_glyphs = used.entry(font).or_default();
+                if let Some(g) = g {
+                    used_glyphs.insert(g.0, c);
+                }
+            }
+        }
+    }
+    let font_refs = used.keys().map(|f| (*f, next.bump())).collect::<BTreeMap<_, _>>();
+
+    let mut pdf = Pdf::new();
+    let mut page_ids = Vec::new();
+
+    for p in pages.iter() {
+        let page_id = next.bump();
+        let content_id = next.bump();
+        page_ids.push(page_id);
+
+        let mut image_ids = Vec::new();
+        let mut content = Content::new();
+
+        for (i, ([x, y, iw, ih], img)) in p.content.images.iter().enumerate() {
+            let id = next.bump();
+            let data = deflate(&img.rgb);
+            let mut xobj = pdf.image_xobject(id, &data);
+            xobj.filter(Filter::FlateDecode);
+            xobj.width(img.width as i32);
+            xobj.height(img.height as i32);
+            xobj.color_space().device_rgb();
+            xobj.bits_per_component(8);
+            xobj.finish();
+            image_ids.push(id);
+
+            content.save_state();
+            content.transform([*iw, 0.0, 0.0, *ih, *x, h - y - ih]);
+            content.x_object(Name(format!("Im{i}").as_bytes()));
+            content.restore_state();
+        }
+
+        content.set_fill_gray(0.0);
+        for [x, y, rw, rh] in p.content.rects.iter() {
+            content.rect(*x, h - y - rh, *rw, *rh);
+        }
+        if !p.content.rects.is_empty() {
+            content.fill_nonzero();
+        }
+
+        content.set_line_width(0.5);
+        for [x1, x2, y] in p.content.rules.iter() {
+            content.move_to(*x1, h - y);
+            content.line_to(*x2, h - y);
+        }
+        if !p.content.rules.is_empty() {
+            content.stroke();
+        }
+
+        for s in p.content.spans.iter() {
+            // runs of glyphs in the same font, the text position moves on
+            // by the widths of the glyphs
+            let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+            for (_, font, g) in glyphs(faces, s.font, &s.text) {
+                if runs.last().map(|(f, _)| *f) != Some(font) {
+                    runs.push((font, Vec::new()));
+                }
+                if let Some((_, encoded)) = runs.last_mut() {
+                    encoded.extend(g.unwrap_or(GlyphId(0)).0.to_be_bytes());
+                }
+            }
+            content.begin_text();
+            content.next_line(s.x, h - s.y);
+            for (font, encoded) in runs {
+                content.set_font(Name(format!("F{font}").as_bytes()), s.size);
+                content.show(Str(&encoded));
+            }
+            content.end_text();
+        }
+
+        let data = deflate(&content.finish());
+        pdf.stream(content_id, &data).filter(Filter::FlateDecode);
+
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, w, h));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        {
+            let mut resources = page.resources();
+            {
+                let mut f = resources.fonts();
+                for (font, id) in font_refs.iter() {
+                    f.pair(Name(format!("F{font}").as_bytes()), *id);
+                }
+            }
+            if !image_ids.is_empty() {
+                let mut x = resources.x_objects();
+                for (i, id) in image_ids.iter().enumerate() {
+                    x.pair(Name(format!("Im{i}").as_bytes()), *id);
+                }
+            }
+        }
+        if !p.content.links.is_empty() {
+            let mut annots = page.annotations();
+            for ([x, y, lw, lh], uri) in p.content.links.iter() {
+                let mut a = annots.push();
+                a.subtype(AnnotationType::Link);
+                a.rect(Rect::new(*x, h - y - lh, x + lw, h - y));
+                a.border(0.0, 0.0, 0.0, None);
+                a.action().action_type(ActionType::Uri).uri(Str(uri.as_bytes()));
+            }
+        }
+        page.finish();
+    }
+
+    for (font, type0_id) in font_refs.iter() {
+        let ids = [next.bump(), next.bump(), next.bump(), next.bump()];
+        write_font(&mut pdf, *type0_id, ids, *font, &faces[*font], &used[font])?;
+    }
+
+    pdf.catalog(catalog_id).pages(page_tree_id).lang(TextStr(lang));
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(page_ids.len() as i32);
+    let mut info = pdf.document_info(info_id);
+    info.title(TextStr(title));
+    if !authors.is_empty() {
+        info.author(TextStr(authors));
+    }
+    info.creator(TextStr("dubia.cc"));
+    info.finish();
+
+    Ok(pdf.finish())
+}
+
+// Type0 font with Identity-H encoding: the text is written as glyph ids
+fn write_font(
+    pdf: &mut Pdf,
+    type0_id: Ref,
+    [cid_id, descriptor_id, cmap_id, file_id]: [Ref; 4],
+    index: usize,
+    face: &Face<'_>,
+    glyphs: &BTreeMap<u16, char>,
+) -> Result<(), String> {
+    // subset fonts need a unique six letter tag
+    let letter = |i: usize| (b'A' + (i % 26) as u8) as char;
+    let tag = format!("DUBI{}{}", letter(index / 26), letter(index));
+    let family = face
+        .names()
+        .into_iter()
+        .filter(|n| n.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+        // the Macintosh records come first and aren't decoded
+        .find_map(|n| n.to_string())
+        .unwrap_or_else(|| format!("KanzleiInitialen-{}", letter(index.saturating_sub(INITIALS))));
+    let base_font = format!("{tag}+{family}");
+    let upem = face.units_per_em() as f32;
+    let to_pdf = |v: f32| v / upem * 1000.0;
+
+    pdf.type0_font(type0_id)
+        .base_font(Name(base_font.as_bytes()))
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_id)
+        .to_unicode(cmap_id);
+
+    let mut cid = pdf.cid_font(cid_id);
+    cid.subtype(CidFontType::Type2);
+    cid.base_font(Name(base_font.as_bytes()));
+    cid.system_info(SYSTEM_INFO);
+    cid.font_descriptor(descriptor_id);
+    cid.cid_to_gid_map_predefined(Name(b"Identity"));
+    {
+        let mut widths = cid.widths();
+        for g in glyphs.keys() {
+            let advance = face.glyph_hor_advance(GlyphId(*g)).unwrap_or_default() as f32;
+            widths.consecutive(*g, [to_pdf(advance)]);
+        }
+    }
+    cid.finish();
+
+    let mut flags = FontFlags::NON_SYMBOLIC;
+    if index < INITIALS {
+        flags |= FontFlags::SERIF;
+    }
+    if face.is_italic() {
+        flags |= FontFlags::ITALIC;
+    }
+    let bbox = face.global_bounding_box();
+    pdf.font_descriptor(descriptor_id)
+        .name(Name(base_font.as_bytes()))
+        .flags(flags)
+        .bbox(Rect::new(
+            to_pdf(bbox.x_min as f32),
+            to_pdf(bbox.y_min as f32),
+            to_pdf(bbox.x_max as f32),
+            to_pdf(bbox.y_max as f32),
+        ))
+        .italic_angle(face.italic_angle().unwrap_or_default())
+        .ascent(to_pdf(face.ascender() as f32))
+        .descent(to_pdf(face.descender() as f32))
+        .cap_height(to_pdf(face.capital_height().unwrap_or(face.ascender()) as f32))
+        .stem_v(if index < INITIALS && matches!(index % FALLBACK, BOLD | BOLD_ITALIC) { 140.0 } else { 80.0 })
+        .font_file2(file_id);
+
+    let gids = glyphs.keys().copied().collect::<Vec<_>>();
+    let subset = subsetter::subset(face.raw_face().data, 0, subsetter::Profile::pdf(&gids)).map_err(|e| e.to_string())?;
+    let compressed = deflate(&subset);
+    pdf.stream(file_id, &compressed)
+        .filter(Filter::FlateDecode)
+        .pair(Name(b"Length1"), subset.len() as i32);
+
+    let mut cmap = pdf_writer::types::UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+    for (g, c) in glyphs.iter() {
+        cmap.pair(*g, *c);
+    }
+    pdf.cmap(cmap_id, &cmap.finish());
+
+    Ok(())
+}
+
+/// Writes /{lang}/pdf/{path}.pdf (A4) and /{lang}/pdf/{path}-a5.pdf
+/// for all articles and documents
+pub fn write_pdfs(
+    cwd: &Path,
+    dist: &Path,
+    articles: &AnalyzedArticles,
+    documents: &AnalyzedDocuments,
+    meta: &MetaJson,
+) -> Result<(), String> {
+    let fonts = FontFiles::load(cwd)?;
+    let faces = fonts.faces()?;
+    let root_href = get_root_href();
+
+    for lang in meta.strings.keys() {
+        let docs_path = get_string(meta, lang, "special-docs-path")?;
+
+        // (page path, article, folder of the images)
+        let mut pages = Vec::new();
+        for (slug, a) in articles.map.get(lang).into_iter().flatten() {
+            pages.push((slug.clone(), a, cwd.join("articles").join(lang).join(slug)));
+        }
+        for (collection, docs) in documents.map.get(lang).into_iter().flatten() {
+            for (slug, d) in docs {
+                let dir = cwd.join("docs").join(lang).join(collection);
+                pages.push((format!("{docs_path}/{collection}/{slug}"), d, dir));
+            }
+        }
+
+        let mut skipped = Vec::new();
+        for (page_path, a, img_dir) in pages {
+            if !printable(lang, a, meta) {
+                skipped.push(missing_chars(lang, a, meta));
+                continue;
+            }
+            let url = format!("{root_href}/{lang}/{page_path}");
+            for size in PageSize::ALL {
+                let bytes = render(&faces, size, lang, &url, a, &img_dir, meta)?;
+                let path = dist.join(lang).join(format!("{}.pdf", size.path(&page_path)));
+                if let Some(p) = path.parent() {
+                    std::fs::create_dir_all(p).map_err(|e| format!("{}: {e}", p.display()))?;
+                }
+                std::fs::write(&path, bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+
+        if !skipped.is_empty() {
+            let chars = skipped.iter().flatten().collect::<BTreeSet<_>>().into_iter().take(10).collect::<String>();
+            println!("pdf: skipped {} pages in {lang}, characters not in the fonts: {chars}", skipped.len());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pdf() {
+    use crate::{ArticleSection, Footnote};
+
+    let cwd = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let dir = cwd.join("static").join("font");
+    let fonts = FontFiles::load(&cwd).unwrap();
+    let faces = fonts.faces().unwrap();
+    let mut meta = MetaJson::default();
+    meta.strings.entry("en".to_string()).or_default().insert("pdf-online".to_string(), "Online:".to_string());
+
+    let text = |s: &str| SentenceItem::Text { text: s.to_string() };
+    let long = "Lorem ipsum dolor sit amet, *consectetur* adipiscing elit. ".repeat(12);
+    let a = ParsedArticleAnalyzed {
+        title: "Test".to_string(),
+        sections: (0..20)
+            .map(|i| ArticleSection {
+                title: format!("Section {i}"),
+                indent: 2,
+                pars: vec![Paragraph::Sentence {
+                    s: vec![text(&long), SentenceItem::Footnote { id: i.to_string() }],
+                }],
+            })
+            .collect(),
+        footnotes: (0..20).map(|i| Footnote { id: i.to_string(), text: vec![text("note")] }).collect(),
+        ..Default::default()
+    };
+
+    let mut l = Layouter::new(&faces, PageSize::A5);
+    let words = l.words(&[Inline::Text("a **b** *c*".to_string())], Style::new(10.0));
+    assert_eq!(words.iter().map(|w| w[0].style.font()).collect::<Vec<_>>(), [REGULAR, BOLD, ITALIC]);
+
+    // every footnote is on the page of its reference
+    let mut body = Vec::new();
+    for p in a.sections.iter().flat_map(|s| s.pars.iter()) {
+        body.extend(l.block(p, &dir, 0.0, false));
+    }
+    let notes = a.footnotes.iter().map(|f| (f.id.clone(), l.footnote(&f.id, &f.text))).collect();
+    let pages = l.paginate(body, &notes, "Test", "dubia.cc");
+    assert!(pages.len() > 2);
+    for p in pages.iter() {
+        // superscript references and footnote numbers on the same page
+        let refs = p.content.spans.iter().filter(|s| s.size < 7.0).map(|s| s.text.clone()).collect::<BTreeSet<_>>();
+        let notes = p
+            .content
+            .spans
+            .iter()
+            .filter(|s| s.size == 9.5 * 0.8 && s.text.parse::<usize>().is_ok())
+            .map(|s| s.text.clone())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(refs, notes);
+    }
+
+    let pdf = render(&faces, PageSize::A4, "en", "https://dubia.cc/en/test", &a, &dir, &meta)
+        .unwrap();
+    assert!(pdf.starts_with(b"%PDF-1.7"));
+
+    // Polish letters come from the fallback face, nothing is printed as an empty box
+    let g = glyphs(&faces, ITALIC, "aą„");
+    assert_eq!(
+        g.iter().map(|(c, f, g)| (*c, *f, g.is_some())).collect::<Vec<_>>(),
+        [('a', ITALIC, true), ('ą', ITALIC + FALLBACK, true), ('“', ITALIC, true)]
+    );
+    let pl = ParsedArticleAnalyzed { title: "Zażółć gęślą jaźń".to_string(), ..Default::default() };
+    assert!(!printable("en", &pl, &meta));
+    meta.pdf_chars = fonts.printable_chars("Online: Zażółć gęślą jaźń 中ℝ".chars()).unwrap();
+    assert!(printable("en", &pl, &meta));
+    // 中 is in no face, ℝ only in the regular fallback face and not in the italic one
+    assert!(faces[REGULAR + FALLBACK].glyph_index('ℝ').is_some());
+    let other = ParsedArticleAnalyzed { title: "中 ℝ".to_string(), ..Default::default() };
+    assert_eq!(missing_chars("en", &other, &meta), "中ℝ".chars().collect());
+    assert!(!printable("en", &other, &meta));
+
+    let pdf = render(&faces, PageSize::A5, "en", "https://dubia.cc/en/test", &pl, &dir, &meta).unwrap();
+    assert!(pdf.windows(b"/F4 ".len()).any(|w| w == b"/F4 "));
+}
@@ -0,0 +1,435 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::assets::Assets;
+use crate::{get_string, sha256, MetaJson};
+
+// largest files listed in the budget report
+const REPORT_LARGEST: usize = 10;
+
+// workbox.* namespaces of workbox-sw.js and the module they load
+const WORKBOX_MODULES: &[(&str, &str)] = &[
+    ("backgroundSync", "background-sync"),
+    ("broadcastUpdate", "broadcast-cache-update"),
+    ("cacheableResponse", "cacheable-response"),
+    ("expiration", "cache-expiration"),
+    ("googleAnalytics", "google-analytics"),
+    ("precaching", "precaching"),
+    ("rangeRequests", "range-requests"),
+    ("routing", "routing"),
+    ("strategies", "strategies"),
+    ("streams", "streams"),
+];
+
+/// config/precache.json
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrecacheJson {
+    // glob patterns of URLs ("/static/font/ssp/*.woff2", "/*/*.html"),
+    // "*" stays inside one path segment, "**" spans segments
+    #[serde(default)]
+    pub include: Vec<String>,
+    // patterns that are removed again from the included files
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // warn when the install plus the first visit of a language downloads more than this
+    #[serde(default, rename = "budget-kb")]
+    pub budget_kb: Option<u64>,
+}
+
+impl PrecacheJson {
+    pub fn load(file: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(file) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{}: {e}", file.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_included(&self, url: &str) -> bool {
+        self.include.iter().any(|p| glob_match(p, url)) && !self.exclude.iter().any(|p| glob_match(p, url))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PrecacheEntry {
+    pub url: String,
+    // content hash, changes whenever the file changes
+    pub revision: String,
+    pub bytes: u64,
+}
+
+/// Files of one language that the service worker caches on request,
+/// /{lang}/offline/{collection}.json: "site" is requested on the first
+/// visit to /{lang}/, "docs" by the "download for offline" button
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OfflineManifest {
+    pub lang: String,
+    pub collection: String,
+    // hash of all entries, the service worker skips manifests it already has
+    pub revision: String,
+    pub bytes: u64,
+    pub entries: Vec<PrecacheEntry>,
+}
+
+impl OfflineManifest {
+    pub fn new(lang: &str, collection: &str, entries: Vec<PrecacheEntry>) -> Self {
+        let revisions = entries.iter().map(|e| format!("{} {}", e.url, e.revision)).collect::<Vec<_>>();
+        Self {
+            lang: lang.to_string(),
+            collection: collection.to_string(),
+            revision: sha256(revisions.join("\n")),
+            bytes: entries.iter().map(|e| e.bytes).sum(),
+            entries,
+        }
+    }
+
+    pub fn url(lang: &str, collection: &str) -> String {
+        format!("/{lang}/offline/{collection}.json")
+    }
+
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        let path = dist.join(Self::url(&self.lang, &self.collection).trim_start_matches('/'));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// The files the service worker downloads on install (`entries`, shared by
+/// all languages) and the manifests it downloads later per language
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Precache {
+    pub entries: Vec<PrecacheEntry>,
+    // "site" manifest per language
+    pub languages: BTreeMap<String, OfflineManifest>,
+    // other collections ("docs"), only downloaded on request
+    pub collections: Vec<OfflineManifest>,
+    pub budget_kb: Option<u64>,
+}
+
+impl Precache {
+    /// Collects the matching files of /articles (as /articles/...) and /dist
+    /// (as /...). The rules match the original names of fingerprinted assets,
+    /// the manifest gets the fingerprinted URL. `always` are URLs in /dist that
+    /// are precached regardless of the rules (opt-in article audio).
+    /// `docs_paths` is the folder of the documents per language ("de" => "dok").
+    pub fn collect(
+        cwd: &Path,
+        config: &PrecacheJson,
+        always: &[String],
+        docs_paths: &BTreeMap<String, String>,
+        assets: &Assets,
+    ) -> Self {
+        let roots = [
+            (cwd.join("articles"), "/articles".to_string()),
+            (cwd.join("dist"), String::new()),
+        ];
+        // the service worker can't precache itself
+        let files = collect_files(&roots, |url| {
+            url != "/sw.js"
+                && !assets.is_fingerprinted(url)
+                && (config.is_included(url) || always.iter().any(|a| a == url))
+        })
+        .into_iter()
+        .map(|e| PrecacheEntry { url: assets.url(&e.url), ..e });
+
+        let mut entries = Vec::new();
+        let mut languages = BTreeMap::<String, Vec<PrecacheEntry>>::new();
+        for e in files {
+            // /{lang}/offline.html answers failed requests (sw-offline.js),
+            // it is needed before the language manifest is downloaded
+            let offline_page = e.url.ends_with("/offline.html") && e.url.matches('/').count() == 2;
+            match lang_of(&e.url).filter(|l| docs_paths.contains_key(*l) && !offline_page) {
+                Some(l) => languages.entry(l.to_string()).or_default().push(e),
+                None => entries.push(e),
+            }
+        }
+
+        let collections = docs_paths
+            .iter()
+            .map(|(lang, docs_path)| {
+                let root = [(cwd.join("dist").join(lang).join(docs_path), format!("/{lang}/{docs_path}"))];
+                OfflineManifest::new(lang, "docs", collect_files(&root, |url| url.ends_with(".html")))
+            })
+            .filter(|m| !m.entries.is_empty())
+            .collect();
+
+        Self {
+            entries,
+            languages: languages
+                .into_iter()
+                .map(|(l, e)| (l.clone(), OfflineManifest::new(&l, "site", e)))
+                .collect(),
+            collections,
+            budget_kb: config.budget_kb,
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+
+    pub fn manifests(&self) -> impl Iterator<Item = &OfflineManifest> {
+        self.languages.values().chain(self.collections.iter())
+    }
+
+    /// Writes /{lang}/offline/{collection}.json
+    pub fn write(&self, dist: &Path) -> Result<(), String> {
+        for m in self.manifests() {
+            m.write(dist)?;
+        }
+        Ok(())
+    }
+
+    /// workbox.precaching.precacheAndRoute([...]); call for the sw.js template
+    pub fn to_js(&self) -> String {
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| format!("    {{ url: '{}', revision: '{}' }}", e.url, e.revision))
+            .collect::<Vec<_>>();
+        format!(
+            "workbox.precaching.precacheAndRoute([\r\n{}\r\n]);",
+            entries.join(",\r\n")
+        )
+    }
+
+    /// {"de/site": "revision", ...}, so that a new deploy refreshes the
+    /// manifests that changed
+    pub fn revisions_json(&self) -> String {
+        let r = self
+            .manifests()
+            .map(|m| (format!("{}/{}", m.lang, m.collection), m.revision.clone()))
+            .collect::<BTreeMap<_, _>>();
+        serde_json::to_string(&r).unwrap_or_default()
+    }
+
+    /// Size per top-level directory and per language, with a warning when
+    /// the install plus the first visit of a language is over the budget
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let total = self.total_bytes();
+        let budget = match self.budget_kb {
+            Some(b) => format!(" (budget {b} KB)"),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "precache: {} files, {} KB on install{budget}",
+            self.entries.len(),
+            total / 1024
+        ));
+
+        let mut groups = BTreeMap::<String, (usize, u64)>::new();
+        for e in self.entries.iter() {
+            let g = groups.entry(group(&e.url)).or_default();
+            g.0 += 1;
+            g.1 += e.bytes;
+        }
+        for (g, (count, bytes)) in groups.iter() {
+            lines.push(format!("  {g}: {count} files, {} KB", bytes / 1024));
+        }
+        for m in self.manifests() {
+            lines.push(format!(
+                "  {}: {} files, {} KB",
+                OfflineManifest::url(&m.lang, &m.collection),
+                m.entries.len(),
+                m.bytes / 1024
+            ));
+        }
+
+        let first_visit = self.languages.values().max_by_key(|m| (m.bytes, std::cmp::Reverse(m.lang.clone())));
+        let first_visit_bytes = first_visit.map(|m| m.bytes).unwrap_or_default();
+        if let Some(budget) = self.budget_kb.filter(|b| total + first_visit_bytes > b * 1024) {
+            lines.push(format!(
+                "precache is {} KB over budget (install + first visit of /{}), largest files:",
+                (total + first_visit_bytes - budget * 1024) / 1024,
+                first_visit.map(|m| m.lang.as_str()).unwrap_or_default()
+            ));
+            let mut largest = self
+                .entries
+                .iter()
+                .chain(first_visit.iter().flat_map(|m| m.entries.iter()))
+                .collect::<Vec<_>>();
+            largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.url.cmp(&b.url)));
+            for e in largest.iter().take(REPORT_LARGEST) {
+                lines.push(format!("  {} KB {}", e.bytes / 1024, e.url));
+            }
+        }
+
+        lines
+    }
+}
+
+/// The workbox-sw.js loader followed by the modules that `script` uses,
+/// inlined from /static/js/workbox/workbox-{module}.prod.js when the file
+/// is there, otherwise loaded from the CDN while the service worker installs
+pub fn workbox_js(cwd: &Path, loader: &str, script: &str) -> String {
+    let mut modules = WORKBOX_MODULES
+        .iter()
+        .filter(|(ns, _)| script.contains(&format!("workbox.{ns}.")))
+        .map(|(_, m)| *m)
+        .collect::<Vec<_>>();
+    if !modules.is_empty() {
+        modules.insert(0, "core");
+    }
+
+    let mut s = loader.to_string();
+    for m in modules {
+        let file = cwd.join("static").join("js").join("workbox").join(format!("workbox-{m}.prod.js"));
+        match std::fs::read_to_string(&file) {
+            Ok(js) => s += &format!("\r\n{js}"),
+            Err(_) => s += &format!("\r\nworkbox.loadModule('workbox-{m}');"),
+        }
+    }
+    s
+}
+
+/// "Download for offline" button, `collection` None downloads all
+/// collections of the language
+pub fn render_download(lang: &str, collection: Option<&str>, meta: &MetaJson) -> Result<String, String> {
+    let title = match collection {
+        Some(c) => get_string(meta, lang, &format!("offline-download-{c}"))?,
+        None => get_string(meta, lang, "offline-download-all")?,
+    };
+    Ok(include_str!("../../templates/offline-download.html")
+        .replace("$$LANG$$", lang)
+        .replace("$$OFFLINE_COLLECTION$$", collection.unwrap_or_default())
+        .replace("$$OFFLINE_TITLE$$", &title)
+        .replace("$$OFFLINE_DONE$$", &get_string(meta, lang, "offline-download-done")?)
+        .replace("$$OFFLINE_ERROR$$", &get_string(meta, lang, "offline-download-error")?))
+}
+
+// files below the roots (directory, URL prefix), sorted by URL
+fn collect_files(roots: &[(PathBuf, String)], keep: impl Fn(&str) -> bool) -> Vec<PrecacheEntry> {
+    let mut files = BTreeMap::new();
+    for (dir, prefix) in roots.iter() {
+        for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Ok(rel) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let url = format!("{prefix}/{}", rel.to_string_lossy().replace('\\', "/"));
+            if keep(&url) {
+                files.insert(url, path.to_path_buf());
+            }
+        }
+    }
+
+    files
+        .into_iter()
+        .filter_map(|(url, path)| {
+            let bytes = std::fs::read(&path).ok()?;
+            Some(PrecacheEntry {
+                url,
+                revision: sha256(&bytes),
+                bytes: bytes.len() as u64,
+            })
+        })
+        .collect()
+}
+
+// "de" for "/de.html", "/de/x.html" and "/articles/de/x/index.md"
+fn lang_of(url: &str) -> Option<&str> {
+    let parts = url.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [page] => page.strip_suffix(".html"),
+        ["articles", lang, _, ..] => Some(lang),
+        [lang, _, ..] => Some(lang),
+        _ => None,
+    }
+}
+
+// "/static/font" for "/static/font/ssp/x.woff2", "/de" for "/de/x.html",
+// "/" for files in the root
+fn group(url: &str) -> String {
+    let parts = url.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["static" | "articles", second, _, ..] => format!("/{}/{second}", parts[0]),
+        [first, _, ..] => format!("/{first}"),
+        _ => "/".to_string(),
+    }
+}
+
+fn glob_match(pattern: &str, s: &str) -> bool {
+    fn m(p: &[u8], s: &[u8]) -> bool {
+        match p {
+            [] => s.is_empty(),
+            [b'*', b'*', rest @ ..] => (0..=s.len()).any(|i| m(rest, &s[i..])),
+            [b'*', rest @ ..] => {
+                let seg = s.iter().position(|c| *c == b'/').unwrap_or(s.len());
+                (0..=seg).any(|i| m(rest, &s[i..]))
+            }
+            [b'?', rest @ ..] => matches!(s.first(), Some(c) if *c != b'/') && m(rest, &s[1..]),
+            [c, rest @ ..] => s.first() == Some(c) && m(rest, &s[1..]),
+        }
+    }
+    m(pattern.as_bytes(), s.as_bytes())
+}
+
+#[test]
+fn test_precache_rules() {
+    let config = PrecacheJson {
+        include: vec![
+            "/*/*.html".to_string(),
+            "/static/font/**.woff2".to_string(),
+            "/static/font/kanzlei/Kanzlei-Initialen-?.ttf".to_string(),
+        ],
+        exclude: vec!["/*/offline.html".to_string()],
+        budget_kb: Some(1),
+    };
+    assert!(config.is_included("/de/angelus.html"));
+    assert!(!config.is_included("/de/docs/x.html"));
+    assert!(!config.is_included("/de/offline.html"));
+    assert!(config.is_included("/static/font/ssp/SourceSansPro-BASIC-Bold.woff2"));
+    assert!(config.is_included("/static/font/kanzlei/Kanzlei-Initialen-Z.ttf"));
+    assert!(!config.is_included("/static/font/ssp/SourceSansPro-BASIC-Bold.ttf"));
+
+    let sw = workbox_js(Path::new("/nonexistent"), "var workbox;", "workbox.routing.registerRoute(x, workbox.strategies.networkOnly());");
+    assert_eq!(
+        sw,
+        "var workbox;\r\nworkbox.loadModule('workbox-core');\r\nworkbox.loadModule('workbox-routing');\r\nworkbox.loadModule('workbox-strategies');"
+    );
+
+    let entry = |url: &str, bytes: u64| PrecacheEntry {
+        url: url.to_string(),
+        revision: url.len().to_string(),
+        bytes,
+    };
+    assert_eq!(lang_of("/de.html"), Some("de"));
+    assert_eq!(lang_of("/articles/de/angelus/index.md"), Some("de"));
+    assert_eq!(lang_of("/de/dok/x.html"), Some("de"));
+    assert_eq!(lang_of("/manifest.json"), None);
+
+    let site = OfflineManifest::new("de", "site", vec![entry("/de/a.html", 1024)]);
+    assert_eq!(site.revision, OfflineManifest::new("de", "site", vec![entry("/de/a.html", 1024)]).revision);
+    assert_ne!(site.revision, OfflineManifest::new("de", "site", vec![entry("/de/ab.html", 1024)]).revision);
+
+    let precache = Precache {
+        entries: vec![entry("/static/font/ssp/a.woff2", 2048)],
+        languages: [("de".to_string(), site.clone())].into_iter().collect(),
+        collections: vec![OfflineManifest::new("de", "docs", vec![entry("/de/dok/x/y.html", 4096)])],
+        budget_kb: config.budget_kb,
+    };
+    assert_eq!(
+        precache.to_js(),
+        "workbox.precaching.precacheAndRoute([\r\n    { url: '/static/font/ssp/a.woff2', revision: '24' }\r\n]);"
+    );
+    assert!(precache.revisions_json().starts_with("{\"de/docs\":\""));
+    assert_eq!(
+        precache.report(),
+        [
+            "precache: 1 files, 2 KB on install (budget 1 KB)",
+            "  /static/font: 1 files, 2 KB",
+            "  /de/offline/site.json: 1 files, 1 KB",
+            "  /de/offline/docs.json: 1 files, 4 KB",
+            "precache is 2 KB over budget (install + first visit of /de), largest files:",
+            "  2 KB /static/font/ssp/a.woff2",
+            "  1 KB /de/a.html",
+        ]
+    );
+}
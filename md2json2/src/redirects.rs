@@ -0,0 +1,189 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::sitemap::Sitemap;
+use crate::{get_root_href, AnalyzedArticles};
+
+// longest chain of redirects (a -> b -> c) that is resolved
+const MAX_REDIRECT_CHAIN: usize = 10;
+
+/// config/redirects.json
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectsJson {
+    // "netlify", "cloudflare" or "apache", writes the redirects
+    // additionally in the format of the hosting provider
+    #[serde(default)]
+    pub host: Option<String>,
+    // old path ("/de/alter-name") => new path ("/de/neuer-name") or URL
+    #[serde(default)]
+    pub redirects: BTreeMap<String, String>,
+}
+
+/// All redirects of the site, from the site-wide redirect map and the
+/// "aliases" of the articles, old path => new path, without .html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Redirects {
+    pub host: Option<String>,
+    pub map: BTreeMap<String, String>,
+}
+
+fn normalize(path: &str) -> String {
+    if path.starts_with("http://") || path.starts_with("https://") {
+        return path.to_string();
+    }
+    let p = path.trim().trim_end_matches(".html").trim_end_matches('/');
+    format!("/{}", p.trim_start_matches('/'))
+}
+
+impl Redirects {
+    pub fn load(file: &Path, articles: &AnalyzedArticles) -> Result<Self, String> {
+        let config = match std::fs::read_to_string(file) {
+            Ok(s) => serde_json::from_str::<RedirectsJson>(&s)
+                .map_err(|e| format!("{}: {e}", file.display()))?,
+            Err(_) => RedirectsJson::default(),
+        };
+        Self::new(config, articles)
+    }
+
+    pub fn new(config: RedirectsJson, articles: &AnalyzedArticles) -> Result<Self, String> {
+        let mut map = BTreeMap::new();
+        let mut insert = |from: String, to: String| -> Result<(), String> {
+            match map.insert(from.clone(), to.clone()) {
+                Some(old) if old != to => Err(format!("redirect {from} points to both {old} and {to}")),
+                _ => Ok(()),
+            }
+        };
+
+        for (from, to) in config.redirects.iter() {
+            insert(normalize(from), normalize(to))?;
+        }
+
+        for (lang, articles) in articles.map.iter() {
+            for (slug, a) in articles.iter() {
+                for alias in a.aliases.iter() {
+                    let from = match alias.starts_with('/') {
+                        true => normalize(alias),
+                        false => normalize(&format!("{lang}/{alias}")),
+                    };
+                    insert(from, format!("/{lang}/{slug}"))?;
+                }
+            }
+        }
+
+        // an old path must not hide an existing article
+        for from in map.keys() {
+            let mut parts = from.trim_start_matches('/').splitn(2, '/');
+            let (lang, slug) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+            if articles.map.get(lang).map(|a| a.contains_key(slug)).unwrap_or(false) {
+                return Err(format!("redirect {from} overwrites the existing article {lang}/{slug}"));
+            }
+        }
+
+        // a -> b -> c: redirect a directly to c
+        let resolved = map
+            .iter()
+            .map(|(from, to)| {
+                let mut to = to.clone();
+                for _ in 0..MAX_REDIRECT_CHAIN {
+                    match map.get(&to) {
+                        Some(next) => to = next.clone(),
+                        None => return Ok((from.clone(), to)),
+                    }
+                }
+                Err(format!("redirect loop: {from} -> {to}"))
+            })
+            .collect::<Result<BTreeMap<_, _>, String>>()?;
+
+        Ok(Self {
+            host: config.host,
+            map: resolved,
+        })
+    }
+
+    /// Writes one redirect page per old path to /dist, plus the
+    /// redirect file of the hosting provider, if configured
+    ///
+    /// Needs to run after all pages are written, so that redirects
+    /// can't overwrite generated pages
+    pub fn write(&self, dist: &Path, sitemap: &Sitemap) -> Result<(), String> {
+        let root_href = get_root_href();
+
+        for (from, to) in self.map.iter() {
+            if sitemap.lastmod(from).is_some() {
+                return Err(format!("redirect {from} would overwrite a generated page"));
+            }
+            let target = match to.starts_with('/') {
+                true => format!("{root_href}{to}"),
+                false => to.clone(),
+            };
+            let lang = from.trim_start_matches('/').split('/').next().unwrap_or("en");
+            let html = include_str!("../../templates/redirect.html")
+                .replace("$$TARGET$$", &target)
+                .replace("$$LANG$$", lang);
+
+            let path = dist.join(format!("{}.html", from.trim_start_matches('/')));
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            std::fs::write(&path, html).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        if let Some(host) = self.host.as_deref() {
+            let (file, contents) = self.host_file(host)?;
+            std::fs::write(dist.join(file), contents).map_err(|e| format!("{file}: {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Server-side redirects: (file name in /dist, contents)
+    fn host_file(&self, host: &str) -> Result<(&'static str, String), String> {
+        let lines = |f: &dyn Fn(&str, &str) -> String| {
+            self.map
+                .iter()
+                .flat_map(|(from, to)| [f(from, to), f(&format!("{from}.html"), to)])
+                .collect::<Vec<_>>()
+                .join("\r\n")
+        };
+
+        match host {
+            "netlify" | "cloudflare" => Ok(("_redirects", lines(&|from, to| format!("{from} {to} 301")))),
+            "apache" => Ok((".htaccess", lines(&|from, to| format!("Redirect 301 {from} {to}")))),
+            _ => Err(format!("config/redirects.json: unknown host {host}")),
+        }
+    }
+}
+
+#[test]
+fn test_redirects() {
+    use crate::ParsedArticleAnalyzed;
+
+    let mut articles = AnalyzedArticles::default();
+    let a = ParsedArticleAnalyzed {
+        aliases: vec!["alt".to_string(), "/de/sehr-alt.html".to_string()],
+        ..Default::default()
+    };
+    articles.map.entry("de".to_string()).or_default().insert("neu".to_string(), a);
+
+    let config = RedirectsJson {
+        host: Some("netlify".to_string()),
+        redirects: [("/de/uralt".to_string(), "/de/sehr-alt".to_string())].into_iter().collect(),
+    };
+    let r = Redirects::new(config.clone(), &articles).unwrap();
+    assert_eq!(r.map["/de/alt"], "/de/neu");
+    assert_eq!(r.map["/de/sehr-alt"], "/de/neu");
+    assert_eq!(r.map["/de/uralt"], "/de/neu");
+    let (file, contents) = r.host_file("netlify").unwrap();
+    assert_eq!(file, "_redirects");
+    assert!(contents.contains("/de/alt.html /de/neu 301"));
+
+    // old paths can't hide existing articles, loops are errors
+    let mut bad = config.clone();
+    bad.redirects.insert("/de/neu".to_string(), "/de/alt".to_string());
+    assert!(Redirects::new(bad, &articles).is_err());
+    let mut bad = config;
+    bad.redirects.insert("/de/a".to_string(), "/de/b".to_string());
+    bad.redirects.insert("/de/b".to_string(), "/de/a".to_string());
+    assert!(Redirects::new(bad, &articles).is_err());
+}
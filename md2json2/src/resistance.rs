@@ -1,8 +1,9 @@
 use std::path::Path;
 
+use crate::sitemap::Sitemap;
 use crate::{get_string, MetaJson};
 
-pub fn generate_resistance_pages(cwd: &Path, meta: &MetaJson) -> Result<(), String> {
+pub fn generate_resistance_pages(cwd: &Path, meta: &MetaJson, sitemap: &mut Sitemap) -> Result<(), String> {
     // For each language, generate a resistance.html file
     for lang in meta.strings.keys() {
         let content = generate_resistance_html(lang, meta)?;
@@ -18,7 +19,7 @@ pub fn generate_resistance_pages(cwd: &Path, meta: &MetaJson) -> Result<(), Stri
         }
 
         // Write the file
-        let _ = std::fs::write(output_path, &crate::minify(&content));
+        sitemap.write(&output_path, crate::minify(&content), "resistance", None);
     }
 
     Ok(())
@@ -77,12 +77,20 @@ pub struct Mystery {
 pub struct RosaryPrayer {
     pub en: String,
     pub de: String,
+    // not every prayer has a Latin text or a source yet
+    #[serde(default)]
     pub la: String,
+    #[serde(default)]
     pub source_de: String,
+    #[serde(default)]
     pub source_en: String,
+    #[serde(default)]
     pub source_la: String,
+    #[serde(default)]
     pub source_link_de: String,
+    #[serde(default)]
     pub source_link_en: String,
+    #[serde(default)]
     pub source_link_la: String,
     pub image: String,
 }
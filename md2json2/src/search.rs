@@ -0,0 +1,564 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::{gen_section_id, normalize_str_to_string, par2text};
+use crate::{ArticleSection, Paragraph, ParsedArticleAnalyzed};
+
+type Token = String;
+type EntryId = usize;
+
+// Field weights: a match in the title counts ten times as much as a
+// single match in the body text
+const WEIGHT_TITLE: u32 = 10;
+const WEIGHT_HEADING: u32 = 5;
+const WEIGHT_BODY: u32 = 1;
+// Repeating a word more often than this in one section doesn't rank higher
+const MAX_BODY_HITS: u32 = 5;
+
+// Tokens are sharded by their first two characters
+const SHARD_PREFIX_LEN: usize = 2;
+// Number of entries per entry file
+const CHUNK_SIZE: usize = 100;
+const SNIPPET_LEN: usize = 160;
+// Shorter words are not corrected ("Did you mean")
+const MIN_CANDIDATE_LEN: usize = 4;
+
+/// One hit target of the full text search: either the top of a page
+/// (title + summary) or a single section of it
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FullTextEntry {
+    // url of the page, including the "#section-id" anchor
+    pub u: String,
+    // title of the page
+    pub t: String,
+    // heading of the section, empty for the page top
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub h: String,
+    // snippet of the section text
+    pub x: String,
+}
+
+/// Tells the client which files of the full text index exist, stored
+/// in the /{lang}/index.json
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FullTextManifest {
+    // names of the token shards in /{lang}/fulltext/{shard}.json
+    pub shards: Vec<String>,
+    // number of entries per /{lang}/fulltext/e{n}.json
+    pub chunk: usize,
+    // total number of entries
+    pub entries: usize,
+    // (key in index.json "articles", id of the first entry of that page),
+    // lets the client filter hits before downloading the entries
+    pub pages: Vec<(String, EntryId)>,
+    // names of the spelling candidate files /{lang}/fulltext/{v-x,w-x}.json
+    pub candidates: Vec<String>,
+    // stemming rules of this language, applied to the query on the client
+    pub stemmer: Stemmer,
+}
+
+/// Per-language inverted index: stem => entry => score
+#[derive(Debug, Default, Clone)]
+pub struct FullTextIndex {
+    pub entries: Vec<FullTextEntry>,
+    pub pages: Vec<(String, EntryId)>,
+    pub postings: BTreeMap<Token, BTreeMap<EntryId, u32>>,
+    // stem => lowercase word as written in the text => count,
+    // used to suggest real words instead of stems
+    pub forms: BTreeMap<Token, BTreeMap<String, u32>>,
+    pub stemmer: Stemmer,
+}
+
+impl FullTextIndex {
+    pub fn new(lang: &str) -> Self {
+        Self {
+            stemmer: Stemmer::new(lang),
+            ..Default::default()
+        }
+    }
+
+    // (word as written, stem)
+    fn words(&self, text: &str) -> Vec<(String, Token)> {
+        tokenize_words(text)
+            .into_iter()
+            .map(|(form, token)| (form, self.stemmer.stem(&token)))
+            .collect()
+    }
+    /// Adds an article or document, `key` is the id of the page in the
+    /// index.json, `url` is the absolute path of the page ("/de/slug")
+    pub fn add_page(&mut self, key: &str, url: &str, a: &ParsedArticleAnalyzed) {
+        let mut top_text = a
+            .subtitle
+            .iter()
+            .chain(a.summary.iter())
+            .map(paragraph_text)
+            .collect::<Vec<_>>();
+
+        let top = self.push_entry(url, &a.title, "", "");
+        self.pages.push((key.to_string(), top));
+        self.add_tokens(top, &a.title, WEIGHT_TITLE);
+
+        for s in a.sections.iter() {
+            let id = gen_section_id(&s.title);
+            if s.title.trim().is_empty() || id.replace('-', "").is_empty() {
+                // no anchor to link to, count as part of the page top
+                top_text.push(section_text(s));
+                continue;
+            }
+
+            let text = section_text(s);
+            let e = self.push_entry(&format!("{url}#{id}"), &a.title, &s.title, &text);
+            self.add_tokens(e, &s.title, WEIGHT_HEADING);
+            self.add_body(e, &text);
+        }
+
+        let top_text = top_text.join(" ");
+        self.entries[top].x = snippet(&top_text);
+        self.add_body(top, &top_text);
+    }
+
+    fn push_entry(&mut self, url: &str, title: &str, heading: &str, text: &str) -> EntryId {
+        self.entries.push(FullTextEntry {
+            u: url.to_string(),
+            t: title.to_string(),
+            h: heading.to_string(),
+            x: snippet(text),
+        });
+        self.entries.len() - 1
+    }
+
+    fn add_tokens(&mut self, entry: EntryId, text: &str, weight: u32) {
+        for (form, t) in self.words(text) {
+            *self.forms.entry(t.clone()).or_default().entry(form).or_default() += 1;
+            *self.postings.entry(t).or_default().entry(entry).or_default() += weight;
+        }
+    }
+
+    fn add_body(&mut self, entry: EntryId, text: &str) {
+        let mut counts = BTreeMap::<Token, u32>::new();
+        for (form, t) in self.words(text) {
+            *self.forms.entry(t.clone()).or_default().entry(form).or_default() += 1;
+            *counts.entry(t).or_default() += 1;
+        }
+        for (t, c) in counts {
+            *self.postings.entry(t).or_default().entry(entry).or_default() +=
+                c.min(MAX_BODY_HITS) * WEIGHT_BODY;
+        }
+    }
+
+    /// Shard name => token => [entry, score, entry, score, ...],
+    /// sorted by descending score
+    pub fn shards(&self) -> BTreeMap<String, BTreeMap<Token, Vec<usize>>> {
+        let mut shards = BTreeMap::<String, BTreeMap<Token, Vec<usize>>>::new();
+        for (token, hits) in self.postings.iter() {
+            let mut hits = hits.iter().collect::<Vec<_>>();
+            hits.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let flat = hits
+                .into_iter()
+                .flat_map(|(e, s)| [*e, *s as usize])
+                .collect();
+            shards
+                .entry(shard_name(token))
+                .or_default()
+                .insert(token.clone(), flat);
+        }
+        shards
+    }
+
+    /// Spelling candidates: every stem is listed in the file of its first
+    /// ("v-") and of its second ("w-") character, so that the client finds
+    /// a misspelled word as long as one of the two is correct.
+    ///
+    /// file name => [stem, most common written form, number of entries],
+    /// sorted by descending number of entries
+    pub fn candidates(&self) -> BTreeMap<String, Vec<(Token, String, usize)>> {
+        let mut files = BTreeMap::<String, Vec<(Token, String, usize)>>::new();
+        for (stem, hits) in self.postings.iter() {
+            let chars = stem.chars().collect::<Vec<_>>();
+            if chars.len() < MIN_CANDIDATE_LEN
+                || chars.iter().any(|c| !c.is_alphabetic() || is_cjk(*c))
+            {
+                continue;
+            }
+
+            let form = self
+                .forms
+                .get(stem)
+                .and_then(|f| f.iter().max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0))))
+                .map(|(f, _)| f.clone())
+                .unwrap_or_else(|| stem.clone());
+
+            let c = (stem.clone(), form, hits.len());
+            files.entry(format!("v-{}", shard_name(&chars[0].to_string()))).or_default().push(c.clone());
+            files.entry(format!("w-{}", shard_name(&chars[1].to_string()))).or_default().push(c);
+        }
+
+        for v in files.values_mut() {
+            v.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        }
+        files
+    }
+
+    pub fn manifest(&self) -> FullTextManifest {
+        FullTextManifest {
+            shards: self
+                .postings
+                .keys()
+                .map(|t| shard_name(t))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            chunk: CHUNK_SIZE,
+            entries: self.entries.len(),
+            pages: self.pages.clone(),
+            candidates: self.candidates().into_keys().collect(),
+            stemmer: self.stemmer.clone(),
+        }
+    }
+
+    /// Writes the shards and entry chunks into `dir` (/dist/{lang}/fulltext)
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+
+        for (name, tokens) in self.shards() {
+            let json = serde_json::to_string(&tokens).unwrap_or_default();
+            let path = dir.join(format!("{name}.json"));
+            std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        for (name, candidates) in self.candidates() {
+            let json = serde_json::to_string(&candidates).unwrap_or_default();
+            let path = dir.join(format!("{name}.json"));
+            std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        for (i, chunk) in self.entries.chunks(CHUNK_SIZE).enumerate() {
+            let json = serde_json::to_string(&chunk).unwrap_or_default();
+            let path = dir.join(format!("e{i}.json"));
+            std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn paragraph_text(p: &Paragraph) -> String {
+    let text = match p {
+        Paragraph::Quote { q } => {
+            let mut t = q.quote.iter().map(par2text).collect::<Vec<_>>();
+            t.push(q.title.clone());
+            t.join(" ")
+        }
+        Paragraph::Image { i } => i.title.clone(),
+        _ => par2text(p),
+    };
+    strip_tags(&text)
+}
+
+fn section_text(s: &ArticleSection) -> String {
+    s.pars.iter().map(paragraph_text).collect::<Vec<_>>().join(" ")
+}
+
+// Paragraph texts may contain inline HTML such as "<br/>"
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Cuts the text at a word boundary after ~SNIPPET_LEN characters
+fn snippet(text: &str) -> String {
+    let text = text
+        .replace("[R]: ", "")
+        .replace("[V]: ", "")
+        .replace("**", "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if text.chars().count() <= SNIPPET_LEN {
+        return text;
+    }
+
+    let mut s = String::new();
+    for w in text.split(' ') {
+        if s.chars().count() + w.chars().count() > SNIPPET_LEN {
+            break;
+        }
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        s.push_str(w);
+    }
+    if s.is_empty() {
+        s = text.chars().take(SNIPPET_LEN).collect();
+    }
+    s + " …"
+}
+
+// Han, Hiragana, Katakana: no whitespace between words
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF |
+        0x3400..=0x4DBF |
+        0x4E00..=0x9FFF |
+        0xF900..=0xFAFF
+    )
+}
+
+/// Splits the text into lowercase, diacritic-free search tokens, returns
+/// (word as written in lowercase, token).
+///
+/// Must stay in sync with `tokenize` in static/js/search.js
+pub fn tokenize_words(s: &str) -> Vec<(String, Token)> {
+    fn flush_word(word: &mut String, tokens: &mut Vec<(String, Token)>) {
+        let folded = normalize_str_to_string(word);
+        if folded.chars().count() >= 2 {
+            tokens.push((word.clone(), folded));
+        }
+        word.clear();
+    }
+
+    // CJK runs are indexed as overlapping bigrams
+    fn flush_cjk(run: &mut Vec<char>, tokens: &mut Vec<(String, Token)>) {
+        if run.len() == 1 {
+            tokens.push((run[0].to_string(), run[0].to_string()));
+        }
+        tokens.extend(run.windows(2).map(|w| {
+            let w = w.iter().collect::<String>();
+            (w.clone(), w)
+        }));
+        run.clear();
+    }
+
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut run = Vec::new();
+
+    for c in s.chars().flat_map(|c| c.to_lowercase()) {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            run.push(c);
+            continue;
+        }
+        flush_cjk(&mut run, &mut tokens);
+        if c.is_alphanumeric() || is_combining_mark(c) {
+            word.push(c);
+        } else {
+            flush_word(&mut word, &mut tokens);
+        }
+    }
+
+    flush_word(&mut word, &mut tokens);
+    flush_cjk(&mut run, &mut tokens);
+    tokens
+}
+
+/// Light stemmer: strips one inflectional suffix (and for Arabic one article
+/// prefix) per word. The tables are shipped to the client in the index.json,
+/// so that queries are stemmed exactly like the indexed text.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stemmer {
+    // minimum number of characters that have to be left of the word
+    pub min: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefixes: Vec<String>,
+    // (suffix, replacement), the first matching suffix wins, as long as
+    // `min` characters are left after replacing it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suffixes: Vec<(String, String)>,
+}
+
+impl Stemmer {
+    pub fn new(lang: &str) -> Self {
+        let (min, prefixes, suffixes): (usize, &[&str], &[(&str, &str)]) = match lang {
+            "de" => (3, &[], &[
+                ("ern", ""), ("em", ""), ("en", ""), ("er", ""), ("es", ""),
+                ("e", ""), ("n", ""), ("s", ""),
+            ]),
+            "en" => (3, &[], &[
+                ("sses", "ss"), ("ies", "i"), ("ing", ""), ("ss", "ss"), ("ed", ""), ("s", ""),
+            ]),
+            "fr" => (3, &[], &[
+                ("eaux", "eau"), ("aux", "al"), ("es", ""), ("s", ""), ("x", ""), ("e", ""),
+            ]),
+            "es" => (3, &[], &[
+                ("iones", "ion"), ("ces", "z"), ("es", ""), ("os", ""), ("as", ""),
+                ("s", ""), ("o", ""), ("a", ""), ("e", ""),
+            ]),
+            "pt" | "br" => (3, &[], &[
+                ("ões", "ão"), ("ães", "ão"), ("ais", "al"), ("eis", "el"), ("ão", "ão"),
+                ("ns", "m"), ("os", ""), ("as", ""), ("es", ""), ("s", ""),
+                ("o", ""), ("a", ""), ("e", ""),
+            ]),
+            "it" => (3, &[], &[
+                ("che", "c"), ("chi", "c"), ("ghe", "g"), ("ghi", "g"),
+                ("i", ""), ("e", ""), ("a", ""), ("o", ""),
+            ]),
+            "ro" => (3, &[], &[
+                ("urilor", ""), ("ilor", ""), ("elor", ""), ("ului", ""), ("ele", ""),
+                ("ile", ""), ("ul", ""), ("ii", ""), ("le", ""), ("a", ""), ("e", ""), ("i", ""),
+            ]),
+            "pl" => (3, &[], &[
+                ("ami", ""), ("ach", ""), ("owi", ""), ("ów", ""), ("om", ""), ("em", ""),
+                ("ie", ""), ("ą", ""), ("ę", ""), ("y", ""), ("i", ""), ("a", ""),
+                ("e", ""), ("u", ""), ("o", ""),
+            ]),
+            "ru" => (3, &[], &[
+                ("ями", ""), ("ами", ""), ("ого", ""), ("его", ""), ("ому", ""), ("ему", ""),
+                ("ыми", ""), ("ими", ""), ("ах", ""), ("ях", ""), ("ов", ""), ("ев", ""),
+                ("ой", ""), ("ей", ""), ("ом", ""), ("ем", ""), ("ам", ""), ("ям", ""),
+                ("ый", ""), ("ий", ""), ("ая", ""), ("яя", ""), ("ое", ""), ("ее", ""),
+                ("ы", ""), ("и", ""), ("а", ""), ("я", ""), ("о", ""), ("е", ""),
+                ("у", ""), ("ю", ""), ("ь", ""), ("й", ""),
+            ]),
+            "el" => (3, &[], &[
+                ("ες", ""), ("ος", ""), ("ης", ""), ("ας", ""), ("ων", ""), ("ου", ""),
+                ("οι", ""), ("α", ""), ("η", ""), ("ο", ""), ("ε", ""), ("ι", ""), ("υ", ""),
+            ]),
+            "tr" => (3, &[], &[
+                ("lerinin", ""), ("larının", ""), ("lerin", ""), ("ların", ""),
+                ("leri", ""), ("ları", ""), ("ler", ""), ("lar", ""),
+            ]),
+            "ar" => (2, &["وال", "بال", "كال", "فال", "لل", "ال"], &[
+                ("ها", ""), ("ان", ""), ("ات", ""), ("ون", ""), ("ين", ""), ("يه", ""),
+                ("ية", ""), ("ه", ""), ("ة", ""), ("ي", ""),
+            ]),
+            // tl, zh, ko, ja: no stemming
+            _ => (0, &[], &[]),
+        };
+
+        // the rules are written with diacritics, but applied to folded words
+        Stemmer {
+            min,
+            prefixes: prefixes.iter().map(|p| normalize_str_to_string(p)).collect(),
+            suffixes: suffixes
+                .iter()
+                .map(|(s, r)| (normalize_str_to_string(s), normalize_str_to_string(r)))
+                .collect(),
+        }
+    }
+
+    /// Must stay in sync with `stemWord` in static/js/search.js
+    pub fn stem(&self, token: &str) -> Token {
+        if token.chars().any(|c| c.is_numeric() || is_cjk(c)) {
+            return token.to_string();
+        }
+
+        let len = |s: &str| s.chars().count();
+        let mut word = token;
+
+        if let Some(p) = self.prefixes.iter().find(|p| word.starts_with(p.as_str()) && len(word) - len(p) >= self.min) {
+            word = &word[p.len()..];
+        }
+
+        for (suffix, replacement) in self.suffixes.iter() {
+            if word.ends_with(suffix.as_str()) && len(word) - len(suffix) + len(replacement) >= self.min {
+                return format!("{}{replacement}", &word[..word.len() - suffix.len()]);
+            }
+        }
+
+        word.to_string()
+    }
+}
+
+/// Name of the shard file a token is stored in, non-alphanumeric
+/// characters are hex-encoded to keep the file names ASCII
+pub fn shard_name(token: &str) -> String {
+    token
+        .chars()
+        .take(SHARD_PREFIX_LEN)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_string()
+            } else {
+                format!("_{:x}", c as u32)
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_tokenize() {
+    let tokenize = |s: &str| tokenize_words(s).into_iter().map(|(_, t)| t).collect::<Vec<_>>();
+    assert_eq!(
+        tokenize("Über die Straße, à Noël: 12 Æpfel!"),
+        vec!["uber", "die", "strasse", "noel", "12", "aepfel"]
+    );
+    assert_eq!(tokenize("Молитва"), vec!["молитва"]);
+    assert_eq!(tokenize("Ἁγία Σοφίας, Łódź"), vec!["αγια", "σοφιασ", "lodz"]);
+    assert_eq!(tokenize("祈祷文"), vec!["祈祷", "祷文"]);
+    assert_eq!(shard_name("uber"), "ub");
+    assert_eq!(shard_name("молитва"), "_43c_43e");
+}
+
+#[test]
+fn test_fulltext_index() {
+    let a = ParsedArticleAnalyzed {
+        title: "Rosenkranz".to_string(),
+        sections: vec![ArticleSection {
+            title: "Die Geheimnisse".to_string(),
+            indent: 2,
+            pars: vec![Paragraph::Sentence {
+                s: vec![crate::SentenceItem::Text {
+                    text: "Der Rosenkranz hat freudenreiche Geheimnisse.".to_string(),
+                }],
+            }],
+        }],
+        ..Default::default()
+    };
+
+    let mut index = FullTextIndex::new("de");
+    index.add_page("rosenkranz", "/de/rosenkranz", &a);
+
+    assert_eq!(index.entries.len(), 2);
+    assert_eq!(index.pages, vec![("rosenkranz".to_string(), 0)]);
+    assert_eq!(index.entries[1].u, "/de/rosenkranz#die-geheimnisse");
+    let hits = &index.postings["rosenkranz"];
+    assert_eq!(hits[&0], WEIGHT_TITLE);
+    assert_eq!(hits[&1], WEIGHT_BODY);
+    assert_eq!(index.postings["geheimniss"][&1], WEIGHT_HEADING + WEIGHT_BODY);
+    assert_eq!(index.forms["geheimniss"]["geheimnisse"], 2);
+    assert_eq!(
+        index.candidates()["v-g"],
+        vec![("geheimniss".to_string(), "geheimnisse".to_string(), 1)]
+    );
+    assert_eq!(index.shards()["ro"]["rosenkranz"], vec![0, 10, 1, 1]);
+}
+
+#[test]
+fn test_stemmer() {
+    let de = Stemmer::new("de");
+    assert_eq!(de.stem("gebete"), de.stem("gebet"));
+    assert_eq!(de.stem("gebeten"), "gebet");
+    assert_eq!(de.stem("die"), "die");
+
+    let es = Stemmer::new("es");
+    let stem = |s: &str| es.stem(&normalize_str_to_string(s));
+    assert_eq!(stem("oraciones"), stem("oración"));
+    assert_eq!(stem("pío"), "pio");
+
+    let pt = Stemmer::new("pt");
+    let stem = |s: &str| pt.stem(&normalize_str_to_string(s));
+    assert_eq!(stem("orações"), stem("oração"));
+
+    let en = Stemmer::new("en");
+    assert_eq!(en.stem("masses"), en.stem("mass"));
+
+    let ar = Stemmer::new("ar");
+    assert_eq!(ar.stem("الصلاة"), "صلا");
+}
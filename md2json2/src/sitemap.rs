@@ -0,0 +1,426 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::citation::xml_escape;
+use crate::docs::AnalyzedDocuments;
+use crate::{get_root_href, sha256, AnalyzedArticles, ArticlesByTag, Lang, MetaJson, ParsedArticleAnalyzed};
+
+// dubia.cc went online in 2024, older article dates are the dates of
+// historic texts, not of the page
+const MIN_LASTMOD: &str = "2024-01-01";
+
+/// A page written to /dist
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SitemapPage {
+    // None for pages in the root of /dist (/index.html)
+    lang: Option<Lang>,
+    // path without .html, e.g. "/de/angelus"
+    url: String,
+    // pages with the same group are translations of each other
+    group: String,
+    // hash of the article source or of the page contents
+    hash: String,
+    // yyyy-mm-dd
+    lastmod: String,
+}
+
+/// Last known content hash and modification date of a page, stored in
+/// .cache/lastmod.json so that unchanged pages keep their lastmod. The file
+/// isn't part of the repository (see generate_gitignore), deploy.yml keeps
+/// it between runs
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct LastMod {
+    hash: String,
+    lastmod: String,
+}
+
+/// Records every html page written by main() and generates the
+/// sitemap.xml files and robots.txt from them
+#[derive(Debug, Default)]
+pub struct Sitemap {
+    dist: PathBuf,
+    langs: BTreeSet<Lang>,
+    pages: Vec<SitemapPage>,
+    lastmod_file: PathBuf,
+    // contents of the lastmod_file before this build
+    previous: BTreeMap<String, LastMod>,
+    // old url => new url, renamed pages keep their lastmod
+    redirects: BTreeMap<String, String>,
+    // content hash of an article or document => date of its last commit
+    source_dates: BTreeMap<String, String>,
+    today: String,
+}
+
+impl Sitemap {
+    pub fn new(dist: &Path, langs: &[Lang], lastmod_file: &Path) -> Self {
+        let previous = std::fs::read_to_string(lastmod_file)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        Self {
+            dist: dist.to_path_buf(),
+            langs: langs.iter().cloned().collect(),
+            pages: Vec::new(),
+            lastmod_file: lastmod_file.to_path_buf(),
+            previous,
+            redirects: BTreeMap::new(),
+            source_dates: BTreeMap::new(),
+            today: today(),
+        }
+    }
+
+    /// Redirects from old to new urls (without .html), see redirects.rs
+    pub fn set_redirects(&mut self, redirects: &BTreeMap<String, String>) {
+        self.redirects = redirects.clone();
+    }
+
+    /// Commit dates of the sources, see source_dates()
+    pub fn set_source_dates(&mut self, source_dates: BTreeMap<String, String>) {
+        self.source_dates = source_dates;
+    }
+
+    /// Writes the page to disk and adds it to the sitemap
+    ///
+    /// group: id shared by all translations of this page
+    /// source: article or document rendered on this page, its content hash
+    /// and date are used instead of the html, so that template changes
+    /// don't count as modifications
+    pub fn write(
+        &mut self,
+        path: &Path,
+        contents: impl AsRef<[u8]>,
+        group: &str,
+        source: Option<&ParsedArticleAnalyzed>,
+    ) {
+        let contents = contents.as_ref();
+        let _ = std::fs::write(path, contents);
+
+        let rel = match path.strip_prefix(&self.dist) {
+            Ok(o) => o.with_extension(""),
+            Err(_) => return,
+        };
+        let parts = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let lang = parts.first().filter(|l| parts.len() > 1 || self.langs.contains(*l)).cloned();
+        let url = match parts.join("/").as_str() {
+            "index" => "/".to_string(),
+            p => format!("/{p}"),
+        };
+
+        let (hash, date) = match source {
+            Some(a) => (a.sha256.clone(), a.get_iso_date()),
+            None => {
+                let html = String::from_utf8_lossy(contents).replace(get_root_href(), "");
+                (sha256(&html), String::new())
+            }
+        };
+
+        let today = &self.today;
+        let previous = self.previous.get(&url).or_else(|| {
+            self.redirects
+                .iter()
+                .filter(|(_, to)| **to == url)
+                .find_map(|(from, _)| self.previous.get(from))
+        });
+        let lastmod = match previous {
+            Some(l) if l.hash == hash => l.lastmod.clone(),
+            Some(_) => today.clone(),
+            None if date.as_str() >= MIN_LASTMOD && &date <= today => date,
+            // not in the lastmod_file (first build, or a CI run without it):
+            // the last commit of the source is closer than the day of the build
+            None => self.source_dates.get(&hash).cloned().unwrap_or_else(|| today.clone()),
+        };
+
+        self.pages.push(SitemapPage {
+            lang,
+            url,
+            group: group.to_string(),
+            hash,
+            lastmod,
+        });
+    }
+
+    /// Date of the last modification of an already written page (url without .html)
+    pub fn lastmod(&self, url: &str) -> Option<&str> {
+        self.pages.iter().find(|p| p.url == url).map(|p| p.lastmod.as_str())
+    }
+
+    /// Writes /sitemap.xml (sitemap index), one /sitemap-{lang}.xml per
+    /// language and /robots.txt, updates the lastmod_file
+    pub fn write_sitemaps(&self) -> Result<(), String> {
+        let root_href = get_root_href();
+
+        let lastmods = self
+            .pages
+            .iter()
+            .map(|p| {
+                let l = LastMod {
+                    hash: p.hash.clone(),
+                    lastmod: p.lastmod.clone(),
+                };
+                (p.url.clone(), l)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let json = serde_json::to_string_pretty(&lastmods).map_err(|e| e.to_string())?;
+        if let Some(parent) = self.lastmod_file.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        std::fs::write(&self.lastmod_file, json + "\n")
+            .map_err(|e| format!("{}: {e}", self.lastmod_file.display()))?;
+
+        let mut groups = BTreeMap::new();
+        for p in self.pages.iter() {
+            groups.entry(p.group.as_str()).or_insert_with(Vec::new).push(p);
+        }
+
+        let mut sitemaps = BTreeMap::new();
+        for p in self.pages.iter() {
+            let name = p.lang.clone().unwrap_or_else(|| "root".to_string());
+            sitemaps.entry(name).or_insert_with(Vec::new).push(p);
+        }
+
+        let mut index = Vec::new();
+        for (name, pages) in sitemaps.iter() {
+            let mut s = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+            s += "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" xmlns:xhtml=\"http://www.w3.org/1999/xhtml\">\n";
+            for p in pages.iter() {
+                s += "  <url>\n";
+                s += &format!("    <loc>{root_href}{}</loc>\n", xml_escape(&p.url));
+                s += &format!("    <lastmod>{}</lastmod>\n", p.lastmod);
+                let alternates = groups.get(p.group.as_str()).cloned().unwrap_or_default();
+                if alternates.len() > 1 {
+                    for a in alternates.iter() {
+                        let hreflang = a.lang.as_deref().map(hreflang).unwrap_or("x-default");
+                        s += &format!(
+                            "    <xhtml:link rel=\"alternate\" hreflang=\"{hreflang}\" href=\"{root_href}{}\"/>\n",
+                            xml_escape(&a.url)
+                        );
+                    }
+                }
+                s += "  </url>\n";
+            }
+            s += "</urlset>\n";
+
+            let filename = format!("sitemap-{name}.xml");
+            std::fs::write(self.dist.join(&filename), s).map_err(|e| e.to_string())?;
+
+            let newest = pages
+                .iter()
+                .map(|p| p.lastmod.clone())
+                .max()
+                .unwrap_or_default();
+            index.push((filename, newest));
+        }
+
+        let mut s = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+        s += "<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n";
+        for (filename, lastmod) in index.iter() {
+            s += &format!("  <sitemap>\n    <loc>{root_href}/{filename}</loc>\n    <lastmod>{lastmod}</lastmod>\n  </sitemap>\n");
+        }
+        s += "</sitemapindex>\n";
+        std::fs::write(self.dist.join("sitemap.xml"), s).map_err(|e| e.to_string())?;
+
+        let robots = format!("User-agent: *\nAllow: /\n\nSitemap: {root_href}/sitemap.xml\n");
+        std::fs::write(self.dist.join("robots.txt"), robots).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+/// Date (yyyy-mm-dd) of the last commit of every article and document
+/// source, keyed by the content hash of the file (see parse_article), empty
+/// if `cwd` isn't a git checkout with history
+///
+/// Needs the full history: a shallow clone (actions/checkout without
+/// `fetch-depth: 0`) dates every source to the one fetched commit
+pub fn source_dates(cwd: &Path) -> BTreeMap<String, String> {
+    let Ok(out) = std::process::Command::new("git")
+        .arg("-C")
+        .arg(cwd)
+        .args(["log", "--format=%x00%cs", "--name-only", "--", "articles", "docs"])
+        .output()
+    else {
+        return BTreeMap::new();
+    };
+
+    // newest commit first, so the first date of a file is its last change
+    let mut dates = BTreeMap::new();
+    let log = String::from_utf8_lossy(&out.stdout);
+    for commit in log.split('\0').filter(|c| !c.trim().is_empty()) {
+        let mut lines = commit.lines();
+        let date = lines.next().unwrap_or_default().trim();
+        for file in lines.filter(|f| f.ends_with(".md")) {
+            dates.entry(file.to_string()).or_insert_with(|| date.to_string());
+        }
+    }
+
+    dates
+        .into_iter()
+        .filter_map(|(file, date)| Some((sha256(std::fs::read_to_string(cwd.join(file)).ok()?), date)))
+        .collect()
+}
+
+// "br" is Brazilian Portuguese on dubia.cc, not Breton
+pub fn hreflang(lang: &str) -> &str {
+    match lang {
+        "br" => "pt-BR",
+        l => l,
+    }
+}
+
+// yyyy-mm-dd of the build, SOURCE_DATE_EPOCH overrides the clock
+fn today() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+    civil_date(secs / 86400)
+}
+
+// days since 1970-01-01 to yyyy-mm-dd (proleptic gregorian calendar)
+fn civil_date(days: u64) -> String {
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+type PageKey = (Lang, String);
+
+// Pages that declare each other as translations (also indirectly)
+// end up in the same group, named after the first page of the group
+fn group_translations(pages: &BTreeMap<PageKey, Vec<PageKey>>) -> BTreeMap<PageKey, String> {
+    fn find(parent: &BTreeMap<PageKey, PageKey>, k: &PageKey) -> PageKey {
+        let mut k = k.clone();
+        while parent[&k] != k {
+            k = parent[&k].clone();
+        }
+        k
+    }
+
+    let mut parent = pages
+        .keys()
+        .map(|k| (k.clone(), k.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    for (k, translations) in pages.iter() {
+        for t in translations.iter().filter(|t| pages.contains_key(*t)) {
+            let (a, b) = (find(&parent, k), find(&parent, t));
+            if a != b {
+                let (first, other) = if a < b { (a, b) } else { (b, a) };
+                parent.insert(other, first);
+            }
+        }
+    }
+
+    pages
+        .keys()
+        .map(|k| {
+            let (lang, key) = find(&parent, k);
+            (k.clone(), format!("{lang}/{key}"))
+        })
+        .collect()
+}
+
+/// Translation group of every article, keyed by (lang, slug)
+pub fn article_groups(articles: &AnalyzedArticles) -> BTreeMap<PageKey, String> {
+    let pages = articles
+        .map
+        .iter()
+        .flat_map(|(lang, v)| {
+            v.iter().map(move |(slug, a)| {
+                let t = a.translations.iter().map(|(l, s)| (l.clone(), s.clone())).collect();
+                ((lang.clone(), slug.clone()), t)
+            })
+        })
+        .collect();
+
+    group_translations(&pages)
+        .into_iter()
+        .map(|(k, g)| (k, format!("article:{g}")))
+        .collect()
+}
+
+/// Translation group of every document, keyed by (lang, "{collection}/{slug}"),
+/// translations are looked up in the same collection
+pub fn document_groups(documents: &AnalyzedDocuments) -> BTreeMap<PageKey, String> {
+    let pages = documents
+        .map
+        .iter()
+        .flat_map(|(lang, collections)| {
+            collections.iter().flat_map(move |(collection, docs)| {
+                docs.iter().map(move |(slug, d)| {
+                    let t = d
+                        .translations
+                        .iter()
+                        .map(|(l, s)| (l.clone(), format!("{collection}/{s}")))
+                        .collect();
+                    ((lang.clone(), format!("{collection}/{slug}")), t)
+                })
+            })
+        })
+        .collect();
+
+    group_translations(&pages)
+        .into_iter()
+        .map(|(k, g)| (k, format!("doc:{g}")))
+        .collect()
+}
+
+/// Translation group of every tag page, keyed by (lang, tag): the tags with the
+/// same id and the "translations" of the "tag-pages" in meta.json
+pub fn tag_groups(by_tag: &ArticlesByTag, meta: &MetaJson) -> BTreeMap<PageKey, String> {
+    let pages = by_tag
+        .iter()
+        .flat_map(|(lang, tags)| {
+            tags.keys().map(move |tag| {
+                let mut t = by_tag.keys().map(|l| (l.clone(), tag.clone())).collect::<Vec<_>>();
+                let tag_page = meta.tags.get(lang).and_then(|t| t.tag_pages.get(tag));
+                t.extend(tag_page.into_iter().flat_map(|p| p.translations.iter().map(|(l, s)| (l.clone(), s.clone()))));
+                ((lang.clone(), tag.clone()), t)
+            })
+        })
+        .collect();
+
+    group_translations(&pages)
+        .into_iter()
+        .map(|(k, g)| (k, format!("tag:{g}")))
+        .collect()
+}
+
+#[test]
+fn test_sitemap_groups() {
+    assert_eq!(civil_date(0), "1970-01-01");
+    assert_eq!(civil_date(11016), "2000-02-29");
+    assert_eq!(civil_date(20000), "2024-10-04");
+
+    let k = |l: &str, s: &str| (l.to_string(), s.to_string());
+    let mut pages = BTreeMap::new();
+    // fr only links to en, en links to de: all three are one group
+    pages.insert(k("de", "europa"), vec![]);
+    pages.insert(k("en", "europe"), vec![k("de", "europa")]);
+    pages.insert(k("fr", "europe"), vec![k("en", "europe"), k("pl", "missing")]);
+    pages.insert(k("de", "angelus"), vec![]);
+
+    let groups = group_translations(&pages);
+    assert_eq!(groups[&k("fr", "europe")], "de/europa");
+    assert_eq!(groups[&k("en", "europe")], "de/europa");
+    assert_eq!(groups[&k("de", "angelus")], "de/angelus");
+}
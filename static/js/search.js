@@ -152,6 +152,25 @@ function doAjax(options) {
     }
 }
 
+// Maximum number of full text hits shown below the title matches
+const MAX_FULLTEXT_RESULTS = 8;
+// Maximum number of sections shown per page
+const MAX_FULLTEXT_PER_PAGE = 2;
+// Shorter words are not corrected, same as MIN_CANDIDATE_LEN in md2json2
+const MIN_CANDIDATE_LEN = 4;
+
+function getResultUrl(result) {
+    if (result.url) {
+        return result.url;
+    }
+    if (result.doc_type === "document") {
+        const parts = result.id.split('/');
+        const slug = parts[parts.length - 1];
+        return "/$$LANG$$/$$DOC_FOLDER$$/" + result.author + "/" + slug;
+    }
+    return "/$$LANG$$/" + result.id;
+}
+
 function goToFirstArticle(e) {
     var searchterm = document.getElementById("index-search-input").value;
     if (searchterm.length == 0) {
@@ -160,73 +179,226 @@ function goToFirstArticle(e) {
         return false;
     }
     
-    var results = searchArticlesLocal(searchterm);
-    if (results.length < 1) {
+    var facets = getFacets();
+    var results = searchArticlesLocal(searchterm, facets);
+    if (results.length > 0) {
+        window.location.href = getResultUrl(results[0]);
         return false;
     }
-    
-    const result = results[0];
-    let url;
-    
-    if (result.doc_type === "document") {
-        const parts = result.id.split('/');
-        const slug = parts[parts.length - 1];
-        url = "/$$LANG$$/$$DOC_FOLDER$$/" + result.author + "/" + slug;
-    } else {
-        url = "/$$LANG$$/" + result.id;
-    }
-    
-    window.location.href = url;
+
+    searchFullText(searchterm, function(hits, suggestion) {
+        if (hits.length > 0) {
+            window.location.href = hits[0].url;
+        }
+    }, facets);
     return false;
 }
 
+// Reads the filter form, null on pages without one (index page)
+function getFacets() {
+    if (!document.getElementById("search-facets")) {
+        return null;
+    }
+    const v = (id) => document.getElementById(id).value.trim();
+    return {
+        kind: v("facet-kind"),
+        type: v("facet-type"),
+        tag: v("facet-tag"),
+        author: v("facet-author"),
+        collection: v("facet-collection"),
+        from: v("facet-from"),
+        to: v("facet-to"),
+        sort: v("facet-sort"),
+    };
+}
+
+const FACET_PARAMS = ["kind", "type", "tag", "author", "collection", "from", "to", "sort"];
+
+function hasActiveFilter(facets) {
+    return facets != null && FACET_PARAMS.some(p => p !== "sort" && facets[p]);
+}
+
+// Returns whether the window.articles.articles item passes all filters
+function matchesFacets(item, facets) {
+    if (!facets) {
+        return true;
+    }
+    if (!item) {
+        return false;
+    }
+
+    const isDoc = item.doc_type === "document";
+    if (facets.kind === "article" && isDoc) { return false; }
+    if (facets.kind === "document" && !isDoc) { return false; }
+    if (facets.type && (item.atype || "tract") !== facets.type) { return false; }
+    if (facets.tag && !(item.tags || []).includes(facets.tag)) { return false; }
+    if (facets.author && !(item.authors || []).includes(facets.author)) { return false; }
+    if (facets.collection && !(isDoc && item.author === facets.collection)) { return false; }
+
+    const year = parseInt((item.date || "").split("-")[0]);
+    if (facets.from && !(year >= parseInt(facets.from))) { return false; }
+    if (facets.to && !(year <= parseInt(facets.to))) { return false; }
+    return true;
+}
+
+// Newest first, undated articles last
+function sortByDate(results) {
+    const date = (r) => (window.articles.articles[r.id] || {}).date || "";
+    return results
+        .map((r, i) => [r, i])
+        .sort((a, b) => date(b[0]).localeCompare(date(a[0])) || (a[1] - b[1]))
+        .map(q => q[0]);
+}
+
+// Lists all articles passing the filters, used when there is no search term
+function listArticles(facets) {
+    const results = [];
+    for (var id in window.articles.articles) {
+        const item = window.articles.articles[id];
+        if (matchesFacets(item, facets)) {
+            results.push({ id: id, title: item.title, doc_type: item.doc_type, author: item.author });
+        }
+    }
+    results.sort((a, b) => a.title.localeCompare(b.title));
+    return facets.sort === "date" ? sortByDate(results) : results;
+}
+
+// Keeps the search term and filters in the URL, so that filtered searches can be linked
+function updateSearchUrl(searchterm, facets) {
+    if (!facets || !window.history || !window.history.replaceState) {
+        return;
+    }
+    const params = new URLSearchParams();
+    if (searchterm) {
+        params.set("q", searchterm);
+    }
+    for (const p of FACET_PARAMS) {
+        if (facets[p]) {
+            params.set(p, facets[p]);
+        }
+    }
+    const query = params.toString();
+    window.history.replaceState(null, "", window.location.pathname + (query ? "?" + query : ""));
+}
+
+function applySearchUrl() {
+    const params = new URLSearchParams(window.location.search);
+    const input = document.getElementById("index-search-input");
+    if (input && params.get("q")) {
+        input.value = params.get("q");
+    }
+    if (!document.getElementById("search-facets")) {
+        return;
+    }
+    for (const p of FACET_PARAMS) {
+        if (params.get(p)) {
+            document.getElementById("facet-" + p).value = params.get(p);
+        }
+    }
+}
+
+function escapeHtml(s) {
+    return String(s)
+        .replaceAll("&", "&amp;")
+        .replaceAll("<", "&lt;")
+        .replaceAll(">", "&gt;")
+        .replaceAll("'", "&#39;")
+        .replaceAll("\"", "&quot;");
+}
+
+// Wraps every word of the snippet that matches one of the query stems in <mark>
+function highlightSnippet(snippet, stems) {
+    return snippet.split(/([\p{Alphabetic}\p{N}\p{M}]+)/u).map(part => {
+        const hit = queryStems(part).some(t => stems.some(q => t.startsWith(q)));
+        return hit ? "<mark>" + escapeHtml(part) + "</mark>" : escapeHtml(part);
+    }).join("");
+}
+
+function createResultHTML(result, tokens) {
+    const url = getResultUrl(result);
+    let label = escapeHtml(result.title);
+    if (result.heading) {
+        label += " › " + escapeHtml(result.heading);
+    }
+
+    let a = "<li class='link-modified-recently-list-item dark-mode-invert'>";
+    a += "<p class='in-list first-graf block' style='--bsm: 0;'>";
+    a += "<a href='" + escapeHtml(url) + "'";
+    a += " class='link-annotated link-page in-list has-annotation spawns-popup' ";
+    a += " data-attribute-title='" + escapeHtml(result.title) + "'>" + label + "</a>";
+    if (result.snippet) {
+        a += "<br/><span class='search-snippet'>" + highlightSnippet(result.snippet, tokens) + "</span>";
+    }
+    a += "</p>";
+    a += "</li>";
+    return a;
+}
+
+// "Did you mean …", clicking it replaces the search term
+function createSuggestionHTML(suggestion) {
+    const link = "<a href='#' class='search-suggestion' data-suggestion='" + escapeHtml(suggestion) + "'"
+        + " onclick='useSuggestion(this); return false;'>" + escapeHtml(suggestion) + "</a>";
+    return "<p id='did-you-mean' style='padding-left:10px;'>" + "$$DID_YOU_MEAN$$".replace("$$QUERY$$", link) + "</p>";
+}
+
+function useSuggestion(el) {
+    document.getElementById("index-search-input").value = el.getAttribute("data-suggestion");
+    searchAndDisplayArticles();
+}
+
+window.useSuggestion = useSuggestion;
+
 function searchAndDisplayArticles(e) {
     var searchterm = document.getElementById("index-search-input").value;
     var target = document.getElementById("index-search-results");
     var no_results = "<p id='no-results' style='padding-left:10px;'>$$NO_RESULTS$$</p>"
     
+    var facets = getFacets();
+    updateSearchUrl(searchterm, facets);
+
     if (searchterm.length < 3) {
-        displayDefaultLinks(target);
+        if (!hasActiveFilter(facets) || !window.articles) {
+            displayDefaultLinks(target);
+            return false;
+        }
+        var all = listArticles(facets);
+        target.innerHTML = all.length < 1 ? no_results :
+            "<ul class='list'>" + all.map(r => createResultHTML(r, [])).join("") + "</ul>";
         return false;
     } 
 
-    var results = searchArticlesLocal(searchterm);
-    
-    if (results.length < 1) {
-        target.innerHTML = no_results;
-        return false;
+    var results = searchArticlesLocal(searchterm, facets);
+    if (results.length > 0) {
+        const stems = queryStems(searchterm);
+        target.innerHTML = "<ul class='list'>" + results.map(r => createResultHTML(r, stems)).join("") + "</ul>";
     }
-    
-    let a = "";
-    for (let i = 0; i < results.length; i++) {
-        const result = results[i];
-        let id = result.id;
-        let title = result.title;
-        let url;
-        
-        // Generate correct URL based on result type
-        if (result.doc_type === "document") {
-            const parts = id.split('/');
-            const slug = parts[parts.length - 1];
-            url = "/$$LANG$$/$$DOC_FOLDER$$/" + result.author + "/" + slug;
-        } else {
-            url = "/$$LANG$$/" + id;
+
+    searchFullText(searchterm, function(hits, suggestion) {
+        // the user kept typing, a newer search is running
+        if (document.getElementById("index-search-input").value !== searchterm) {
+            return;
         }
-        
-        a += "<li class='link-modified-recently-list-item dark-mode-invert'>";
-        a += "<p class='in-list first-graf block' style='--bsm: 0;'>";
-        a += "<a href='" + url + "' id='sr-" + id;
-        a += "' class='link-annotated link-page in-list has-annotation spawns-popup' ";
-        a += " data-attribute-title='" + title + "'>" + title + "</a>";
-        a += "</p>";
-        a += "</li>";
-    }
 
-    target.innerHTML = "<ul class='list'>" + a + "</ul>";
+        const seen = new Set(results.map(getResultUrl));
+        let all = results.concat(hits.filter(h => !seen.has(h.url)));
+        if (facets && facets.sort === "date") {
+            all = sortByDate(all);
+        }
+        const didYouMean = suggestion ? createSuggestionHTML(suggestion) : "";
+        if (all.length < 1) {
+            target.innerHTML = didYouMean + no_results;
+            return;
+        }
+
+        const stems = queryStems(suggestion || searchterm);
+        target.innerHTML = didYouMean + "<ul class='list'>" + all.map(r => createResultHTML(r, stems)).join("") + "</ul>";
+    }, facets);
+
     return false;
 }
 
-function searchArticlesLocal(searchterm) {
+// Searches the slugs and titles of window.articles
+function searchArticlesLocal(searchterm, facets) {
     if (searchterm.length == 0) {
         return [];
     }
@@ -236,6 +408,8 @@ function searchArticlesLocal(searchterm) {
         return [];
     }
 
+    // "Zolibat" finds "Zölibat"
+    var foldedterm = tokenize(searchterm).join(" ");
     var searchterm = searchterm.toLowerCase();
     for (q of TOREPLACE) {
         searchterm = searchterm.replaceAll(q, "");
@@ -254,6 +428,9 @@ function searchArticlesLocal(searchterm) {
         }
         
         const item = window.articles.articles[id];
+        if (!matchesFacets(item, facets)) {
+            continue;
+        }
         
         if (id.toLowerCase().includes(searchterm)) {
             if (item.doc_type === "document") {
@@ -284,9 +461,12 @@ function searchArticlesLocal(searchterm) {
         }
         
         const item = window.articles.articles[id];
-        var title = item.title;
+        if (!matchesFacets(item, facets)) {
+            continue;
+        }
+        var title = tokenize(item.title).join(" ");
         
-        if (title.toLowerCase().includes(searchterm)) {
+        if (foldedterm.length > 0 && title.includes(foldedterm)) {
             if (item.doc_type === "document") {
                 results.push({
                     id: id, 
@@ -305,125 +485,395 @@ function searchArticlesLocal(searchterm) {
         }
     }
 
-    // Finally search content
-    for (var id in window.articles.articles) {
-        if (results.length > 3) {
-            break;
+    return results;
+}
+
+// Han, Hiragana, Katakana: no whitespace between words
+function isCjk(c) {
+    const cp = c.codePointAt(0);
+    return (cp >= 0x3040 && cp <= 0x30FF)
+        || (cp >= 0x3400 && cp <= 0x4DBF)
+        || (cp >= 0x4E00 && cp <= 0x9FFF)
+        || (cp >= 0xF900 && cp <= 0xFAFF);
+}
+
+// Same as normalize_char_to_string in md2json2
+function normalizeChar(c) {
+    if (c.codePointAt(0) < 128) {
+        return c;
+    }
+    if (c === "ß") { return "ss"; }
+    if (c === "æ" || c === "Æ") { return "ae"; }
+    if (c === "œ" || c === "Œ") { return "oe"; }
+    if (c === "ł" || c === "Ł") { return "l"; }
+    if (c === "ø" || c === "Ø") { return "o"; }
+    if (c === "đ" || c === "Đ") { return "d"; }
+    if (c === "ı") { return "i"; }
+    for (const d of c.normalize("NFD")) {
+        if (/^[a-zA-Z]$/.test(d)) {
+            return d;
         }
-        if (results_contains.hasOwnProperty(id)) {
+    }
+    return c;
+}
+
+// Same as normalize_str_to_string in md2json2: also removes the
+// diacritics of non-Latin scripts
+function normalizeWord(w) {
+    let out = "";
+    for (const c0 of w) {
+        for (const c of normalizeChar(c0)) {
+            const d = c.normalize("NFD");
+            out += /\p{M}/u.test(d) ? d.replace(/\p{M}/gu, "") : c;
+        }
+    }
+    return out.replaceAll("ς", "σ");
+}
+
+// Must stay in sync with `tokenize` in md2json2/src/search.rs
+function tokenize(s) {
+    const tokens = [];
+    let word = "";
+    let run = [];
+
+    const flushWord = () => {
+        const folded = normalizeWord(word);
+        if ([...folded].length >= 2) {
+            tokens.push(folded);
+        }
+        word = "";
+    };
+
+    // CJK runs are indexed as overlapping bigrams
+    const flushCjk = () => {
+        if (run.length === 1) {
+            tokens.push(run[0]);
+        }
+        for (let i = 0; i + 1 < run.length; i++) {
+            tokens.push(run[i] + run[i + 1]);
+        }
+        run = [];
+    };
+
+    for (const c of s.toLowerCase()) {
+        if (isCjk(c)) {
+            flushWord();
+            run.push(c);
             continue;
         }
-        
-        const item = window.articles.articles[id];
-        var sha256 = item.sha256;
-        var mdfile = localStorage.getItem("b" + sha256);
-        
-        if (!mdfile) {
-            // If document content not in localStorage, try to load it
-            if (item.doc_type === "document") {
-                loadDocumentContent(id, sha256);
+        flushCjk();
+        if (/[\p{Alphabetic}\p{N}\p{M}]/u.test(c)) {
+            word += c;
+        } else {
+            flushWord();
+        }
+    }
+
+    flushWord();
+    flushCjk();
+    return tokens;
+}
+
+// Must stay in sync with `Stemmer::stem` in md2json2/src/search.rs,
+// the rules come from the index.json
+function stemWord(token, stemmer) {
+    if (!stemmer || /\p{N}/u.test(token) || [...token].some(isCjk)) {
+        return token;
+    }
+
+    const len = (s) => [...s].length;
+    let word = token;
+
+    for (const p of (stemmer.prefixes || [])) {
+        if (word.startsWith(p) && len(word) - len(p) >= stemmer.min) {
+            word = word.slice(p.length);
+            break;
+        }
+    }
+
+    for (const [suffix, replacement] of (stemmer.suffixes || [])) {
+        if (word.endsWith(suffix) && len(word) - len(suffix) + len(replacement) >= stemmer.min) {
+            return word.slice(0, word.length - suffix.length) + replacement;
+        }
+    }
+
+    return word;
+}
+
+// Damerau-Levenshtein distance (optimal string alignment)
+function editDistance(a, b) {
+    a = [...a];
+    b = [...b];
+    const d = [];
+    for (let i = 0; i <= a.length; i++) {
+        d.push([i]);
+    }
+    for (let j = 1; j <= b.length; j++) {
+        d[0][j] = j;
+    }
+    for (let i = 1; i <= a.length; i++) {
+        for (let j = 1; j <= b.length; j++) {
+            const cost = a[i - 1] === b[j - 1] ? 0 : 1;
+            d[i][j] = Math.min(d[i - 1][j] + 1, d[i][j - 1] + 1, d[i - 1][j - 1] + cost);
+            if (i > 1 && j > 1 && a[i - 1] === b[j - 2] && a[i - 2] === b[j - 1]) {
+                d[i][j] = Math.min(d[i][j], d[i - 2][j - 2] + 1);
             }
-            continue;
         }
-        
-        if (mdfile.includes(searchterm)) {
-            if (item.doc_type === "document") {
-                results.push({
-                    id: id, 
-                    title: item.title,
-                    doc_type: "document",
-                    author: item.author
-                });
-            } else {
-                results.push({
-                    id: id, 
-                    title: item.title
-                });
+    }
+    return d[a.length][b.length];
+}
+
+// Name of the /$$LANG$$/fulltext/{shard}.json file containing the token
+function shardName(token) {
+    return [...token].slice(0, 2).map(c =>
+        /^[a-z0-9]$/.test(c) ? c : "_" + c.codePointAt(0).toString(16)
+    ).join("");
+}
+
+const fulltextCache = { shards: {}, chunks: {}, candidates: {} };
+
+// Downloads all given shard or entry files, then calls the callback
+function loadFullTextFiles(kind, names, callback) {
+    const cache = fulltextCache[kind];
+    const missing = names.filter(n => !cache.hasOwnProperty(n));
+    let pending = missing.length;
+    if (pending === 0) {
+        callback();
+        return;
+    }
+
+    const done = () => {
+        pending -= 1;
+        if (pending === 0) {
+            callback();
+        }
+    };
+
+    for (const name of missing) {
+        doAjax({
+            location: `${location.origin}/$$LANG$$/fulltext/${name}.json?v=$$VERSION$$`,
+            onSuccess: function(event) {
+                try {
+                    cache[name] = JSON.parse(event.target.responseText);
+                } catch(e) {
+                    cache[name] = null;
+                }
+                done();
+            },
+            onFailure: function(event) {
+                console.error("searchFullText: could not load " + name, event);
+                done();
             }
-            results_contains[id] = "";
+        });
+    }
+}
+
+// Returns the key of the window.articles.articles item the entry belongs to
+function getEntryPage(manifest, entry) {
+    let lo = 0;
+    let hi = manifest.pages.length - 1;
+    while (lo < hi) {
+        const mid = (lo + hi + 1) >> 1;
+        if (manifest.pages[mid][1] <= entry) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
         }
     }
+    return manifest.pages.length > 0 ? manifest.pages[lo][0] : null;
+}
 
-    return results;
+// Folded and stemmed words of the query, the same way the index was built
+function queryStems(searchterm) {
+    const manifest = window.articles && window.articles.fulltext;
+    return tokenize(searchterm).map(w => stemWord(w, manifest && manifest.stemmer));
 }
 
-// Load document content when needed
-function loadDocumentContent(id, sha256) {
-    const item = window.articles.articles[id];
-    if (!item || item.doc_type !== "document") {
+// Whether the index contains the stem, the last word may still be incomplete
+function hasStem(stem, isLast) {
+    const shard = fulltextCache.shards[shardName(stem)];
+    if (!shard) {
+        return false;
+    }
+    return shard.hasOwnProperty(stem) || (isLast && Object.keys(shard).some(t => t.startsWith(stem)));
+}
+
+// Finds the closest word of the site's vocabulary for a stem that is
+// not in the index, calls `callback` with { stem, form } or null
+function findCandidate(stem, manifest, callback) {
+    const chars = [...stem];
+    if (chars.length < MIN_CANDIDATE_LEN || chars.some(c => isCjk(c) || !/\p{Alphabetic}/u.test(c))) {
+        callback(null);
         return;
     }
-    
-    const parts = id.split('/');
-    const slug = parts[parts.length - 1];
-    const author = item.author;
-    
-    console.log("downloading document content for $$LANG$$/$$DOC_FOLDER$$/" + author + "/" + slug);
-    doAjax({
-        location: `${location.origin}/docs/$$LANG$$/${author}/${slug}.md`,
-        onSuccess: function(event) {
-            let t = event.target.responseText.toLowerCase();
-            for (q of TOREPLACE) {
-                t = t.replaceAll(q, "");
+
+    const available = new Set(manifest.candidates || []);
+    const files = ["v-" + shardName(chars[0]), "w-" + shardName(chars[1])].filter(f => available.has(f));
+    loadFullTextFiles("candidates", files, function() {
+        const maxDistance = chars.length <= 7 ? 1 : 2;
+        let best = null;
+        for (const f of files) {
+            // sorted by the number of entries, so the more common word wins ties
+            for (const [candidate, form, _count] of (fulltextCache.candidates[f] || [])) {
+                if (Math.abs([...candidate].length - chars.length) > maxDistance) {
+                    continue;
+                }
+                const d = editDistance(stem, candidate);
+                if (d <= maxDistance && (!best || d < best.distance)) {
+                    best = { stem: candidate, form: form, distance: d };
+                }
             }
-            localStorage.setItem("b" + sha256, t);
         }
+        callback(best);
     });
 }
 
-function checkArticlesAreInitialized(force) {
-    var do_force = force === true;
+// Replaces the stems that are not in the index with the closest known word,
+// calls `callback` with the corrected stems and the corrected query (or null)
+function correctStems(words, stems, manifest, callback) {
+    const fixed = stems.slice();
+    const suggestion = words.slice();
+    const unknown = stems
+        .map((s, i) => i)
+        .filter(i => !hasStem(stems[i], i === stems.length - 1));
+
+    let changed = false;
+    const next = (k) => {
+        if (k >= unknown.length) {
+            callback(fixed, changed ? suggestion.join(" ") : null);
+            return;
+        }
+        const i = unknown[k];
+        findCandidate(stems[i], manifest, function(c) {
+            if (c) {
+                fixed[i] = c.stem;
+                suggestion[i] = c.form;
+                changed = true;
+            }
+            next(k + 1);
+        });
+    };
+    next(0);
+}
 
-    if (!(window.articles && window.articles != null && window.articles != undefined)) {
-        console.error("checkArticlesAreInitialized called but window.articles not initialized!");
+// Searches the full text index, calls `callback` with a list of
+// { id, url, title, heading, snippet }, best hits first, and the
+// corrected query if some words were misspelled ("Did you mean")
+function searchFullText(searchterm, callback, facets) {
+    const manifest = window.articles && window.articles.fulltext;
+    const words = tokenize(searchterm);
+    if (!manifest || words.length === 0) {
+        callback([], null);
         return;
     }
 
-    for (var id in window.articles.articles) {
-        const item = window.articles.articles[id];
-        let sha256 = item.sha256;
-        let cachedarticle = localStorage.getItem("b" + sha256);
-        
-        if (!do_force && cachedarticle) {
-            continue;
-        }
-        
-        // For documents, use a different path scheme
-        if (item.doc_type === "document") {
-            const parts = id.split('/');
-            const slug = parts[parts.length - 1];
-            const author = item.author;
-            
-            console.log("downloading document for $$LANG$$/$$DOC_FOLDER$$/" + author + "/" + slug);
-            doAjax({
-                location: `${location.origin}/docs/$$LANG$$/${author}/${slug}.md`,
-                onSuccess: function(event) {
-                    let t = event.target.responseText.toLowerCase();
-                    for (q of TOREPLACE) {
-                        t = t.replaceAll(q, "");
-                    }
-                    localStorage.setItem("b" + sha256, t);
-                }
+    const available = new Set(manifest.shards);
+    const shardsOf = (stems) => [...new Set(stems.map(shardName))].filter(s => available.has(s));
+    const stems = words.map(w => stemWord(w, manifest.stemmer));
+
+    loadFullTextFiles("shards", shardsOf(stems), function() {
+        correctStems(words, stems, manifest, function(fixed, suggestion) {
+            loadFullTextFiles("shards", shardsOf(fixed), function() {
+                rankFullText(fixed, manifest, facets, function(hits) {
+                    callback(hits, suggestion);
+                });
             });
-        } else {
-            // Regular article
-            console.log("downloading index.md file for $$LANG$$/" + id);
-            doAjax({
-                location: `${location.origin}/articles/$$LANG$$/${id}/index.md`,
-                onSuccess: function(event) {
-                    let t = event.target.responseText.toLowerCase();
-                    for (q of TOREPLACE) {
-                        t = t.replaceAll(q, "");
-                    }
-                    localStorage.setItem("b" + sha256, t);
-                }
+        });
+    });
+}
+
+// Ranks the entries containing the (already loaded) stems
+function rankFullText(stems, manifest, facets, callback) {
+    const scores = {};
+    const matched = {};
+
+    stems.forEach((token, i) => {
+        const shard = fulltextCache.shards[shardName(token)];
+        if (!shard) {
+            return;
+        }
+        // the last word may still be incomplete
+        const isLast = i === stems.length - 1;
+        for (const t in shard) {
+            let factor;
+            if (t === token) {
+                factor = 1;
+            } else if (isLast && t.startsWith(token)) {
+                factor = 0.5;
+            } else {
+                continue;
+            }
+            const postings = shard[t];
+            for (let j = 0; j < postings.length; j += 2) {
+                const e = postings[j];
+                scores[e] = (scores[e] || 0) + postings[j + 1] * factor;
+                (matched[e] = matched[e] || new Set()).add(i);
+            }
+        }
+    });
+
+    // entries matching more of the query words first, then by score
+    const ids = Object.keys(scores)
+        .map(Number)
+        .filter(e => matchesFacets(window.articles.articles[getEntryPage(manifest, e)], facets));
+    ids.sort((a, b) => (matched[b].size - matched[a].size) || (scores[b] - scores[a]) || (a - b));
+    const top = ids.slice(0, MAX_FULLTEXT_RESULTS * MAX_FULLTEXT_PER_PAGE);
+    const chunks = [...new Set(top.map(e => "e" + Math.floor(e / manifest.chunk)))];
+
+    loadFullTextFiles("chunks", chunks, function() {
+        const hits = [];
+        const perPage = {};
+        for (const e of top) {
+            const chunk = fulltextCache.chunks["e" + Math.floor(e / manifest.chunk)];
+            const entry = chunk && chunk[e % manifest.chunk];
+            if (!entry) {
+                continue;
+            }
+            const page = entry.u.split("#")[0];
+            perPage[page] = (perPage[page] || 0) + 1;
+            if (perPage[page] > MAX_FULLTEXT_PER_PAGE) {
+                continue;
+            }
+            hits.push({
+                id: getEntryPage(manifest, e),
+                url: entry.u,
+                title: entry.t,
+                heading: entry.h || "",
+                snippet: entry.x,
             });
+            if (hits.length >= MAX_FULLTEXT_RESULTS) {
+                break;
+            }
+        }
+        callback(hits);
+    });
+}
+
+// Removes the Markdown files older versions of this script cached for searching
+function removeCachedArticles() {
+    const keys = [];
+    for (let i = 0; i < localStorage.length; i++) {
+        const key = localStorage.key(i);
+        if (/^b[0-9a-f]{64}$/.test(key)) {
+            keys.push(key);
         }
     }
+    keys.forEach(k => localStorage.removeItem(k));
 }
 
 window.searchArticlesLocal = searchArticlesLocal;
-window.checkArticlesAreInitialized = checkArticlesAreInitialized;
+window.searchFullText = searchFullText;
+
+var searchIndexCallbacks = [];
+
+// Runs the callback once window.articles is loaded
+function onSearchIndexReady(callback) {
+    if (window.articles && window.articles.fulltext) {
+        callback();
+    } else {
+        searchIndexCallbacks.push(callback);
+    }
+}
 
 function initSearchIndex(force) {
     var do_force = force === true;
@@ -435,8 +885,8 @@ function initSearchIndex(force) {
             window.articles && 
             window.articles != null && 
             window.articles != undefined && 
-            window.articles.git == version) {
-            checkArticlesAreInitialized(force);
+            window.articles.git == version &&
+            window.articles.fulltext) {
             return;
         } else {
             try {
@@ -445,9 +895,9 @@ function initSearchIndex(force) {
                     b && 
                     b != null && 
                     b != undefined && 
-                    b.git == version) {
+                    b.git == version &&
+                    b.fulltext) {
                     window.articles = b;
-                    checkArticlesAreInitialized(force);
                     return;
                 }
             } catch(e) {
@@ -463,11 +913,12 @@ function initSearchIndex(force) {
         location: `${location.origin}/$$LANG$$/index.json`,
         onSuccess: function(event) {
             var tar = JSON.parse(event.target.responseText);
+            removeCachedArticles();
             localStorage.setItem('articles', JSON.stringify(tar));
             window.articles = tar;
-            checkArticlesAreInitialized(force);
+            searchIndexCallbacks.splice(0).forEach(c => c());
         },
-        onError: function(event) {
+        onFailure: function(event) {
             console.error(event);
         }
     });
@@ -486,6 +937,22 @@ document.addEventListener('DOMContentLoaded', () => {
     if (searchInput && target && searchInput.value.length === 0) {
         displayDefaultLinks(target);
     }
+
+    applySearchUrl();
+    const facets = document.getElementById("search-facets");
+    if (facets) {
+        facets.onchange = function(event) { searchAndDisplayArticles(event); return false; };
+        document.getElementById("search-facets-reset").onclick = function(event) {
+            facets.reset();
+            searchAndDisplayArticles(event);
+            return false;
+        };
+    }
+    onSearchIndexReady(function() {
+        if (searchInput && (searchInput.value.length > 0 || hasActiveFilter(getFacets()))) {
+            searchAndDisplayArticles();
+        }
+    });
 });
 document.getElementById("index-search-form").onsubmit = function(event) { goToFirstArticle(event); return false; };
 document.getElementById("index-search-input").onkeyup = function(event) { searchAndDisplayArticles(event); return false; };
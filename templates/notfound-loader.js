@@ -0,0 +1,16 @@
+(function() {
+  // /404.html is served for all missing pages, show the one of the requested language
+  const langs = $$LANGS$$;
+  const lang = window.location.pathname.split('/')[1].replace(/\.html$/, '');
+  if (lang === '$$DEFAULT_LANG$$' || langs.indexOf(lang) === -1 || !window.fetch) {
+    return;
+  }
+  fetch('/' + lang + '/404.html')
+    .then(r => r.ok ? r.text() : Promise.reject(r.status))
+    .then(html => {
+      document.open();
+      document.write(html);
+      document.close();
+    })
+    .catch(() => {});
+})();
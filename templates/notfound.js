@@ -0,0 +1,48 @@
+(function() {
+  // [slug, url, title] of all pages in this language
+  const pages = $$PAGES$$;
+  const maxSuggestions = 5;
+
+  function editDistance(a, b) {
+    let prev = Array.from({length: b.length + 1}, (_, i) => i);
+    for (let i = 1; i <= a.length; i++) {
+      const cur = [i];
+      for (let j = 1; j <= b.length; j++) {
+        const cost = a[i - 1] === b[j - 1] ? 0 : 1;
+        cur[j] = Math.min(prev[j] + 1, cur[j - 1] + 1, prev[j - 1] + cost);
+      }
+      prev = cur;
+    }
+    return prev[b.length];
+  }
+
+  const path = decodeURIComponent(window.location.pathname).replace(/\.html$/, '').replace(/\/+$/, '');
+  const slug = path.split('/').pop().toLowerCase();
+
+  const input = document.getElementById('index-search-input');
+  if (input && slug) {
+    input.value = slug.replace(/[-_]+/g, ' ');
+  }
+
+  const maxDistance = Math.max(2, Math.floor(slug.length / 2));
+  const suggestions = pages
+    .map(p => [editDistance(slug, p[0]), p])
+    .filter(s => slug.length > 0 && s[0] <= maxDistance)
+    .sort((a, b) => a[0] - b[0] || a[1][2].localeCompare(b[1][2]))
+    .slice(0, maxSuggestions);
+
+  const target = document.getElementById('notfound-suggestions');
+  if (!target || suggestions.length === 0) {
+    return;
+  }
+  const list = target.querySelector('ul');
+  for (const [, p] of suggestions) {
+    const li = document.createElement('li');
+    const a = document.createElement('a');
+    a.href = p[1];
+    a.textContent = p[2];
+    li.appendChild(a);
+    list.appendChild(li);
+  }
+  target.style.display = 'block';
+})();
@@ -5,5 +5,46 @@ if ('serviceWorker' in navigator) {
     }).catch(registrationError => {
       console.log('Service Worker registration failed: ', registrationError)
     })
+
+    // the pages of this language are cached on the first visit
+    const lang = location.pathname.split('/')[1].replace(/\.html$/, '')
+    navigator.serviceWorker.ready.then(registration => {
+      registration.active.postMessage({type: 'offline', lang: lang, collection: 'site', auto: true})
+    })
+
+    // "download for offline" buttons
+    document.querySelectorAll('.offline-download').forEach(el => {
+      const button = el.querySelector('button')
+      const progress = el.querySelector('progress')
+      const status = el.querySelector('.offline-download-status')
+      const collection = el.dataset.offlineCollection
+      const collections = collection ? [collection] : ['site', 'docs']
+      Promise.all(collections.map(c => fetch('/' + el.dataset.offlineLang + '/offline/' + c + '.json')
+        .then(r => r.ok ? r.json() : {bytes: 0}).catch(() => ({bytes: 0}))))
+        .then(m => {
+          const mb = m.reduce((a, b) => a + b.bytes, 0) / 1024 / 1024
+          status.textContent = mb.toFixed(1) + ' MB'
+        })
+      el.hidden = false
+      button.addEventListener('click', () => {
+        button.disabled = true
+        progress.hidden = false
+        navigator.serviceWorker.ready.then(registration => {
+          const channel = new MessageChannel()
+          channel.port1.onmessage = e => {
+            if (e.data.type === 'progress') {
+              progress.max = e.data.total
+              progress.value = e.data.done
+            } else if (e.data.type === 'done') {
+              status.textContent = el.dataset.offlineDone
+            } else if (e.data.type === 'error') {
+              status.textContent = el.dataset.offlineError
+              button.disabled = false
+            }
+          }
+          registration.active.postMessage({type: 'offline', lang: el.dataset.offlineLang, collection: collection}, [channel.port2])
+        })
+      })
+    })
   })
-}
\ No newline at end of file
+}
@@ -0,0 +1,19 @@
+
+// Navigations that are neither precached nor reachable get the offline page
+workbox.routing.registerRoute(
+  ({event}) => event.request.mode === 'navigate',
+  workbox.strategies.networkOnly()
+);
+
+workbox.routing.setCatchHandler(({url, event}) => {
+  if (event.request.mode !== 'navigate') {
+    return Response.error();
+  }
+  const langs = $$LANGS$$;
+  let lang = url.pathname.split('/')[1].replace(/\.html$/, '');
+  if (langs.indexOf(lang) === -1) {
+    lang = 'en';
+  }
+  return caches.match('/' + lang + '/offline.html', {ignoreSearch: true})
+    .then(response => response || Response.error());
+});
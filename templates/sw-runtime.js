@@ -0,0 +1,115 @@
+
+// Pages of a language are cached on the first visit to /{lang}/, documents
+// when the reader presses "download for offline", see /{lang}/offline/*.json
+const offlineLangs = $$LANGS$$;
+const offlineRevisions = $$OFFLINE_REVISIONS$$;
+const docsPaths = $$DOCS_PATHS$$;
+const offlineState = '/offline-state.json';
+
+const offlineCacheName = (lang, collection) => 'dubia-' + lang + '-' + collection;
+
+// downloads the files of the manifest that changed since the last time,
+// reports {done, total} to the port of the page that asked for it
+const cacheCollection = async (lang, collection, auto, port) => {
+  const key = lang + '/' + collection;
+  const cache = await caches.open(offlineCacheName(lang, collection));
+  const state = await cache.match(offlineState)
+    .then(r => r ? r.json() : {revision: '', files: {}})
+    .catch(() => ({revision: '', files: {}}));
+  if (auto && state.revision === offlineRevisions[key]) {
+    return;
+  }
+
+  const manifest = await fetch('/' + lang + '/offline/' + collection + '.json', {cache: 'no-cache'})
+    .then(r => r.json());
+  const files = {};
+  let done = 0;
+  for (const entry of manifest.entries) {
+    const cached = state.files[entry.url] === entry.revision && await cache.match(entry.url);
+    if (!cached) {
+      const response = await fetch(entry.url, {cache: 'no-cache'});
+      if (!response.ok) {
+        continue;
+      }
+      await cache.put(entry.url, response);
+    }
+    files[entry.url] = entry.revision;
+    done += 1;
+    if (port) {
+      port.postMessage({type: 'progress', done: done, total: manifest.entries.length});
+    }
+  }
+  for (const url of Object.keys(state.files)) {
+    if (!(url in files)) {
+      await cache.delete(url);
+    }
+  }
+  await cache.put(offlineState, new Response(JSON.stringify({revision: manifest.revision, files: files})));
+  if (port) {
+    port.postMessage({type: 'done', done: done, total: manifest.entries.length});
+  }
+};
+
+self.addEventListener('message', event => {
+  const data = event.data || {};
+  if (data.type !== 'offline' || offlineLangs.indexOf(data.lang) === -1) {
+    return;
+  }
+  const port = event.ports[0];
+  const collections = data.collection ? [data.collection] : ['site', 'docs'];
+  event.waitUntil(
+    collections
+      .filter(c => (data.lang + '/' + c) in offlineRevisions)
+      .reduce((p, c) => p.then(() => cacheCollection(data.lang, c, data.auto, port)), Promise.resolve())
+      .catch(() => port && port.postMessage({type: 'error'}))
+  );
+});
+
+// "/de/angelus" is cached as "/de/angelus.html"
+const matchOffline = url => caches.match(url.pathname, {ignoreSearch: true}).then(r => {
+  if (r || /\.[a-z0-9]+$/i.test(url.pathname) || url.pathname.endsWith('/')) {
+    return r;
+  }
+  return caches.match(url.pathname + '.html', {ignoreSearch: true});
+});
+
+const offlineFirst = strategy => ({url, event}) =>
+  matchOffline(url).then(r => r || strategy.handle({event}));
+
+const isDocument = url => {
+  const parts = url.pathname.split('/');
+  return docsPaths[parts[1]] !== undefined && docsPaths[parts[1]] === parts[2];
+};
+
+const isLanguage = url => {
+  const parts = url.pathname.split('/');
+  const lang = parts[1] === 'articles' ? parts[2] : parts[1].replace(/\.html$/, '');
+  return offlineLangs.indexOf(lang) !== -1;
+};
+
+// documents that weren't downloaded: show the cached copy, refresh it in the background
+workbox.routing.registerRoute(
+  ({url}) => url.origin === location.origin && isDocument(url),
+  offlineFirst(workbox.strategies.staleWhileRevalidate({
+    cacheName: 'dubia-docs',
+    plugins: [new workbox.expiration.Plugin({maxEntries: 100})],
+  }))
+);
+
+// images, PDFs and e-books are large and rarely change
+workbox.routing.registerRoute(
+  ({url}) => url.origin === location.origin && /\.(avif|png|jpe?g|svg|pdf|epub)$/i.test(url.pathname),
+  offlineFirst(workbox.strategies.cacheFirst({
+    cacheName: 'dubia-assets',
+    plugins: [
+      new workbox.cacheableResponse.Plugin({statuses: [200]}),
+      new workbox.expiration.Plugin({maxEntries: 60, maxAgeSeconds: 30 * 24 * 60 * 60}),
+    ],
+  }))
+);
+
+// everything else of a language: cached pages first, then the network
+workbox.routing.registerRoute(
+  ({url}) => url.origin === location.origin && isLanguage(url),
+  offlineFirst(workbox.strategies.networkOnly())
+);